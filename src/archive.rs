@@ -0,0 +1,159 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::{Compression, Crc};
+
+// write packs the files into an archive at path, by its extension:
+// .zip, .tar.gz or .tgz, or .tar.
+// The files are named in it by their paths relative to dir,
+// and a directory, like that of a --profile, is packed with everything in it.
+pub fn write(path: &Path, dir: &Path, files: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+    let mut entries = Vec::new();
+    for file in files {
+        collect(dir, file, &mut entries)?;
+    }
+    let name = path.to_string_lossy().to_ascii_lowercase();
+    let f = File::create(path).map_err(|e| format!("failed to create {path:?}: {e}"))?;
+    let mut out = BufWriter::new(f);
+    if name.ends_with(".zip") {
+        zip(&mut out, &entries)?;
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        let mut gz = GzEncoder::new(&mut out, Compression::default());
+        tar(&mut gz, &entries)?;
+        gz.finish()?;
+    } else if name.ends_with(".tar") {
+        tar(&mut out, &entries)?;
+    } else {
+        return Err(
+            format!("--archive should end in .zip, .tar.gz, .tgz or .tar, got {path:?}").into(),
+        );
+    }
+    out.flush()
+        .map_err(|e| format!("failed to write {path:?}: {e}").into())
+}
+
+// Entry is a file to pack, by its name in the archive, like charging/battery-40.svg.
+struct Entry {
+    name: String,
+    bytes: Vec<u8>,
+}
+
+// collect reads the file, or the files under the directory, into entries.
+fn collect(dir: &Path, path: &Path, entries: &mut Vec<Entry>) -> Result<(), Box<dyn Error>> {
+    if path.is_dir() {
+        let mut children: Vec<PathBuf> = fs::read_dir(path)
+            .map_err(|e| format!("failed to read directory {path:?}: {e}"))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()?;
+        // Sorted, so that the same icons always make the same archive.
+        children.sort();
+        for child in children {
+            collect(dir, &child, entries)?;
+        }
+        return Ok(());
+    }
+    let relative = path
+        .strip_prefix(dir)
+        .map_err(|_| format!("{path:?} isn't in {dir:?}"))?;
+    let name: Vec<String> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    let bytes = fs::read(path).map_err(|e| format!("failed to read {path:?}: {e}"))?;
+    entries.push(Entry {
+        name: name.join("/"),
+        bytes,
+    });
+    Ok(())
+}
+
+// tar writes the entries as a ustar archive, read-write for the owner and readable by all.
+fn tar<W: Write>(out: &mut W, entries: &[Entry]) -> Result<(), Box<dyn Error>> {
+    for entry in entries {
+        if entry.name.len() > 100 {
+            return Err(format!("{:?} is too long a name for a tar archive", entry.name).into());
+        }
+        let mut header = [0u8; 512];
+        let mut field = |offset: usize, value: &[u8]| {
+            header[offset..offset + value.len()].copy_from_slice(value);
+        };
+        field(0, entry.name.as_bytes());
+        field(100, b"0000644\0");
+        field(108, b"0000000\0");
+        field(116, b"0000000\0");
+        field(124, format!("{:011o}\0", entry.bytes.len()).as_bytes());
+        field(136, b"00000000000\0");
+        field(156, b"0");
+        field(257, b"ustar\0");
+        field(263, b"00");
+        // The checksum is summed with its own field as spaces.
+        field(148, b"        ");
+        let sum: u32 = header.iter().map(|&b| b as u32).sum();
+        header[148..156].copy_from_slice(format!("{sum:06o}\0 ").as_bytes());
+        out.write_all(&header)?;
+        out.write_all(&entry.bytes)?;
+        let padding = (512 - entry.bytes.len() % 512) % 512;
+        out.write_all(&vec![0; padding])?;
+    }
+    // The archive ends with two empty blocks.
+    out.write_all(&[0; 1024])?;
+    Ok(())
+}
+
+// zip writes the entries as a zip archive, deflated,
+// all dated 1980-01-01, the earliest date it has, so that it comes out the same every time.
+fn zip<W: Write>(out: &mut W, entries: &[Entry]) -> Result<(), Box<dyn Error>> {
+    const UTF8_NAMES: u16 = 1 << 11;
+    const DEFLATED: u16 = 8;
+    const DOS_DATE: u16 = (1 << 5) | 1;
+    let size = |n: usize| u32::try_from(n).map_err(|_| "too large a file for a zip archive");
+    let mut central = Vec::new();
+    let mut offset = 0;
+    for entry in entries {
+        let mut crc = Crc::new();
+        crc.update(&entry.bytes);
+        let mut deflater = DeflateEncoder::new(Vec::new(), Compression::default());
+        deflater.write_all(&entry.bytes)?;
+        let deflated = deflater.finish()?;
+        let name = entry.name.as_bytes();
+        // The fields that the local header and the central directory share.
+        let mut common = Vec::new();
+        for half in [20, UTF8_NAMES, DEFLATED, 0, DOS_DATE] {
+            common.extend(half.to_le_bytes());
+        }
+        for word in [crc.sum(), size(deflated.len())?, size(entry.bytes.len())?] {
+            common.extend(word.to_le_bytes());
+        }
+        common.extend((name.len() as u16).to_le_bytes());
+        common.extend(0u16.to_le_bytes());
+
+        let mut local = 0x04034b50u32.to_le_bytes().to_vec();
+        local.extend(&common);
+        local.extend(name);
+        out.write_all(&local)?;
+        out.write_all(&deflated)?;
+
+        central.extend(0x02014b50u32.to_le_bytes());
+        central.extend(20u16.to_le_bytes());
+        central.extend(&common);
+        // No comment, on the first disk, with no attributes.
+        central.extend([0; 10]);
+        central.extend(size(offset)?.to_le_bytes());
+        central.extend(name);
+        offset += local.len() + deflated.len();
+    }
+    let count = u16::try_from(entries.len()).map_err(|_| "too many files for a zip archive")?;
+    let mut end = 0x06054b50u32.to_le_bytes().to_vec();
+    end.extend([0; 4]);
+    end.extend(count.to_le_bytes());
+    end.extend(count.to_le_bytes());
+    end.extend(size(central.len())?.to_le_bytes());
+    end.extend(size(offset)?.to_le_bytes());
+    end.extend(0u16.to_le_bytes());
+    out.write_all(&central)?;
+    out.write_all(&end)?;
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use std::error::Error;
+
+use clap::ValueEnum;
+use indexmap::IndexMap;
+
+use crate::format_number;
+
+/// Which way the bar fills up as the battery charges.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Orientation {
+    /// From the left, by scaling its width.
+    #[default]
+    Horizontal,
+    /// From the bottom, by scaling its height and moving its y down to match.
+    Vertical,
+}
+
+impl Orientation {
+    // of returns the orientation of a bar, from its data-orient attribute,
+    // or else the default, from --orientation.
+    pub fn of(
+        attr_map: &IndexMap<String, String>,
+        default: Orientation,
+    ) -> Result<Orientation, Box<dyn Error>> {
+        let Some(orient) = attr_map.get("data-orient") else {
+            return Ok(default);
+        };
+        Orientation::from_str(orient.trim(), true).map_err(|_| {
+            format!("data-orient should be horizontal or vertical, got {orient:?}").into()
+        })
+    }
+}
+
+/// The edge of the bar that stays in place as it shrinks,
+/// so that it empties toward it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Anchor {
+    /// The left edge, for a horizontal bar, as by default.
+    Left,
+    /// The right edge, for a horizontal bar.
+    Right,
+    /// The top edge, for a vertical bar.
+    Top,
+    /// The bottom edge, for a vertical bar, as by default.
+    Bottom,
+    /// The middle, shrinking from both ends.
+    Center,
+}
+
+impl Anchor {
+    // of returns the anchor of a bar, from its data-anchor attribute,
+    // or else the default, from --anchor,
+    // or else the left of a horizontal bar or the bottom of a vertical one.
+    pub fn of(
+        attr_map: &IndexMap<String, String>,
+        default: Option<Anchor>,
+        orientation: Orientation,
+    ) -> Result<Anchor, Box<dyn Error>> {
+        let anchor = match attr_map.get("data-anchor") {
+            Some(anchor) => Anchor::from_str(anchor.trim(), true).map_err(|_| {
+                format!("data-anchor should be left, right, top, bottom or center, got {anchor:?}")
+            })?,
+            None => default.unwrap_or(match orientation {
+                Orientation::Horizontal => Anchor::Left,
+                Orientation::Vertical => Anchor::Bottom,
+            }),
+        };
+        match (orientation, anchor) {
+            (Orientation::Horizontal, Anchor::Top | Anchor::Bottom)
+            | (Orientation::Vertical, Anchor::Left | Anchor::Right) => Err(format!(
+                "a {orientation:?} bar can't be anchored to its {anchor:?}",
+            )
+            .to_lowercase()
+            .into()),
+            _ => Ok(anchor),
+        }
+    }
+
+    // share is how much of what the bar loses is taken off of the side where x or y is,
+    // moving it over by as much.
+    fn share(self) -> f64 {
+        match self {
+            Anchor::Left | Anchor::Top => 0.0,
+            Anchor::Center => 0.5,
+            Anchor::Right | Anchor::Bottom => 1.0,
+        }
+    }
+}
+
+// scale shrinks the bar from full to the charge, along its orientation,
+// toward its anchor: its x or y moves over by what it loses on that side.
+// It is full at data-full-width, or the width it is drawn with,
+// and empty at data-empty-width, or 0, and at its data-full-x when full, or the x it is drawn at;
+// or data-full-height, data-empty-height and data-full-y, if it is vertical.
+// With the bounds given, a render of the output comes out the same as one of the template.
+// The path of the bar, like svg > rect#fraction, is for the errors.
+pub fn scale(
+    attr_map: &mut IndexMap<String, String>,
+    path: &str,
+    charge: f64,
+    orientation: Orientation,
+    anchor: Anchor,
+    precision: usize,
+) -> Result<(), Box<dyn Error>> {
+    let (size_attr, position_attr) = match orientation {
+        Orientation::Horizontal => ("width", "x"),
+        Orientation::Vertical => ("height", "y"),
+    };
+    let number = |attr: &str, default: Option<f64>| -> Result<f64, Box<dyn Error>> {
+        let Some(value) = attr_map.get(attr) else {
+            return default.ok_or_else(|| format!("{path} had no [{attr}]").into());
+        };
+        let value: f64 = value
+            .parse()
+            .map_err(|e| format!("failed to parse {path}[{attr}]: {e}"))?;
+        Ok(value)
+    };
+    let full_attr = match attr_map.contains_key(&format!("data-full-{size_attr}")) {
+        true => format!("data-full-{size_attr}"),
+        false => size_attr.to_string(),
+    };
+    let empty_attr = format!("data-empty-{size_attr}");
+    let full = number(&full_attr, None)?;
+    if !(full >= 0.0 && full.is_finite()) {
+        return Err(format!(
+            "{path} has a {full_attr} of {full}, which should be a number from 0 up"
+        )
+        .into());
+    }
+    let empty = number(&empty_attr, Some(0.0))?;
+    if !(0.0..=full).contains(&empty) {
+        return Err(format!(
+            "{path} has a {empty_attr} of {empty}, which should be from 0 up to its full {size_attr} of {full}"
+        )
+        .into());
+    }
+    let full_position_attr = format!("data-full-{position_attr}");
+    let position = match (attr_map.contains_key(&full_position_attr), anchor) {
+        (true, _) => Some(number(&full_position_attr, None)?),
+        (false, Anchor::Left | Anchor::Top) => None,
+        (false, _) => Some(number(position_attr, Some(0.0))?),
+    };
+    let scaled = empty + (full - empty) * charge;
+    attr_map.insert(size_attr.to_string(), format_number(scaled, precision));
+    if let Some(position) = position {
+        let moved = position + (full - scaled) * anchor.share();
+        attr_map.insert(position_attr.to_string(), format_number(moved, precision));
+    }
+    log::debug!("scaled the {size_attr} of {path} from {full} to {scaled}");
+    Ok(())
+}
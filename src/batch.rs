@@ -0,0 +1,222 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use clap::{Parser, ValueEnum};
+use serde_json::json;
+
+use battery_icon::source::BatteryState;
+
+use crate::{archive, render, Args};
+
+/// Settings for the batch subcommand.
+#[derive(clap::Args, Debug)]
+pub struct BatchArgs {
+    /// Path to the base SVG.
+    svg: PathBuf,
+
+    /// Directory to write the icons to.
+    out: PathBuf,
+
+    /// Percentage between the charges to render, from 0 up to 100%.
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..=100))]
+    step: u32,
+
+    /// The states to render each charge in.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "discharging,charging"
+    )]
+    states: Vec<BatteryState>,
+
+    /// The file name of each icon, with {charge} and {state} in place of its percentage and state.
+    /// The extension picks the format, like .png.
+    #[arg(long, default_value = "battery-{charge}-{state}.svg")]
+    name: String,
+
+    /// Also pack the icons into an archive, like icons.zip or icons.tar.gz,
+    /// named in it by their paths in the directory.
+    #[arg(long, value_name = "PATH")]
+    archive: Option<PathBuf>,
+
+    /// How to report the progress: a bar on a terminal and a summary,
+    /// or json, a line of JSON for each icon and one for the summary.
+    #[arg(long, value_enum, default_value_t = Progress::Text)]
+    progress: Progress,
+
+    /// More options for each render, after --, like -- --width 64.
+    #[arg(last = true, allow_hyphen_values = true)]
+    render_args: Vec<OsString>,
+}
+
+/// The formats of --progress.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Progress {
+    /// A bar on stderr, while it is a terminal, and a summary at the end.
+    Text,
+    /// One line of JSON on stdout per icon, and one for the summary.
+    Json,
+}
+
+// BAR_WIDTH is how many characters the progress bar fills.
+const BAR_WIDTH: usize = 30;
+
+// run renders the template at each step of the charge in each of the states,
+// into files named by the pattern, like battery-40-charging.svg,
+// to be linked to as the battery changes instead of rendering on the fly.
+pub fn run(args: &BatchArgs) -> Result<(), Box<dyn Error>> {
+    if !args.name.contains("{charge}") || (args.states.len() > 1 && !args.name.contains("{state}"))
+    {
+        return Err(format!(
+            "--name needs {{charge}}, and {{state}} for more than one state, to name each icon apart, got {:?}",
+            args.name
+        )
+        .into());
+    }
+    fs::create_dir_all(&args.out)
+        .map_err(|e| format!("failed to create directory {:?}: {e}", args.out))?;
+    // A step that doesn't divide 100 still ends with a full battery.
+    let mut charges: Vec<u32> = (0..=100).step_by(args.step as usize).collect();
+    if charges.last() != Some(&100) {
+        charges.push(100);
+    }
+    let icons: Vec<Icon> = charges
+        .iter()
+        .flat_map(|charge| {
+            args.states.iter().map(move |state| Icon {
+                name: args
+                    .name
+                    .replace("{charge}", &charge.to_string())
+                    .replace("{state}", state.name()),
+                reading: vec![
+                    format!("--charge={charge}").into(),
+                    format!("--state={}", state.name()).into(),
+                ],
+            })
+        })
+        .collect();
+    let outputs = render_all(
+        &args.svg,
+        &args.out,
+        icons,
+        &args.render_args,
+        args.progress,
+    )?;
+    if let Some(archive) = &args.archive {
+        archive::write(archive, &args.out, &outputs)
+            .map_err(|e| format!("failed to archive the icons: {e}"))?;
+        match args.progress {
+            Progress::Text => println!("packed them into {archive:?}"),
+            Progress::Json => println!(
+                "{}",
+                json!({"event": "archive", "path": archive, "bytes": size_of(archive)?})
+            ),
+        }
+    }
+    Ok(())
+}
+
+// Icon is one render of a batch: the name of its file,
+// and the options that give it its reading, like --charge=40.
+pub struct Icon {
+    pub name: String,
+    pub reading: Vec<OsString>,
+}
+
+// render_all renders the template once for each icon into the directory,
+// with the extra options of every render, like --width 64,
+// reporting its progress in the format given, and returns the paths it wrote.
+pub fn render_all(
+    svg: &Path,
+    out: &Path,
+    icons: Vec<Icon>,
+    render_args: &[OsString],
+    progress: Progress,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let started = Instant::now();
+    let total = icons.len();
+    let bar = progress == Progress::Text && io::stderr().is_terminal();
+    let mut outputs = Vec::new();
+    let mut bytes = 0;
+    for icon in icons {
+        let output = out.join(&icon.name);
+        let argv: Vec<OsString> = [
+            "battery-icon".into(),
+            svg.as_os_str().to_owned(),
+            output.clone().into(),
+        ]
+        .into_iter()
+        .chain(icon.reading)
+        .chain(render_args.iter().cloned())
+        .collect();
+        let rendered = Args::try_parse_from(argv)
+            .map_err(Box::from)
+            .and_then(|render_args| {
+                let config = render_args.config()?;
+                let data = render_args.data()?;
+                render(&render_args, &config, render_args.read_source()?, &data)
+            })
+            .map_err(|e| format!("failed to render {output:?}: {e}"));
+        if let Err(e) = rendered {
+            // End the bar's line, so that the error starts on one of its own.
+            if bar {
+                eprintln!();
+            }
+            return Err(e.into());
+        }
+        let size = size_of(&output)?;
+        bytes += size;
+        outputs.push(output);
+        let done = outputs.len();
+        match progress {
+            Progress::Text if bar => {
+                let filled = done * BAR_WIDTH / total;
+                eprint!(
+                    "\r[{}{}] {done}/{total} {}\x1b[K",
+                    "#".repeat(filled),
+                    ".".repeat(BAR_WIDTH - filled),
+                    icon.name
+                );
+            }
+            Progress::Text => {}
+            Progress::Json => println!(
+                "{}",
+                json!({"event": "icon", "name": icon.name, "done": done, "total": total, "bytes": size})
+            ),
+        }
+    }
+    if bar {
+        eprintln!();
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    match progress {
+        Progress::Text => println!(
+            "wrote {} icons, {bytes} bytes, to {out:?} in {elapsed:.2}s",
+            outputs.len()
+        ),
+        Progress::Json => println!(
+            "{}",
+            json!({"event": "done", "files": outputs.len(), "bytes": bytes, "elapsed": elapsed, "out": out})
+        ),
+    }
+    Ok(outputs)
+}
+
+// size_of returns the size of a file in bytes,
+// or of all the files under a directory, like that of a --profile.
+fn size_of(path: &Path) -> Result<u64, Box<dyn Error>> {
+    let metadata = fs::metadata(path).map_err(|e| format!("failed to read {path:?}: {e}"))?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut size = 0;
+    for entry in fs::read_dir(path).map_err(|e| format!("failed to read {path:?}: {e}"))? {
+        size += size_of(&entry?.path())?;
+    }
+    Ok(size)
+}
@@ -0,0 +1,762 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+#[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+use crate::bsd;
+#[cfg(target_os = "macos")]
+use crate::macos;
+use crate::source::{command_output, parse_level_line, BatteryState, Reading};
+#[cfg(windows)]
+use crate::windows;
+
+// A BatterySource reads the charge of a battery, and what it is doing.
+pub trait BatterySource {
+    // name says where the readings come from, for the logs.
+    fn name(&self) -> &'static str;
+    fn read(&self) -> Result<Reading, Box<dyn Error>>;
+}
+
+// registry returns the sources that --source auto tries, in order,
+// for the named battery, like BAT1, or else the first one each finds.
+// The native source of the platform goes first.
+pub fn registry(battery: Option<&str>) -> Vec<Box<dyn BatterySource>> {
+    let battery = battery.map(str::to_string);
+    vec![
+        #[cfg(target_os = "macos")]
+        Box::new(macos::Pmset {
+            battery: battery.clone(),
+        }),
+        #[cfg(windows)]
+        Box::new(windows::PowerStatusApi {
+            battery: battery.clone(),
+        }),
+        #[cfg(target_os = "freebsd")]
+        Box::new(bsd::Sysctl {
+            battery: battery.clone(),
+        }),
+        #[cfg(target_os = "openbsd")]
+        Box::new(bsd::Apm {
+            battery: battery.clone(),
+        }),
+        Box::new(Upower {
+            battery: battery.clone(),
+        }),
+        Box::new(Sysfs::new(battery)),
+    ]
+}
+
+// The source that answered last time, so that changes can be logged once.
+static WINNER: Mutex<Option<&str>> = Mutex::new(None);
+
+// read_auto returns the reading of the first source that has the battery.
+// If none does, it returns the fallback charge, when there is one,
+// or else takes the machine to be a desktop, running on AC power alone.
+pub fn read_auto(battery: Option<&str>, fallback: Option<f64>) -> Result<Reading, Box<dyn Error>> {
+    let mut errors = Vec::new();
+    for source in registry(battery) {
+        match source.read() {
+            Ok(reading) => {
+                log_winner(source.name());
+                return Ok(reading);
+            }
+            Err(e) => errors.push(format!("{}: {e}", source.name())),
+        }
+    }
+    let errors = errors.join("; ");
+    match fallback {
+        Some(charge) => {
+            log_winner("the fallback charge");
+            Ok((charge / 100.0).into())
+        }
+        None => match battery {
+            Some(battery) => Err(format!("no battery {battery:?} found ({errors})").into()),
+            None => {
+                log_winner("AC power, with no battery found");
+                Ok(ac_only())
+            }
+        },
+    }
+}
+
+// ac_only is the reading of a machine without a battery.
+// It is drawn full, for templates without an #ac layer.
+pub fn ac_only() -> Reading {
+    Reading {
+        level: 1.0,
+        plugged: true,
+        ac_only: true,
+        ..Default::default()
+    }
+}
+
+// log_winner reports which backend is in use,
+// only when it differs from the previous reading.
+fn log_winner(name: &'static str) {
+    let mut winner = WINNER.lock().unwrap_or_else(|e| e.into_inner());
+    if *winner != Some(name) {
+        log::info!("reading the battery from {name}");
+        *winner = Some(name);
+    }
+}
+
+// Fixed is a charge given on the command line, from 0 to 1.
+pub struct Fixed(pub f64);
+
+impl BatterySource for Fixed {
+    fn name(&self) -> &'static str {
+        "--level"
+    }
+
+    // The battery is taken to be discharging, unless --charging says otherwise.
+    fn read(&self) -> Result<Reading, Box<dyn Error>> {
+        Ok(Reading {
+            level: self.0,
+            state: BatteryState::Discharging,
+            ..Default::default()
+        })
+    }
+}
+
+// StatusFile is a file holding a level like 0.4 or 40%,
+// or a JSON status like {"percent": 40, "state": "charging", "time_remaining": 95},
+// which another program keeps up to date.
+pub struct StatusFile {
+    pub path: PathBuf,
+}
+
+impl BatterySource for StatusFile {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn read(&self) -> Result<Reading, Box<dyn Error>> {
+        let path = &self.path;
+        let text = fs::read_to_string(path).map_err(|e| format!("failed to read {path:?}: {e}"))?;
+        parse_status_text(&text).map_err(|e| format!("in {path:?}: {e}").into())
+    }
+}
+
+// parse_status_text reads a JSON status, or else a level line.
+fn parse_status_text(text: &str) -> Result<Reading, Box<dyn Error>> {
+    if text.trim_start().starts_with('{') {
+        parse_status(text)
+    } else {
+        parse_level_line(text)
+    }
+}
+
+// Http fetches a status like that of a StatusFile with curl,
+// for a desktop to show the battery of a laptop or phone it monitors.
+// Failed fetches are tried again, a second apart.
+pub struct Http {
+    pub url: String,
+    pub timeout: Duration,
+    pub retries: u32,
+}
+
+impl BatterySource for Http {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn read(&self) -> Result<Reading, Box<dyn Error>> {
+        let max_time = self.timeout.as_secs_f64().to_string();
+        let curl = [
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--max-time",
+            &max_time,
+            &self.url,
+        ];
+        let mut attempt = 0;
+        let text = loop {
+            match command_output("curl", &curl) {
+                Ok(text) => break text,
+                Err(e) if attempt >= self.retries => {
+                    return Err(format!("failed to fetch {}: {e}", self.url).into())
+                }
+                Err(_) => {
+                    attempt += 1;
+                    std::thread::sleep(Duration::from_secs(1));
+                }
+            }
+        };
+        parse_status_text(&text).map_err(|e| format!("from {}: {e}", self.url).into())
+    }
+}
+
+// ChargeStdin reads a line of stdin for each reading,
+// for other programs to pipe charges into battery-icon.
+pub struct ChargeStdin;
+
+impl BatterySource for ChargeStdin {
+    fn name(&self) -> &'static str {
+        "stdin"
+    }
+
+    fn read(&self) -> Result<Reading, Box<dyn Error>> {
+        let mut line = String::new();
+        let n = io::stdin()
+            .read_line(&mut line)
+            .map_err(|e| format!("failed to read stdin: {e}"))?;
+        if n == 0 {
+            return Err("stdin is closed".into());
+        }
+        parse_charge(&line).map_err(|e| format!("failed to parse stdin: {e}").into())
+    }
+}
+
+// Status is the JSON form of a reading, like
+//   {"percent": 40, "state": "charging", "time_remaining": 95}
+// Only the percent is required.
+// The state defaults to discharging, and the time remaining is in minutes.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Status {
+    percent: f64,
+    state: Option<String>,
+    time_remaining: Option<f64>,
+}
+
+// parse_status reads a JSON status, checking that its values make sense.
+pub fn parse_status(text: &str) -> Result<Reading, Box<dyn Error>> {
+    let status: Status = serde_json::from_str(text)?;
+    if !(0.0..=100.0).contains(&status.percent) {
+        return Err(format!("percent should be from 0 to 100, got {}", status.percent).into());
+    }
+    let state = match &status.state {
+        Some(name) => BatteryState::from_str(name, true).map_err(|_| {
+            let names: Vec<&str> = BatteryState::value_variants()
+                .iter()
+                .map(BatteryState::name)
+                .collect();
+            format!("state should be one of {}, got {name:?}", names.join(", "))
+        })?,
+        None => BatteryState::Discharging,
+    };
+    let time_remaining = match status.time_remaining {
+        Some(minutes) if minutes < 0.0 || !minutes.is_finite() => {
+            return Err(format!("time_remaining should be minutes from 0 up, got {minutes}").into())
+        }
+        minutes => minutes.map(|minutes| Duration::from_secs_f64(minutes * 60.0)),
+    };
+    Ok(Reading {
+        level: status.percent / 100.0,
+        state,
+        time_remaining,
+        ..Default::default()
+    })
+}
+
+// parse_charge reads a charge percentage, like 40 or 40%,
+// or a JSON status like {"percent": 40, "state": "charging"}.
+pub fn parse_charge(text: &str) -> Result<Reading, Box<dyn Error>> {
+    let text = text.trim();
+    if text.starts_with('{') {
+        return parse_status(text);
+    }
+    let percent = text.strip_suffix('%').unwrap_or(text).trim();
+    let percent: f64 = percent
+        .parse()
+        .map_err(|e| format!("invalid charge {text:?}: {e}"))?;
+    Fixed(percent / 100.0).read()
+}
+
+// Upower asks UPower for the charge of the battery, or of the first one it knows of.
+pub struct Upower {
+    pub battery: Option<String>,
+}
+
+impl BatterySource for Upower {
+    fn name(&self) -> &'static str {
+        "upower"
+    }
+
+    fn read(&self) -> Result<Reading, Box<dyn Error>> {
+        read_upower(self.battery.as_deref()).map(with_power_profile)
+    }
+}
+
+fn read_upower(battery: Option<&str>) -> Result<Reading, Box<dyn Error>> {
+    let devices = command_output("upower", &["--enumerate"])?;
+    // /org/freedesktop/UPower/devices/battery_BAT0
+    let device = devices
+        .lines()
+        .find(|line| match battery {
+            Some(battery) => line.ends_with(&format!("/battery_{battery}")),
+            None => line.contains("/battery_"),
+        })
+        .ok_or("no battery device")?;
+    let info = command_output("upower", &["--show-info", device])?;
+    let field = |name: &str| {
+        info.lines()
+            .find_map(|line| line.trim().strip_prefix(name))
+            .map(str::trim)
+    };
+    if field("present:") == Some("no") {
+        return Err(format!("{device} is not present").into());
+    }
+    // percentage:          85%
+    let percent = field("percentage:").ok_or_else(|| format!("no percentage for {device}"))?;
+    let percent: f64 = percent
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|e| format!("failed to parse percentage {percent:?}: {e}"))?;
+    // state:               charging
+    let state = match field("state:") {
+        Some("charging") => BatteryState::Charging,
+        Some("discharging" | "empty") => BatteryState::Discharging,
+        Some("fully-charged") => BatteryState::Full,
+        Some("pending-charge") => BatteryState::NotCharging,
+        _ => BatteryState::Unknown,
+    };
+    // time to empty:       3.2 hours
+    let time_remaining = field("time to empty:")
+        .or_else(|| field("time to full:"))
+        .and_then(parse_upower_time);
+    // energy-rate:         8.3 W
+    let power = field("energy-rate:")
+        .and_then(|rate| rate.strip_suffix('W'))
+        .and_then(|watts| watts.trim().parse().ok());
+    // capacity:            92.5%
+    let health = field("capacity:")
+        .and_then(|capacity| capacity.strip_suffix('%'))
+        .and_then(|percent| percent.parse::<f64>().ok())
+        .map(|percent| percent / 100.0);
+    // charge-threshold-enabled: yes
+    // charge-end-threshold:     80%
+    let charge_limit = field("charge-end-threshold:")
+        .filter(|_| field("charge-threshold-enabled:") == Some("yes"))
+        .and_then(|threshold| threshold.strip_suffix('%'))
+        .and_then(|percent| percent.parse::<f64>().ok())
+        .filter(|&percent| percent < 100.0)
+        .map(|percent| percent / 100.0);
+    // temperature:         31.5 degrees C
+    let temperature = field("temperature:")
+        .and_then(|temperature| temperature.strip_suffix(" degrees C"))
+        .and_then(|degrees| degrees.trim().parse().ok());
+    // charge-cycles:       123
+    // It is N/A, or -1, if unknown.
+    let cycles = field("charge-cycles:").and_then(|cycles| cycles.parse().ok());
+    Ok(Reading {
+        level: percent / 100.0,
+        state,
+        time_remaining,
+        power,
+        health,
+        charge_limit,
+        temperature,
+        cycles,
+        ..Default::default()
+    })
+}
+
+// parse_upower_time reads a time like 3.2 hours or 45.0 minutes.
+fn parse_upower_time(time: &str) -> Option<Duration> {
+    let (count, unit) = time.split_once(' ')?;
+    let count: f64 = count.parse().ok()?;
+    let seconds = match unit.trim() {
+        "seconds" => 1.0,
+        "minutes" => 60.0,
+        "hours" => 3600.0,
+        "days" => 86400.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(count * seconds).ok()
+}
+
+// Sysfs reads the capacity and status of the battery,
+// or of the first one in the power supply class.
+pub struct Sysfs {
+    // That is /sys/class/power_supply, but for tests.
+    pub class: PathBuf,
+    pub battery: Option<String>,
+}
+
+impl Sysfs {
+    pub fn new(battery: Option<String>) -> Sysfs {
+        Sysfs {
+            class: PathBuf::from("/sys/class/power_supply"),
+            battery,
+        }
+    }
+}
+
+impl BatterySource for Sysfs {
+    fn name(&self) -> &'static str {
+        "sysfs"
+    }
+
+    fn read(&self) -> Result<Reading, Box<dyn Error>> {
+        read_sysfs(&self.class, self.battery.as_deref()).map(with_power_profile)
+    }
+}
+
+// with_power_profile marks the reading as in power save mode
+// when power-profiles-daemon is set to power-saver,
+// since Linux doesn't report battery saver through the battery.
+fn with_power_profile(reading: Reading) -> Reading {
+    let power_saver = command_output("powerprofilesctl", &["get"])
+        .is_ok_and(|profile| profile.trim() == "power-saver");
+    Reading {
+        power_saver: reading.power_saver || power_saver,
+        ..reading
+    }
+}
+
+fn read_sysfs(class: &Path, battery: Option<&str>) -> Result<Reading, Box<dyn Error>> {
+    let dir = match battery {
+        Some(battery) => class.join(battery),
+        None => batteries(class)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("no battery in {class:?}"))?,
+    };
+    read_supply(&dir)
+}
+
+fn read_supply(dir: &Path) -> Result<Reading, Box<dyn Error>> {
+    let capacity = read_attribute(dir, "capacity")?;
+    let percent: f64 = capacity
+        .parse()
+        .map_err(|e| format!("failed to parse capacity {capacity:?} of {dir:?}: {e}"))?;
+    let state = match read_attribute(dir, "status").as_deref() {
+        Ok("Charging") => BatteryState::Charging,
+        Ok("Discharging") => BatteryState::Discharging,
+        Ok("Full") => BatteryState::Full,
+        Ok("Not charging") => BatteryState::NotCharging,
+        _ => BatteryState::Unknown,
+    };
+    Ok(Reading {
+        level: percent / 100.0,
+        state,
+        power: read_power(dir),
+        health: read_capacities(dir).map(|(full, design)| full / design),
+        charge_limit: read_attribute(dir, "charge_control_end_threshold")
+            .ok()
+            .and_then(|percent| percent.parse::<f64>().ok())
+            .filter(|&percent| percent < 100.0)
+            .map(|percent| percent / 100.0),
+        // In tenths of a degree.
+        temperature: read_attribute(dir, "temp")
+            .ok()
+            .and_then(|tenths| tenths.parse::<f64>().ok())
+            .map(|tenths| tenths / 10.0),
+        // Batteries that don't count their cycles report 0.
+        cycles: read_attribute(dir, "cycle_count")
+            .ok()
+            .and_then(|cycles| cycles.parse().ok())
+            .filter(|&cycles| cycles > 0),
+        ..Default::default()
+    })
+}
+
+// read_capacities reads how much a battery holds when full, and held when new,
+// in either energy or charge, whichever the battery reports.
+fn read_capacities(dir: &Path) -> Option<(f64, f64)> {
+    let read = |name: &str| read_attribute(dir, name).ok()?.parse::<f64>().ok();
+    ["energy", "charge"].iter().find_map(|unit| {
+        let full = read(&format!("{unit}_full"))?;
+        let design = read(&format!("{unit}_full_design"))?;
+        (design > 0.0).then_some((full, design))
+    })
+}
+
+// read_power reads the draw of a supply in watts, from power_now,
+// or from current_now and voltage_now for batteries that report charge.
+// They are in microwatts, microamps and microvolts,
+// and some batteries report them negative while discharging.
+fn read_power(dir: &Path) -> Option<f64> {
+    let micro = |name: &str| read_attribute(dir, name).ok()?.parse::<f64>().ok();
+    let watts = match micro("power_now") {
+        Some(microwatts) => microwatts / 1e6,
+        None => micro("current_now")? * micro("voltage_now")? / 1e12,
+    };
+    Some(watts.abs())
+}
+
+fn read_attribute(dir: &Path, name: &str) -> Result<String, Box<dyn Error>> {
+    let file = dir.join(name);
+    let value =
+        fs::read_to_string(&file).map_err(|e| format!("{file:?} should be readable: {e}"))?;
+    Ok(value.trim().to_string())
+}
+
+// batteries returns the supplies in the class whose type is Battery, in order,
+// since mains adapters and peripherals are listed alongside.
+fn batteries(class: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut supplies = fs::read_dir(class)
+        .map_err(|e| format!("{class:?} should be readable: {e}"))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("failed to list {class:?}: {e}"))?;
+    supplies.sort();
+    supplies.retain(|dir| read_attribute(dir, "type").is_ok_and(|kind| kind == "Battery"));
+    Ok(supplies)
+}
+
+// Combined reads all of the batteries in the power supply class as one,
+// weighing the charge of each by its design capacity,
+// for laptops with a second battery.
+pub struct Combined {
+    // That is /sys/class/power_supply, but for tests.
+    pub class: PathBuf,
+}
+
+impl Default for Combined {
+    fn default() -> Combined {
+        Combined {
+            class: PathBuf::from("/sys/class/power_supply"),
+        }
+    }
+}
+
+impl BatterySource for Combined {
+    fn name(&self) -> &'static str {
+        "sysfs"
+    }
+
+    fn read(&self) -> Result<Reading, Box<dyn Error>> {
+        let dirs = batteries(&self.class)?;
+        if dirs.is_empty() {
+            return Err(format!("no battery in {:?}", self.class).into());
+        }
+        let (mut charge, mut capacity) = (0.0, 0.0);
+        let mut states = Vec::new();
+        let mut power = None;
+        let (mut full, mut new) = (0.0, 0.0);
+        let mut charge_limit = None;
+        let mut temperature: Option<f64> = None;
+        for dir in &dirs {
+            let reading = read_supply(dir)?;
+            // Batteries report either energy or charge, and all of them the same one.
+            // Without either, they count the same.
+            let design = ["energy_full_design", "charge_full_design"]
+                .iter()
+                .find_map(|name| read_attribute(dir, name).ok()?.parse::<f64>().ok())
+                .unwrap_or(1.0);
+            charge += reading.level * design;
+            capacity += design;
+            states.push(reading.state);
+            if let Some(watts) = reading.power {
+                power = Some(power.unwrap_or(0.0) + watts);
+            }
+            charge_limit = charge_limit.or(reading.charge_limit);
+            // The hottest battery is the one to worry about.
+            if let Some(degrees) = reading.temperature {
+                temperature = Some(temperature.map_or(degrees, |t| t.max(degrees)));
+            }
+            if let Some((now, design)) = read_capacities(dir) {
+                full += now;
+                new += design;
+            }
+        }
+        Ok(with_power_profile(Reading {
+            level: charge / capacity,
+            state: combine_states(&states),
+            power,
+            health: (new > 0.0).then(|| full / new),
+            charge_limit,
+            temperature,
+            ..Default::default()
+        }))
+    }
+}
+
+// combine_states says what a set of batteries is doing as a whole:
+// charging or discharging if any of them is, and only full if all of them are.
+fn combine_states(states: &[BatteryState]) -> BatteryState {
+    let any = |state| states.contains(&state);
+    if any(BatteryState::Charging) {
+        BatteryState::Charging
+    } else if any(BatteryState::Discharging) {
+        BatteryState::Discharging
+    } else if states.iter().all(|&state| state == BatteryState::Full) {
+        BatteryState::Full
+    } else if any(BatteryState::NotCharging) || any(BatteryState::Full) {
+        BatteryState::NotCharging
+    } else {
+        BatteryState::Unknown
+    }
+}
+
+// names lists the batteries of the machine, like BAT0 and BAT1,
+// from /sys/class/power_supply, or else from UPower.
+pub fn names() -> Result<Vec<String>, Box<dyn Error>> {
+    let class = Path::new("/sys/class/power_supply");
+    let sysfs = batteries(class)
+        .map(|dirs| {
+            dirs.iter()
+                .filter_map(|dir| Some(dir.file_name()?.to_string_lossy().into_owned()))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if !sysfs.is_empty() {
+        return Ok(sysfs);
+    }
+    // /org/freedesktop/UPower/devices/battery_BAT0
+    let devices = command_output("upower", &["--enumerate"])
+        .map_err(|e| format!("no batteries in {class:?}, and {e}"))?;
+    let upower: Vec<String> = devices
+        .lines()
+        .filter_map(|line| Some(line.rsplit_once("/battery_")?.1.to_string()))
+        .collect();
+    if upower.is_empty() {
+        return Err("no batteries found".into());
+    }
+    Ok(upower)
+}
+
+// Batteries is a --batteries setting: all of them combined into one reading,
+// or each of all or some of them into an icon of its own.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Batteries {
+    Combined,
+    All,
+    Named(Vec<String>),
+}
+
+impl FromStr for Batteries {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Batteries, String> {
+        match s {
+            "combined" => Ok(Batteries::Combined),
+            "all" => Ok(Batteries::All),
+            _ => {
+                let names: Vec<String> = s
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                if names.is_empty() {
+                    return Err(format!(
+                        "expected all, combined or names like BAT0,BAT1, got {s:?}"
+                    ));
+                }
+                Ok(Batteries::Named(names))
+            }
+        }
+    }
+}
+
+// Smoother averages the level over the last few readings, for --smooth,
+// since sysfs readings can jitter between refreshes.
+pub struct Smoother {
+    window: usize,
+    levels: VecDeque<f64>,
+}
+
+impl Smoother {
+    // new keeps the last window readings, or only the latest one without --smooth.
+    pub fn new(window: Option<NonZeroUsize>) -> Smoother {
+        let window = window.map_or(1, NonZeroUsize::get);
+        Smoother {
+            window,
+            levels: VecDeque::with_capacity(window),
+        }
+    }
+
+    // smooth returns the reading with its level averaged with the ones before it.
+    pub fn smooth(&mut self, reading: Reading) -> Reading {
+        if self.levels.len() == self.window {
+            self.levels.pop_front();
+        }
+        self.levels.push_back(reading.level);
+        Reading {
+            level: self.levels.iter().sum::<f64>() / self.levels.len() as f64,
+            ..reading
+        }
+    }
+}
+
+// Mock makes up readings, for testing watch mode and the templates without a battery:
+// a percentage, a state, and a rate at which the percentage changes,
+// counted from the first reading. It stops at empty and at full.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mock {
+    pub percent: f64,
+    pub state: BatteryState,
+    // In percent per second.
+    pub rate: f64,
+}
+
+// When the first mock reading was taken, for the rate to count from.
+static MOCK_START: OnceLock<Instant> = OnceLock::new();
+
+impl BatterySource for Mock {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn read(&self) -> Result<Reading, Box<dyn Error>> {
+        let elapsed = MOCK_START.get_or_init(Instant::now).elapsed();
+        Ok(self.at(elapsed))
+    }
+}
+
+impl Mock {
+    // at returns the reading the given time after the first one.
+    pub fn at(&self, elapsed: Duration) -> Reading {
+        let percent = (self.percent + self.rate * elapsed.as_secs_f64()).clamp(0.0, 100.0);
+        let seconds = match self.rate {
+            rate if rate < 0.0 => percent / -rate,
+            rate if rate > 0.0 => (100.0 - percent) / rate,
+            _ => 0.0,
+        };
+        Reading {
+            level: percent / 100.0,
+            state: self.state,
+            time_remaining: Some(Duration::from_secs_f64(seconds)).filter(|time| !time.is_zero()),
+            ..Default::default()
+        }
+    }
+}
+
+impl FromStr for Mock {
+    type Err = String;
+
+    // from_str reads settings like percent=42,state=charging,rate=-1%/s,
+    // where each is optional, and the rate may also be per minute, like -1%/min.
+    fn from_str(s: &str) -> Result<Mock, String> {
+        let mut mock = Mock {
+            percent: 100.0,
+            state: BatteryState::Discharging,
+            rate: 0.0,
+        };
+        for setting in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("expected KEY=VALUE, got {setting:?}"))?;
+            let number = |value: &str| {
+                value
+                    .parse::<f64>()
+                    .map_err(|e| format!("invalid {key} {value:?}: {e}"))
+            };
+            match key {
+                "percent" => mock.percent = number(value.strip_suffix('%').unwrap_or(value))?,
+                "state" => mock.state = BatteryState::from_str(value, false)?,
+                "rate" => {
+                    mock.rate = match value.strip_suffix("%/min") {
+                        Some(per_minute) => number(per_minute)? / 60.0,
+                        None => number(value.strip_suffix("%/s").unwrap_or(value))?,
+                    }
+                }
+                _ => return Err(format!("expected percent, state or rate, got {key:?}")),
+            }
+        }
+        Ok(mock)
+    }
+}
@@ -0,0 +1,65 @@
+use std::error::Error;
+
+use crate::battery::BatterySource;
+use crate::source::{command_output, BatteryState, Reading};
+
+// Bluez reads the battery of a Bluetooth device, like headphones or a mouse,
+// from the Battery1 interface that BlueZ exports on D-Bus, through busctl.
+pub struct Bluez {
+    // Like AA:BB:CC:DD:EE:FF.
+    pub address: String,
+}
+
+impl BatterySource for Bluez {
+    fn name(&self) -> &'static str {
+        "bluez"
+    }
+
+    fn read(&self) -> Result<Reading, Box<dyn Error>> {
+        let tree = command_output("busctl", &["tree", "--list", "org.bluez"])?;
+        let path = device_path(&tree, &self.address)?;
+        let percentage = command_output(
+            "busctl",
+            &[
+                "get-property",
+                "org.bluez",
+                path,
+                "org.bluez.Battery1",
+                "Percentage",
+            ],
+        )
+        .map_err(|e| format!("no battery for {}: {e}", self.address))?;
+        parse_percentage(&percentage)
+    }
+}
+
+// device_path finds the object of the device with the address
+// among those that `busctl tree --list org.bluez` lists:
+//
+//	/org/bluez/hci0
+//	/org/bluez/hci0/dev_AA_BB_CC_DD_EE_FF
+pub fn device_path<'a>(tree: &'a str, address: &str) -> Result<&'a str, Box<dyn Error>> {
+    let device = format!("/dev_{}", address.to_uppercase().replace(':', "_"));
+    tree.lines()
+        .map(str::trim)
+        .find(|path| path.ends_with(&device))
+        .ok_or_else(|| format!("no bluetooth device {address} in BlueZ").into())
+}
+
+// parse_percentage reads the answer of busctl get-property, like y 85,
+// where y says that the value is a byte.
+pub fn parse_percentage(output: &str) -> Result<Reading, Box<dyn Error>> {
+    let percent = output
+        .trim()
+        .strip_prefix("y ")
+        .ok_or_else(|| format!("unexpected percentage {:?}", output.trim()))?;
+    let percent: f64 = percent
+        .parse()
+        .map_err(|e| format!("failed to parse percentage {percent:?}: {e}"))?;
+    // BlueZ doesn't say whether the device is charging.
+    Ok(Reading {
+        level: percent / 100.0,
+        state: BatteryState::Unknown,
+        ..Default::default()
+    })
+}
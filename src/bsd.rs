@@ -0,0 +1,129 @@
+use std::error::Error;
+use std::time::Duration;
+
+use crate::battery::BatterySource;
+use crate::source::{command_output, BatteryState, Reading};
+
+// Sysctl reads the ACPI battery sysctls of FreeBSD,
+// which combine the batteries, so none can be picked by name.
+pub struct Sysctl {
+    pub battery: Option<String>,
+}
+
+impl BatterySource for Sysctl {
+    fn name(&self) -> &'static str {
+        "sysctl"
+    }
+
+    fn read(&self) -> Result<Reading, Box<dyn Error>> {
+        if let Some(battery) = &self.battery {
+            return Err(format!("can't read battery {battery:?} alone").into());
+        }
+        parse_sysctl(&command_output(
+            "sysctl",
+            &["hw.acpi.battery", "hw.acpi.acline"],
+        )?)
+    }
+}
+
+// parse_sysctl reads lines like these:
+//
+//	hw.acpi.battery.life: 85
+//	hw.acpi.battery.time: 192
+//	hw.acpi.battery.state: 1
+//	hw.acpi.acline: 0
+//
+// The state has bits of 1 for discharging and 2 for charging,
+// and the time is in minutes, or -1 when unknown.
+pub fn parse_sysctl(output: &str) -> Result<Reading, Box<dyn Error>> {
+    let field = |name: &str| -> Option<i64> {
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| value.trim().parse().ok())
+    };
+    let life = field("hw.acpi.battery.life").ok_or("no battery in sysctl")?;
+    if life < 0 {
+        return Err("no battery in sysctl".into());
+    }
+    let plugged = field("hw.acpi.acline") == Some(1);
+    let state = match field("hw.acpi.battery.state").unwrap_or(0) {
+        state if state & 2 != 0 => BatteryState::Charging,
+        state if state & 1 != 0 => BatteryState::Discharging,
+        _ => plugged_state(plugged, life as f64),
+    };
+    Ok(Reading {
+        level: life as f64 / 100.0,
+        state,
+        plugged,
+        time_remaining: minutes(field("hw.acpi.battery.time")),
+        ..Default::default()
+    })
+}
+
+// Apm asks OpenBSD's apm, which also combines the batteries.
+pub struct Apm {
+    pub battery: Option<String>,
+}
+
+impl BatterySource for Apm {
+    fn name(&self) -> &'static str {
+        "apm"
+    }
+
+    fn read(&self) -> Result<Reading, Box<dyn Error>> {
+        if let Some(battery) = &self.battery {
+            return Err(format!("can't read battery {battery:?} alone").into());
+        }
+        let apm = |flag: &str| command_output("apm", &[flag]);
+        parse_apm(&apm("-l")?, &apm("-b")?, &apm("-a")?, &apm("-m")?)
+    }
+}
+
+// parse_apm reads the answers of apm -l, -b, -a and -m:
+// the percentage, the battery state (3 for charging, 4 for absent),
+// the AC state (1 for connected), and the minutes remaining, or unknown.
+pub fn parse_apm(
+    percent: &str,
+    state: &str,
+    ac: &str,
+    time: &str,
+) -> Result<Reading, Box<dyn Error>> {
+    let state = state.trim();
+    if state == "4" {
+        return Err("no battery in apm".into());
+    }
+    let percent = percent.trim();
+    let percent: f64 = percent
+        .parse()
+        .map_err(|e| format!("failed to parse percentage {percent:?}: {e}"))?;
+    let plugged = ac.trim() == "1";
+    let state = match state {
+        "3" => BatteryState::Charging,
+        _ => plugged_state(plugged, percent),
+    };
+    Ok(Reading {
+        level: percent / 100.0,
+        state,
+        plugged,
+        time_remaining: minutes(time.trim().parse().ok()),
+        ..Default::default()
+    })
+}
+
+// plugged_state is the state of a battery that isn't charging,
+// since the BSDs don't say whether that is because it is full.
+fn plugged_state(plugged: bool, percent: f64) -> BatteryState {
+    match (plugged, percent >= 100.0) {
+        (false, _) => BatteryState::Discharging,
+        (true, true) => BatteryState::Full,
+        (true, false) => BatteryState::NotCharging,
+    }
+}
+
+// minutes turns a count of minutes into a duration.
+// Unknown times are negative.
+fn minutes(minutes: Option<i64>) -> Option<Duration> {
+    let minutes = u64::try_from(minutes?).ok()?;
+    Some(Duration::from_secs(minutes * 60))
+}
@@ -0,0 +1,299 @@
+use std::error::Error;
+use std::io::Read;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+
+use battery_icon::data;
+use battery_icon::files;
+use battery_icon::source::Reading;
+use battery_icon::{config, tag};
+
+use crate::exit::{Code, OrExit};
+use crate::{render_to, Args};
+
+// Lines of context around each change, as in diff -u.
+const CONTEXT: usize = 3;
+
+// run renders the template without writing the output,
+// and prints a unified diff of the elements and attributes that the render changes.
+// It returns whether there were any.
+pub fn run(
+    args: &Args,
+    config: &config::Config,
+    reading: Reading,
+    data: &data::Data,
+) -> Result<bool, Box<dyn Error>> {
+    let rendered = render_to(args, config, reading, data, Vec::new())?;
+    let mut template = Vec::new();
+    files::open_input(&args.svg)
+        .or_exit(Code::Template)?
+        .read_to_end(&mut template)
+        .map_err(|e| format!("failed to read SVG file {:?}: {e}", args.svg))
+        .or_exit(Code::Template)?;
+    let template = outline(&template).or_exit(Code::Template)?;
+    let diff = unified_diff(&template, &outline(&rendered)?);
+    if diff.is_empty() {
+        return Ok(false);
+    }
+    // Without an output path, the render is named after the template.
+    let output = match args.output == std::path::Path::new(files::STDIO) {
+        true => format!("{} (rendered)", args.svg.display()),
+        false => args.output.display().to_string(),
+    };
+    print!("--- {}\n+++ {output}\n{diff}", args.svg.display());
+    Ok(true)
+}
+
+// outline lists the elements of an SVG by their path,
+// each followed by its attributes and text, indented, one per line,
+// so that a diff of two outlines shows which attributes changed on which element:
+//
+//	svg#svg1 > rect#fraction
+//	    width="38.25"
+fn outline(svg: &[u8]) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut reader = Reader::from_reader(svg);
+    let mut tag_stack = tag::Stack::default();
+    let mut lines = Vec::new();
+    let mut buf = Vec::new();
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|e| format!("error at position {}: {e}", reader.error_position()))?;
+        match event {
+            Event::Eof => break,
+            Event::Start(e) => {
+                tag_stack.push(tag::Tag::new(&e)?);
+                outline_tag(&mut lines, &tag_stack, &e);
+            }
+            Event::Empty(e) => {
+                tag_stack.push(tag::Tag::new(&e)?);
+                outline_tag(&mut lines, &tag_stack, &e);
+                tag_stack.pop();
+            }
+            Event::End(_) => {
+                tag_stack.pop().ok_or("unexpected end tag")?;
+            }
+            Event::Text(e) => {
+                let text = String::from_utf8_lossy(&e);
+                if !text.trim().is_empty() {
+                    lines.push(format!("    {:?}", text.trim()));
+                }
+            }
+            Event::CData(e) => {
+                lines.push(format!("    {:?}", String::from_utf8_lossy(&e).trim()));
+            }
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(lines)
+}
+
+fn outline_tag(lines: &mut Vec<String>, tag_stack: &[tag::Tag], e: &BytesStart) {
+    lines.push(tag::path(tag_stack));
+    for attr in e.attributes().flatten() {
+        lines.push(format!(
+            "    {}=\"{}\"",
+            String::from_utf8_lossy(attr.key.as_ref()),
+            attr.unescape_value()
+                .unwrap_or_else(|_| String::from_utf8_lossy(&attr.value))
+        ));
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Same,
+    Removed,
+    Added,
+}
+
+// unified_diff compares the lines like diff -u, without the file headers,
+// and returns an empty string if they are the same.
+fn unified_diff(old: &[String], new: &[String]) -> String {
+    let ops = diff_ops(old, new);
+    let mut out = String::new();
+    let mut i = 0;
+    while i < ops.len() {
+        if ops[i].0 == Op::Same {
+            i += 1;
+            continue;
+        }
+        // A hunk runs from the context before this change
+        // to the context after the last change within twice the context of another.
+        let start = i.saturating_sub(CONTEXT);
+        let mut end = i;
+        let mut same_run = 0;
+        for (j, (op, _)) in ops.iter().enumerate().skip(i) {
+            if *op == Op::Same {
+                same_run += 1;
+                if same_run > 2 * CONTEXT {
+                    break;
+                }
+            } else {
+                same_run = 0;
+                end = j + 1;
+            }
+        }
+        let end = (end + CONTEXT).min(ops.len());
+
+        let (old_start, new_start) = position(&ops[..start]);
+        let (old_len, new_len) = position(&ops[start..end]);
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(old_start, old_len),
+            range(new_start, new_len)
+        ));
+        for (op, line) in &ops[start..end] {
+            let sign = match op {
+                Op::Same => ' ',
+                Op::Removed => '-',
+                Op::Added => '+',
+            };
+            out.push(sign);
+            out.push_str(line);
+            out.push('\n');
+        }
+        i = end;
+    }
+    out
+}
+
+// position counts the old and new lines of the ops.
+fn position(ops: &[(Op, &str)]) -> (usize, usize) {
+    let old = ops.iter().filter(|(op, _)| *op != Op::Added).count();
+    let new = ops.iter().filter(|(op, _)| *op != Op::Removed).count();
+    (old, new)
+}
+
+// range writes the lines of a hunk like diff -u: the first line, from 1,
+// and the count, unless it is 1.
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{len}", start + 1),
+    }
+}
+
+// diff_ops lines up the two lists by their longest common subsequence,
+// after setting aside the lines they start and end with in common.
+fn diff_ops<'a>(old: &'a [String], new: &'a [String]) -> Vec<(Op, &'a str)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    // lcs[i][j] is the length of the longest common subsequence of a[i..] and b[j..].
+    let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = match a[i] == b[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+
+    let mut ops: Vec<(Op, &str)> = old[..prefix]
+        .iter()
+        .map(|l| (Op::Same, l.as_str()))
+        .collect();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            ops.push((Op::Same, &a[i]));
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push((Op::Removed, &a[i]));
+            i += 1;
+        } else {
+            ops.push((Op::Added, &b[j]));
+            j += 1;
+        }
+    }
+    ops.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|l| (Op::Same, l.as_str())),
+    );
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(s: &str) -> Vec<String> {
+        s.chars().map(String::from).collect()
+    }
+
+    #[test]
+    fn no_change() {
+        assert_eq!(unified_diff(&lines("abc"), &lines("abc")), "");
+        assert_eq!(unified_diff(&[], &[]), "");
+    }
+
+    #[test]
+    fn empty_ranges() {
+        assert_eq!(unified_diff(&[], &lines("a")), "@@ -0,0 +1 @@\n+a\n");
+        assert_eq!(unified_diff(&lines("a"), &[]), "@@ -1 +0,0 @@\n-a\n");
+        assert_eq!(
+            unified_diff(&lines("ab"), &lines("axb")),
+            "@@ -1,2 +1,3 @@\n a\n+x\n b\n"
+        );
+    }
+
+    #[test]
+    fn single_line() {
+        assert_eq!(
+            unified_diff(&lines("a"), &lines("b")),
+            "@@ -1 +1 @@\n-a\n+b\n"
+        );
+        assert_eq!(
+            unified_diff(&lines("abcdefghi"), &lines("abcdEfghi")),
+            "@@ -2,7 +2,7 @@\n b\n c\n d\n-e\n+E\n f\n g\n h\n"
+        );
+    }
+
+    #[test]
+    fn hunks() {
+        // Changes up to twice the context apart share a hunk.
+        assert_eq!(
+            unified_diff(&lines("abcdefghij"), &lines("aBcdefgHij")),
+            "@@ -1,10 +1,10 @@\n a\n-b\n+B\n c\n d\n e\n f\n g\n-h\n+H\n i\n j\n"
+        );
+        // Changes further apart get hunks of their own.
+        assert_eq!(
+            unified_diff(&lines("abcdefghijklmn"), &lines("aBcdefghijKlmn")),
+            "@@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+             @@ -8,7 +8,7 @@\n h\n i\n j\n-k\n+K\n l\n m\n n\n"
+        );
+    }
+
+    #[test]
+    fn outlines() -> Result<(), Box<dyn Error>> {
+        let svg = br#"<svg id="s"><rect width="4"/><text> 50% </text></svg>"#;
+        assert_eq!(
+            outline(svg)?,
+            [
+                "svg#s",
+                "    id=\"s\"",
+                "svg#s > rect",
+                "    width=\"4\"",
+                "svg#s > text",
+                "    \"50%\"",
+            ]
+        );
+        Ok(())
+    }
+}
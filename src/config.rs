@@ -0,0 +1,55 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::rule::Rule;
+
+/// Settings read from a TOML file with --config.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Values from the --data file to write into the template.
+    #[serde(default)]
+    pub bind: Vec<Bind>,
+    /// Actions to apply to the elements of the template.
+    #[serde(default)]
+    pub rule: Vec<Rule>,
+    /// Values for the command line options, by their long names,
+    /// like source = "auto" or interval = 10, which the flags override.
+    #[serde(default)]
+    pub defaults: toml::Table,
+    /// More defaults by the name of a consumer of the icon, like tray or lockscreen,
+    /// which override the [defaults] when given with --profile.
+    #[serde(default)]
+    pub profile: BTreeMap<String, toml::Table>,
+}
+
+/// Binds a --data value to an element of the template.
+/// Without an attr, the value replaces the text content of the element.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bind {
+    pub selector: String,
+    pub key: String,
+    pub attr: Option<String>,
+}
+
+// default_path is config.toml in the battery-icon directory
+// of $XDG_CONFIG_HOME, or else of ~/.config.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("battery-icon").join("config.toml"))
+}
+
+pub fn load(path: &Path) -> Result<Config, Box<dyn Error>> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("config {path:?} should be readable: {e}"))?;
+    let config = toml::from_str(&text).map_err(|e| format!("in config {path:?}: {e}"))?;
+    Ok(config)
+}
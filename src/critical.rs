@@ -0,0 +1,176 @@
+use std::error::Error;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+
+/// Settings for acting on a critically low battery in --watch mode.
+#[derive(clap::Args, Clone, Debug)]
+pub struct CriticalArgs {
+    /// What to do when the charge stays below --critical-charge
+    /// while discharging, for --critical-grace seconds. Needs --watch, or the watch subcommand.
+    #[arg(long, value_enum)]
+    pub on_critical_action: Option<CriticalAction>,
+
+    /// Shell command to run for --on-critical-action command.
+    #[arg(long, required_if_eq("on_critical_action", "command"))]
+    pub critical_command: Option<String>,
+
+    /// Charge percentage below which the battery is critical,
+    /// and drawn with #critical shown and the bar in --color-critical.
+    #[arg(long, default_value_t = 5.0)]
+    pub critical_charge: f64,
+
+    /// Hide the bar entirely while the battery is critical,
+    /// leaving #critical to show it.
+    #[arg(long, default_value_t = false)]
+    pub critical_hide_bar: bool,
+
+    /// Seconds to wait after the warning before acting,
+    /// in case the charger gets plugged in.
+    #[arg(long, default_value_t = 60.0)]
+    pub critical_grace: f64,
+
+    /// Log the critical action instead of taking it.
+    #[arg(long, default_value_t = false)]
+    pub critical_dry_run: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CriticalAction {
+    Suspend,
+    Hibernate,
+    Shutdown,
+    /// Run --critical-command.
+    Command,
+}
+
+// Guard tracks a critical battery across the readings of watch mode,
+// so that it warns once, waits out the grace period, and acts once.
+#[derive(Default)]
+pub struct Guard {
+    // When the warning went out, if the battery is critical.
+    since: Option<Instant>,
+    // Whether the action was taken for this spell of critical battery.
+    acted: bool,
+}
+
+impl Guard {
+    // check looks at a new reading, warning or acting as needed.
+    // Charging or recovering above the threshold resets the guard.
+    pub fn check(
+        &mut self,
+        args: &CriticalArgs,
+        level: f64,
+        charging: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(action) = args.on_critical_action else {
+            return Ok(());
+        };
+        if !is_critical(args, level, charging) {
+            if self.since.take().is_some() && !self.acted {
+                notify(
+                    "Battery recovered",
+                    "Cancelled the critical battery action.",
+                );
+            }
+            self.acted = false;
+            return Ok(());
+        }
+        if self.acted {
+            return Ok(());
+        }
+        let grace = Duration::try_from_secs_f64(args.critical_grace)
+            .map_err(|e| format!("invalid --critical-grace {}: {e}", args.critical_grace))?;
+        let Some(since) = self.since else {
+            self.since = Some(Instant::now());
+            notify(
+                "Battery critical",
+                &format!(
+                    "{:.0}% left. Plug in within {grace:?}, or the system will {}.",
+                    level * 100.0,
+                    action.verb()
+                ),
+            );
+            return Ok(());
+        };
+        if since.elapsed() < grace {
+            return Ok(());
+        }
+        self.acted = true;
+        if args.critical_dry_run {
+            log::warn!("battery critical, would {}", action.verb());
+            return Ok(());
+        }
+        log::warn!("battery critical, going to {}", action.verb());
+        action.run(args.critical_command.as_deref())
+    }
+}
+
+// is_critical says whether the battery is below --critical-charge,
+// and not charging to get out of it.
+pub fn is_critical(args: &CriticalArgs, level: f64, charging: bool) -> bool {
+    !charging && level * 100.0 < args.critical_charge
+}
+
+impl CriticalAction {
+    fn verb(&self) -> &'static str {
+        match self {
+            CriticalAction::Suspend => "suspend",
+            CriticalAction::Hibernate => "hibernate",
+            CriticalAction::Shutdown => "shut down",
+            CriticalAction::Command => "run --critical-command",
+        }
+    }
+
+    fn run(&self, command: Option<&str>) -> Result<(), Box<dyn Error>> {
+        let (program, args): (&str, Vec<&str>) = match self {
+            CriticalAction::Command => {
+                let command = command.ok_or("--critical-command is required")?;
+                if cfg!(windows) {
+                    ("cmd", vec!["/C", command])
+                } else {
+                    ("sh", vec!["-c", command])
+                }
+            }
+            _ if cfg!(target_os = "macos") => match self {
+                CriticalAction::Shutdown => ("shutdown", vec!["-h", "now"]),
+                // macOS hibernates by itself when sleeping long enough on battery.
+                _ => ("pmset", vec!["sleepnow"]),
+            },
+            _ if cfg!(windows) => match self {
+                CriticalAction::Suspend => (
+                    "rundll32.exe",
+                    vec!["powrprof.dll,SetSuspendState", "0,1,0"],
+                ),
+                CriticalAction::Hibernate => ("shutdown", vec!["/h"]),
+                _ => ("shutdown", vec!["/s", "/t", "0"]),
+            },
+            CriticalAction::Suspend => ("systemctl", vec!["suspend"]),
+            CriticalAction::Hibernate => ("systemctl", vec!["hibernate"]),
+            CriticalAction::Shutdown => ("systemctl", vec!["poweroff"]),
+        };
+        let status = Command::new(program)
+            .args(&args)
+            .status()
+            .map_err(|e| format!("failed to run {program}: {e}"))?;
+        if !status.success() {
+            return Err(format!("{program} failed with {status}").into());
+        }
+        Ok(())
+    }
+}
+
+// notify shows a desktop notification, if notify-send is around.
+// The action goes ahead either way.
+fn notify(summary: &str, body: &str) {
+    log::warn!("{summary}: {body}");
+    let _ = Command::new("notify-send")
+        .args([
+            "--urgency=critical",
+            "--app-name=battery-icon",
+            summary,
+            body,
+        ])
+        .status();
+}
@@ -0,0 +1,131 @@
+use std::error::Error;
+
+use crate::matcher::{self, AndMatcher, StackMatcher};
+use crate::tag;
+
+// Stylesheet holds the rules of the template's <style> blocks.
+// Only the selectors that the matcher understands are kept;
+// others, like those with pseudo-elements or child combinators, are skipped.
+#[derive(Default)]
+pub struct Stylesheet {
+    rules: Vec<StyleRule>,
+}
+
+pub struct StyleRule {
+    pub selector: String,
+    matcher: AndMatcher,
+    // The declarations, with their values trimmed.
+    pub declarations: Vec<(String, String)>,
+}
+
+// Declaration is the value a stylesheet gives a property of an element.
+pub struct Declaration<'a> {
+    pub selector: &'a str,
+    pub value: &'a str,
+    pub important: bool,
+}
+
+impl Stylesheet {
+    // parse adds the rules of a <style> block.
+    // At-rules like @media are skipped, along with their contents.
+    pub fn parse(&mut self, css: &str) -> Result<(), Box<dyn Error>> {
+        let css = strip_comments(css);
+        let mut rest = css.as_str();
+        while let Some(open) = rest.find('{') {
+            let prelude = rest[..open].trim();
+            let close = block_end(&rest[open..])
+                .map(|i| open + i)
+                .ok_or_else(|| format!("unclosed {{ after {prelude:?}"))?;
+            let body = &rest[open + 1..close];
+            rest = &rest[close + 1..];
+            if prelude.starts_with('@') {
+                continue;
+            }
+            let declarations: Vec<(String, String)> = body
+                .split(';')
+                .filter_map(|decl| decl.split_once(':'))
+                .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+                .collect();
+            for selector in prelude.split(',').map(str::trim) {
+                let simple = !selector.contains(['>', '+', '~', '*']) && !selector.contains("::");
+                let Some(matcher) = simple
+                    .then(|| matcher::new_tag_matcher(selector).ok())
+                    .flatten()
+                else {
+                    continue;
+                };
+                self.rules.push(StyleRule {
+                    selector: selector.to_string(),
+                    matcher,
+                    declarations: declarations.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // matching returns the rules that apply to the current element.
+    pub fn matching<'a>(
+        &'a self,
+        tag_stack: &'a [tag::Tag],
+    ) -> impl Iterator<Item = &'a StyleRule> {
+        self.rules
+            .iter()
+            .filter(move |rule| rule.matcher.matches(tag_stack))
+    }
+
+    // get returns the stylesheet's value for a property of the current element.
+    // An !important declaration wins, and otherwise the last one does;
+    // specificity is not taken into account.
+    pub fn get<'a>(&'a self, tag_stack: &'a [tag::Tag], property: &str) -> Option<Declaration<'a>> {
+        let mut result: Option<Declaration> = None;
+        for rule in self.matching(tag_stack) {
+            for (key, value) in &rule.declarations {
+                if key != property {
+                    continue;
+                }
+                let (value, important) = match value.strip_suffix("!important") {
+                    Some(value) => (value.trim_end(), true),
+                    None => (value.as_str(), false),
+                };
+                if result.as_ref().is_some_and(|d| d.important && !important) {
+                    continue;
+                }
+                result = Some(Declaration {
+                    selector: &rule.selector,
+                    value,
+                    important,
+                });
+            }
+        }
+        result
+    }
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut result = String::new();
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        result.push_str(&rest[..start]);
+        rest = rest[start + 2..]
+            .find("*/")
+            .map_or("", |end| &rest[start + end + 4..]);
+    }
+    result.push_str(rest);
+    result
+}
+
+// block_end finds the } closing the { at the start of the text,
+// skipping over nested blocks.
+fn block_end(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 1 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
@@ -0,0 +1,65 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::config;
+use crate::matcher;
+use crate::rule::{self, Edit};
+
+/// Key/value pairs read from a JSON file with --data.
+pub type Data = serde_json::Map<String, Value>;
+
+pub fn load(path: &Path) -> Result<Data, Box<dyn Error>> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("data {path:?} should be readable: {e}"))?;
+    let value: Value = serde_json::from_str(&text).map_err(|e| format!("in data {path:?}: {e}"))?;
+    let Value::Object(data) = value else {
+        return Err(format!("data {path:?} should contain a JSON object").into());
+    };
+    Ok(data)
+}
+
+// lookup finds a key in the data, and formats its value for the template.
+// Keys with dots look inside nested objects, like "battery.temperature".
+pub fn lookup(data: &Data, key: &str) -> Result<String, Box<dyn Error>> {
+    let mut parts = key.split('.');
+    let first = parts.next().unwrap_or(key);
+    let mut value = data.get(first);
+    for part in parts {
+        value = value.and_then(|v| v.get(part));
+    }
+    match value {
+        None => Err(format!("data has no {key:?}").into()),
+        Some(Value::String(s)) => Ok(s.clone()),
+        Some(Value::Null) => Ok(String::new()),
+        Some(Value::Bool(b)) => Ok(b.to_string()),
+        Some(Value::Number(n)) => Ok(n.to_string()),
+        Some(_) => Err(format!("data {key:?} should be a string, number or bool").into()),
+    }
+}
+
+// bind turns the config bindings into edits,
+// with their values looked up in the data.
+pub fn bind(binds: &[config::Bind], data: &Data) -> Result<Vec<rule::Compiled>, Box<dyn Error>> {
+    let mut result = Vec::new();
+    for b in binds {
+        let matcher = matcher::new_tag_matcher(&b.selector)
+            .map_err(|e| format!("in bind selector {:?}: {e}", b.selector))?;
+        let value = lookup(data, &b.key).map_err(|e| format!("in bind {:?}: {e}", b.selector))?;
+        let edit = match &b.attr {
+            Some(attr) => Edit::SetAttr {
+                attr: attr.clone(),
+                value,
+            },
+            None => Edit::SetText(value),
+        };
+        result.push(rule::Compiled {
+            selector: b.selector.clone(),
+            matcher,
+            edit: Some(edit),
+        });
+    }
+    Ok(result)
+}
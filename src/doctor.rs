@@ -0,0 +1,115 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+
+use battery_icon::battery;
+
+use crate::{render_to, Args};
+
+/// Settings for the doctor subcommand.
+#[derive(clap::Args, Debug)]
+pub struct DoctorArgs {
+    /// Path to the base SVG to check.
+    svg: Option<PathBuf>,
+    /// Path to the result image, whose directory should be writable.
+    output: Option<PathBuf>,
+
+    /// Path to a TOML config file to check.
+    #[arg(long)]
+    config: Option<PathBuf>,
+}
+
+// run checks everything an icon needs in order to update,
+// prints a line for each check, and fails if any of them did.
+pub fn run(args: &DoctorArgs) -> Result<(), Box<dyn Error>> {
+    let mut failures = 0;
+    let mut report = |name: &str, result: Result<String, Box<dyn Error>>| match result {
+        Ok(detail) => println!("ok    {name}: {detail}"),
+        Err(e) => {
+            println!("FAIL  {name}: {e}");
+            failures += 1;
+        }
+    };
+
+    // Only one source needs to work, so the others are just listed.
+    let mut winner = None;
+    for source in battery::registry(None) {
+        let name = source.name();
+        match source.read() {
+            Ok(reading) => {
+                println!("ok    {name}: {:.0}%", reading.level * 100.0);
+                winner = winner.or(Some(name));
+            }
+            Err(e) => println!("-     {name}: {e}"),
+        }
+    }
+    report(
+        "battery",
+        winner
+            .map(|name| format!("--source auto reads from {name}"))
+            .ok_or_else(|| {
+                "no source found a battery, so --source auto draws AC power; try --fallback-charge"
+                    .into()
+            }),
+    );
+    report("d-bus", check_dbus());
+    if let Some(svg) = &args.svg {
+        report("template", check_template(svg, args.config.as_deref()));
+    }
+    if let Some(output) = &args.output {
+        report("output", check_output(output));
+    }
+
+    if failures > 0 {
+        return Err(format!("{failures} checks failed").into());
+    }
+    Ok(())
+}
+
+// check_dbus looks for the system bus that UPower is reached through.
+fn check_dbus() -> Result<String, Box<dyn Error>> {
+    let address = std::env::var("DBUS_SYSTEM_BUS_ADDRESS")
+        .unwrap_or_else(|_| "unix:path=/run/dbus/system_bus_socket".to_string());
+    let Some(socket) = address.strip_prefix("unix:path=") else {
+        return Ok(format!("using {address}"));
+    };
+    if !Path::new(socket).exists() {
+        return Err(format!("no system bus at {socket}").into());
+    }
+    Ok(format!("system bus at {socket}"))
+}
+
+// check_template renders the template at full charge, discarding the result,
+// to catch XML, placeholder and rule errors.
+fn check_template(svg: &Path, config: Option<&Path>) -> Result<String, Box<dyn Error>> {
+    let mut argv = vec![
+        "battery-icon".into(),
+        svg.as_os_str().to_owned(),
+        "-".into(),
+    ];
+    if let Some(config) = config {
+        argv.push("--config".into());
+        argv.push(config.as_os_str().to_owned());
+    }
+    let args = Args::try_parse_from(argv)?;
+    let config = args.config()?;
+    let data = args.data()?;
+    render_to(&args, &config, args.read_source()?, &data, io::sink())?;
+    Ok(format!("{svg:?} renders"))
+}
+
+// check_output writes and removes a file next to the output,
+// to make sure the icon can be updated.
+fn check_output(output: &Path) -> Result<String, Box<dyn Error>> {
+    let dir = match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let probe = dir.join(format!(".battery-icon-doctor-{}", std::process::id()));
+    fs::write(&probe, "").map_err(|e| format!("{dir:?} should be writable: {e}"))?;
+    fs::remove_file(&probe).map_err(|e| format!("failed to remove {probe:?}: {e}"))?;
+    Ok(format!("{dir:?} is writable"))
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use quick_xml::events::{BytesEnd, BytesStart, Event};
+
+use crate::matcher::{AndMatcher, StackMatcher};
+use crate::tag;
+
+// How deep <use> tags may reference other <use> tags,
+// so that a cycle is reported rather than followed forever.
+const MAX_USE_DEPTH: usize = 16;
+
+// Node is an element of the document, or any other event, like text.
+#[derive(Clone, Debug)]
+pub enum Node {
+    Element(Element),
+    Other(Event<'static>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Element {
+    pub start: BytesStart<'static>,
+    pub children: Vec<Node>,
+    // Whether the element was self-closing, to write it back the same way.
+    pub empty: bool,
+}
+
+// Document is a lightweight DOM of a whole template,
+// for the transforms that can't be done while streaming.
+#[derive(Debug, Default)]
+pub struct Document {
+    pub nodes: Vec<Node>,
+}
+
+impl Document {
+    // parse reads the events of a document into a tree.
+    pub fn parse<I>(events: I) -> Result<Document, Box<dyn Error>>
+    where
+        I: Iterator<Item = Result<Event<'static>, Box<dyn Error>>>,
+    {
+        // The open elements, from the root down.
+        let mut open: Vec<Element> = Vec::new();
+        let mut doc = Document::default();
+        for event in events {
+            let node = match event? {
+                Event::Start(start) => {
+                    open.push(Element {
+                        start,
+                        children: Vec::new(),
+                        empty: false,
+                    });
+                    continue;
+                }
+                Event::End(end) => {
+                    let element = open.pop().ok_or("unexpected close tag")?;
+                    if end.name() != element.start.name() {
+                        return Err(format!(
+                            "unexpected {:?} close tag, current tag is {:?}",
+                            String::from_utf8_lossy(end.name().into_inner()),
+                            String::from_utf8_lossy(element.start.name().into_inner()),
+                        )
+                        .into());
+                    }
+                    Node::Element(element)
+                }
+                Event::Empty(start) => Node::Element(Element {
+                    start,
+                    children: Vec::new(),
+                    empty: true,
+                }),
+                Event::Eof => break,
+                event => Node::Other(event),
+            };
+            match open.last_mut() {
+                Some(parent) => parent.children.push(node),
+                None => doc.nodes.push(node),
+            }
+        }
+        if let Some(element) = open.last() {
+            let tag = tag::Tag::new(&element.start)?;
+            return Err(format!("unexpected end of document inside {tag}").into());
+        }
+        Ok(doc)
+    }
+
+    // into_events flattens the tree back into events.
+    pub fn into_events(self) -> Vec<Event<'static>> {
+        let mut events = Vec::new();
+        flatten(self.nodes, &mut events);
+        events
+    }
+
+    // inline_uses replaces the <use> tags that any of the matchers match
+    // with a <g> holding a copy of the element they reference.
+    pub fn inline_uses(&mut self, matchers: &[&AndMatcher]) -> Result<(), Box<dyn Error>> {
+        let mut ids = HashMap::new();
+        index_ids(&self.nodes, &mut ids)?;
+        let mut tag_stack = tag::Stack::default();
+        inline_nodes(&mut self.nodes, &mut tag_stack, matchers, &ids, 0)
+    }
+}
+
+fn flatten(nodes: Vec<Node>, events: &mut Vec<Event<'static>>) {
+    for node in nodes {
+        match node {
+            Node::Other(event) => events.push(event),
+            Node::Element(element) if element.empty => events.push(Event::Empty(element.start)),
+            Node::Element(element) => {
+                let end = BytesEnd::new(
+                    String::from_utf8_lossy(element.start.name().into_inner()).into_owned(),
+                );
+                events.push(Event::Start(element.start));
+                flatten(element.children, events);
+                events.push(Event::End(end));
+            }
+        }
+    }
+}
+
+// index_ids copies each element with an id into the map,
+// as it was before any inlining.
+fn index_ids(nodes: &[Node], ids: &mut HashMap<String, Element>) -> Result<(), Box<dyn Error>> {
+    for node in nodes {
+        let Node::Element(element) = node else {
+            continue;
+        };
+        let tag = tag::Tag::new(&element.start)?;
+        if !tag.id.is_empty() {
+            ids.entry(tag.id).or_insert_with(|| element.clone());
+        }
+        index_ids(&element.children, ids)?;
+    }
+    Ok(())
+}
+
+fn inline_nodes(
+    nodes: &mut [Node],
+    tag_stack: &mut tag::Stack,
+    matchers: &[&AndMatcher],
+    ids: &HashMap<String, Element>,
+    depth: usize,
+) -> Result<(), Box<dyn Error>> {
+    for node in nodes {
+        let Node::Element(element) = node else {
+            continue;
+        };
+        tag_stack.push(tag::Tag::new(&element.start)?);
+        let tag = &tag_stack[tag_stack.len() - 1];
+        let is_use = tag.name == "use";
+        if is_use && matchers.iter().any(|m| m.matches(tag_stack)) {
+            if depth >= MAX_USE_DEPTH {
+                return Err(format!("{tag} is nested too deeply; is there a cycle?").into());
+            }
+            *element = inline_use(tag, ids).map_err(|e| format!("failed to inline {tag}: {e}"))?;
+        }
+        // Look inside the copy too, for any <use> tags it holds.
+        let depth = if is_use { depth + 1 } else { depth };
+        inline_nodes(&mut element.children, tag_stack, matchers, ids, depth)?;
+        tag_stack.pop();
+    }
+    Ok(())
+}
+
+// inline_use makes the <g> that replaces a <use>.
+// It keeps the attributes of the <use>, with x and y as a translation,
+// and a copy of the referenced element, without its ids.
+// A <symbol> becomes an <svg>, sized like the <use>.
+fn inline_use(tag: &tag::Tag, ids: &HashMap<String, Element>) -> Result<Element, Box<dyn Error>> {
+    let href = tag.href().ok_or("no href")?;
+    let id = href
+        .strip_prefix('#')
+        .ok_or_else(|| format!("only local references can be inlined, not {href:?}"))?;
+    let mut target = ids
+        .get(id)
+        .cloned()
+        .ok_or_else(|| format!("no element has the id {id:?}"))?;
+    let symbol = target.start.name().into_inner() == b"symbol";
+
+    let mut group = BytesStart::new("g");
+    for (key, value) in &tag.attrs {
+        match key.as_str() {
+            "href" | "xlink:href" | "x" | "y" | "transform" => {}
+            "width" | "height" if symbol => {}
+            _ => group.push_attribute((key.as_str(), value.as_str())),
+        }
+    }
+    let x = tag.attr("x").unwrap_or("0");
+    let y = tag.attr("y").unwrap_or("0");
+    // The translation applies after the transform of the <use>.
+    let transform = match (tag.attr("transform"), x == "0" && y == "0") {
+        (Some(transform), true) => transform.to_string(),
+        (Some(transform), false) => format!("{transform} translate({x},{y})"),
+        (None, false) => format!("translate({x},{y})"),
+        (None, true) => String::new(),
+    };
+    if !transform.is_empty() {
+        group.push_attribute(("transform", transform.as_str()));
+    }
+
+    if symbol {
+        let mut svg = BytesStart::new("svg");
+        for attr in target.start.attributes() {
+            let attr = attr?;
+            if attr.key.into_inner() != b"id" {
+                svg.push_attribute(attr);
+            }
+        }
+        for key in ["width", "height"] {
+            svg.push_attribute((key, tag.attr(key).unwrap_or("100%")));
+        }
+        target.start = svg;
+    }
+    strip_ids(&mut target)?;
+    Ok(Element {
+        start: group,
+        children: vec![Node::Element(target)],
+        empty: false,
+    })
+}
+
+// strip_ids removes the ids of a copied element and its children,
+// so that they stay unique in the document.
+fn strip_ids(element: &mut Element) -> Result<(), Box<dyn Error>> {
+    let mut start =
+        BytesStart::new(String::from_utf8_lossy(element.start.name().into_inner()).into_owned());
+    for attr in element.start.attributes() {
+        let attr = attr?;
+        if attr.key.into_inner() != b"id" {
+            start.push_attribute(attr);
+        }
+    }
+    element.start = start;
+    for child in &mut element.children {
+        if let Node::Element(child) = child {
+            strip_ids(child)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde_json::json;
+
+use battery_icon::source::Reading;
+
+// print_state writes the icon path and reading as one line of JSON,
+// for an eww deflisten variable to pick up, like
+//   {"icon":"/home/me/.cache/battery.svg","charge":85,"level":0.85,...}
+pub fn print_state(icon: &Path, reading: Reading, charging: bool) -> Result<(), Box<dyn Error>> {
+    // eww resolves image paths from its own directory, so make it absolute.
+    let icon = icon
+        .canonicalize()
+        .map_err(|e| format!("failed to resolve {icon:?}: {e}"))?;
+    let state = json!({
+        "icon": icon,
+        "charge": (reading.level * 100.0).round() as i64,
+        "level": reading.level,
+        "state": if charging { "charging" } else { reading.state.name() },
+        "charging": charging,
+        "muted": reading.muted,
+    });
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{state}").and_then(|()| stdout.flush())?;
+    Ok(())
+}
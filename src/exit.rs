@@ -0,0 +1,67 @@
+use std::error::Error;
+use std::fmt;
+use std::process::ExitCode;
+
+// Code is how battery-icon exits, so that scripts and service units
+// can tell the ways it fails apart. Like diff, 1 is an answer, not a failure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Code {
+    // --check found something the render would change.
+    Changed = 1,
+    // Bad arguments or config, or any failure without a code of its own.
+    Usage = 2,
+    // The template couldn't be read, parsed or rendered.
+    Template = 3,
+    // The battery backend couldn't be read.
+    Backend = 4,
+    // The --selector, a binding or a rule matched no element of the template.
+    NoMatch = 5,
+    // The output couldn't be written.
+    Write = 6,
+}
+
+impl From<Code> for ExitCode {
+    fn from(code: Code) -> ExitCode {
+        ExitCode::from(code as u8)
+    }
+}
+
+// Failure is an error along with the code to exit with.
+#[derive(Debug)]
+pub struct Failure {
+    pub code: Code,
+    error: Box<dyn Error>,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl Error for Failure {}
+
+// code_of returns the code of the failure, or Usage for an error without one.
+pub fn code_of(error: &(dyn Error + 'static)) -> Code {
+    error
+        .downcast_ref::<Failure>()
+        .map_or(Code::Usage, |failure| failure.code)
+}
+
+// OrExit gives the error of a result a code to exit with,
+// unless it already has one from closer to where it happened.
+pub trait OrExit<T> {
+    fn or_exit(self, code: Code) -> Result<T, Box<dyn Error>>;
+}
+
+impl<T, E: Into<Box<dyn Error>>> OrExit<T> for Result<T, E> {
+    fn or_exit(self, code: Code) -> Result<T, Box<dyn Error>> {
+        self.map_err(|e| {
+            let error = e.into();
+            match error.is::<Failure>() {
+                true => error,
+                false => Box::new(Failure { code, error }),
+            }
+        })
+    }
+}
@@ -0,0 +1,386 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::vars::Vars;
+
+// Value is the result of an expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Bool(bool),
+    Str(String),
+}
+
+impl Value {
+    // from_var interprets the text of a variable,
+    // so that "42" compares as a number and "true" as a bool.
+    fn from_var(text: String) -> Value {
+        if let Ok(n) = text.parse() {
+            return Value::Number(n);
+        }
+        match text.as_str() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => Value::Str(text),
+        }
+    }
+
+    pub fn truthy(&self) -> bool {
+        match self {
+            Value::Number(n) => *n != 0.0,
+            Value::Bool(b) => *b,
+            Value::Str(s) => !s.is_empty(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{n}"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Str(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+// eval evaluates an expression like `charging && charge < 20` or `38.25 * level`,
+// looking up identifiers in the vars.
+pub fn eval(src: &str, vars: &Vars) -> Result<Value, Box<dyn Error>> {
+    let tokens = tokenize(src).map_err(|e| format!("in {src:?}: {e}"))?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        vars,
+    };
+    let value = parser.or().map_err(|e| format!("in {src:?}: {e}"))?;
+    if let Some(token) = parser.peek() {
+        return Err(format!("in {src:?}: unexpected {token}").into());
+    }
+    Ok(value)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{n}"),
+            Token::Str(s) => write!(f, "{s:?}"),
+            Token::Ident(name) => write!(f, "{name}"),
+            Token::Op(op) => write!(f, "{op:?}"),
+        }
+    }
+}
+
+// Longer operators come first, so that "<=" isn't read as "<".
+const OPS: [&str; 16] = [
+    "<=", ">=", "==", "!=", "&&", "||", "<", ">", "!", "(", ")", "+", "-", "*", "/", "%",
+];
+
+fn tokenize(src: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    let mut tokens = Vec::new();
+    let mut rest = src.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() || c == '.' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let n = rest[..end]
+                .parse()
+                .map_err(|e| format!("invalid number {:?}: {e}", &rest[..end]))?;
+            tokens.push(Token::Number(n));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if c == '"' || c == '\'' {
+            let end = rest[1..]
+                .find(c)
+                .ok_or_else(|| format!("unclosed string {rest:?}"))?;
+            tokens.push(Token::Str(rest[1..end + 1].to_string()));
+            rest = &rest[end + 2..];
+        } else if let Some(op) = OPS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(format!("unexpected {c:?}").into());
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+// Parser evaluates the tokens by recursive descent,
+// with one method per level of operator precedence.
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    vars: &'a Vars<'a>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    // eat consumes the next token if it is the given operator.
+    fn eat(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(o)) if *o == op) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn or(&mut self) -> Result<Value, Box<dyn Error>> {
+        let mut value = self.and()?;
+        while self.eat("||") {
+            let rhs = self.and()?;
+            value = Value::Bool(value.truthy() || rhs.truthy());
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> Result<Value, Box<dyn Error>> {
+        let mut value = self.not()?;
+        while self.eat("&&") {
+            let rhs = self.not()?;
+            value = Value::Bool(value.truthy() && rhs.truthy());
+        }
+        Ok(value)
+    }
+
+    fn not(&mut self) -> Result<Value, Box<dyn Error>> {
+        if self.eat("!") {
+            let value = self.not()?;
+            return Ok(Value::Bool(!value.truthy()));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Value, Box<dyn Error>> {
+        let lhs = self.sum()?;
+        for op in ["<=", ">=", "==", "!=", "<", ">"] {
+            if !self.eat(op) {
+                continue;
+            }
+            let rhs = self.sum()?;
+            let result = match (&lhs, &rhs) {
+                (Value::Number(a), Value::Number(b)) => compare(op, a, b),
+                (Value::Str(a), Value::Str(b)) => compare(op, a, b),
+                (a, b) if op == "==" => a == b,
+                (a, b) if op == "!=" => a != b,
+                (a, b) => return Err(format!("cannot compare {a:?} {op} {b:?}").into()),
+            };
+            return Ok(Value::Bool(result));
+        }
+        Ok(lhs)
+    }
+
+    fn sum(&mut self) -> Result<Value, Box<dyn Error>> {
+        let mut value = self.product()?;
+        loop {
+            let op = if self.eat("+") {
+                "+"
+            } else if self.eat("-") {
+                "-"
+            } else {
+                return Ok(value);
+            };
+            let rhs = self.product()?;
+            value = arithmetic(op, &value, &rhs)?;
+        }
+    }
+
+    fn product(&mut self) -> Result<Value, Box<dyn Error>> {
+        let mut value = self.negation()?;
+        loop {
+            let op = if self.eat("*") {
+                "*"
+            } else if self.eat("/") {
+                "/"
+            } else if self.eat("%") {
+                "%"
+            } else {
+                return Ok(value);
+            };
+            let rhs = self.negation()?;
+            value = arithmetic(op, &value, &rhs)?;
+        }
+    }
+
+    fn negation(&mut self) -> Result<Value, Box<dyn Error>> {
+        if self.eat("-") {
+            let value = self.negation()?;
+            return arithmetic("-", &Value::Number(0.0), &value);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Value, Box<dyn Error>> {
+        let Some(token) = self.peek().cloned() else {
+            return Err("unexpected end of expression".into());
+        };
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(Value::Number(n)),
+            Token::Str(s) => Ok(Value::Str(s)),
+            Token::Ident(name) => match name.as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => Ok(Value::from_var(self.vars.get(&name)?)),
+            },
+            Token::Op("(") => {
+                let value = self.or()?;
+                if !self.eat(")") {
+                    return Err("missing \")\"".into());
+                }
+                Ok(value)
+            }
+            token => Err(format!("unexpected {token}").into()),
+        }
+    }
+}
+
+fn arithmetic(op: &str, lhs: &Value, rhs: &Value) -> Result<Value, Box<dyn Error>> {
+    let (Value::Number(a), Value::Number(b)) = (lhs, rhs) else {
+        return Err(format!("cannot compute {lhs:?} {op} {rhs:?}").into());
+    };
+    let result = match op {
+        "+" => a + b,
+        "-" => a - b,
+        "*" => a * b,
+        "/" => a / b,
+        "%" => a % b,
+        _ => unreachable!("unknown arithmetic {op}"),
+    };
+    if !result.is_finite() {
+        return Err(format!("{a} {op} {b} is not a number").into());
+    }
+    Ok(Value::Number(result))
+}
+
+fn compare<T: PartialOrd>(op: &str, a: T, b: T) -> bool {
+    match op {
+        "<=" => a <= b,
+        ">=" => a >= b,
+        "==" => a == b,
+        "!=" => a != b,
+        "<" => a < b,
+        ">" => a > b,
+        _ => unreachable!("unknown comparison {op}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Data;
+    use crate::locale::Locale;
+    use crate::source::Reading;
+
+    // check evaluates each expression with a charge of 50%, while not charging.
+    fn check(cases: &[(&str, Value)]) {
+        let data = Data::new();
+        let vars = Vars::new(Reading::from(0.5), false, &data, &[], &Locale::default());
+        for (src, want) in cases {
+            match eval(src, &vars) {
+                Ok(value) => assert_eq!(&value, want, "{src}"),
+                Err(e) => panic!("{src}: {e}"),
+            }
+        }
+    }
+
+    // error evaluates the expression, and returns the error it should fail with.
+    fn error(src: &str) -> String {
+        let data = Data::new();
+        let vars = Vars::new(Reading::from(0.5), false, &data, &[], &Locale::default());
+        match eval(src, &vars) {
+            Ok(value) => panic!("{src}: expected an error, got {value:?}"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        use Value::{Bool, Number};
+        check(&[
+            ("1 + 2 * 3", Number(7.0)),
+            ("(1 + 2) * 3", Number(9.0)),
+            ("10 - 4 - 3", Number(3.0)),
+            ("12 / 2 / 3", Number(2.0)),
+            ("7 % 4 + 1", Number(4.0)),
+            ("-2 * 3", Number(-6.0)),
+            ("--2", Number(2.0)),
+            ("38.25 * level", Number(19.125)),
+            ("1 + 1 == 2", Bool(true)),
+            ("!false && 1 < 2 || false", Bool(true)),
+            ("true || false && false", Bool(true)),
+            ("(true || false) && false", Bool(false)),
+            ("!(charge < 20)", Bool(true)),
+            ("!charging && charge >= 50", Bool(true)),
+        ]);
+    }
+
+    #[test]
+    fn comparisons() {
+        use Value::{Bool, Str};
+        check(&[
+            ("'a' < 'b'", Bool(true)),
+            ("\"b\" <= 'a'", Bool(false)),
+            ("state == 'unknown'", Bool(true)),
+            ("charge == 50", Bool(true)),
+            ("charge != '50'", Bool(true)),
+            ("1 == '1'", Bool(false)),
+            ("1 != true", Bool(true)),
+            ("charging == false", Bool(true)),
+            ("'50' == \"50\"", Bool(true)),
+            ("'it is'", Str("it is".to_string())),
+        ]);
+        for src in ["1 < 'a'", "charging < 1", "true > false"] {
+            assert!(error(src).contains("cannot compare"), "{src}");
+        }
+        assert!(error("'a' + 1").contains("cannot compute"));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert!(error("1 / 0").contains("is not a number"));
+        assert!(error("1 % 0").contains("is not a number"));
+        assert!(error("level / (charge - 50)").contains("is not a number"));
+    }
+
+    #[test]
+    fn unknown_identifiers() {
+        let e = error("nope + 1");
+        assert!(e.contains("unknown placeholder {{nope}}"), "{e}");
+        assert!(e.starts_with("in \"nope + 1\""), "{e}");
+    }
+
+    #[test]
+    fn malformed() {
+        for (src, want) in [
+            ("1 2", "unexpected 2"),
+            ("charge )", "unexpected \")\""),
+            ("(1 + 2", "missing \")\""),
+            ("1 +", "unexpected end of expression"),
+            ("", "unexpected end of expression"),
+            ("'abc", "unclosed string"),
+            ("1 $ 2", "unexpected '$'"),
+            ("1.2.3", "invalid number"),
+        ] {
+            let e = error(src);
+            assert!(e.contains(want), "{src}: {e}");
+        }
+    }
+}
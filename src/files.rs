@@ -0,0 +1,266 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use flate2::bufread::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::raster::{self, RasterArgs};
+use crate::templates;
+
+// The path that stands for stdin as the input, or stdout as the output.
+pub const STDIO: &str = "-";
+
+// The SVG read from stdin, kept since stdin can only be read once,
+// but the template is read again for each render in --watch mode.
+static STDIN_SVG: OnceLock<Vec<u8>> = OnceLock::new();
+
+// open_input opens an SVG for reading, or reads it from stdin for -,
+// decompressing it if it is gzipped, like an .svgz.
+// A path like builtin:circle reads the built-in template of that name.
+pub fn open_input(path: &Path) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+    if let Some(name) = templates::name(path) {
+        return Ok(Box::new(templates::get(name)?.as_bytes()));
+    }
+    if path == Path::new(STDIO) {
+        let svg = match STDIN_SVG.get() {
+            Some(svg) => svg,
+            None => {
+                let mut svg = Vec::new();
+                io::stdin()
+                    .read_to_end(&mut svg)
+                    .map_err(|e| format!("failed to read SVG from stdin: {e}"))?;
+                STDIN_SVG.get_or_init(|| svg)
+            }
+        };
+        return decompress(svg.as_slice(), path);
+    }
+    let f = File::open(path).map_err(|e| format!("SVG file {path:?} should be readable: {e}"))?;
+    decompress(BufReader::new(f), path)
+}
+
+// decompress gunzips the input if it starts like a gzip file.
+fn decompress(
+    mut input: impl BufRead + 'static,
+    path: &Path,
+) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+    // Check for the gzip magic number, rather than trusting the extension.
+    let gzipped = input
+        .fill_buf()
+        .map_err(|e| format!("failed to read SVG file {path:?}: {e}"))?
+        .starts_with(&[0x1f, 0x8b]);
+    if gzipped {
+        return Ok(Box::new(BufReader::new(GzDecoder::new(input))));
+    }
+    Ok(Box::new(input))
+}
+
+// replace writes the file at path through write, which is given a temporary path
+// in the same directory, then fsyncs it and renames it over the file,
+// so that readers of the path never see it half written.
+// The temporary file keeps the extension, for write to pick the format by.
+// If the file comes out the same, it is left alone.
+pub fn replace(
+    path: &Path,
+    write: impl FnOnce(&Path) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("{path:?} is not a file"))?
+        .to_string_lossy();
+    let tmp = path.with_file_name(format!(".{}.{name}", std::process::id()));
+    let result = write(&tmp).and_then(|()| {
+        if is_unchanged(path, &fs::read(&tmp)?) {
+            log::debug!("{path:?} is unchanged, not replacing it");
+            return fs::remove_file(&tmp)
+                .map_err(|e| format!("failed to remove {tmp:?}: {e}").into());
+        }
+        let f = File::open(&tmp).map_err(|e| format!("failed to reopen {tmp:?}: {e}"))?;
+        f.sync_all()
+            .map_err(|e| format!("failed to sync {tmp:?}: {e}"))?;
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&tmp, metadata.permissions())
+                .map_err(|e| format!("failed to copy the permissions of {path:?}: {e}"))?;
+        }
+        fs::rename(&tmp, path).map_err(|e| format!("failed to replace {path:?}: {e}").into())
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+// is_unchanged says whether the file at path already holds the bytes.
+fn is_unchanged(path: &Path, bytes: &[u8]) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.len() == bytes.len() as u64)
+        && fs::read(path).is_ok_and(|old| old == bytes)
+}
+
+// Target is a path to write a render to, with a width of its own for a PNG or ICO,
+// given like tray.png@24.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Target {
+    pub path: PathBuf,
+    pub width: Option<u32>,
+}
+
+// The width follows the last "@", if it is a number,
+// so that names like icon@2x.png are left whole.
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Target, String> {
+        let (path, width) = match s.rsplit_once('@') {
+            Some((path, width))
+                if !width.is_empty() && width.bytes().all(|c| c.is_ascii_digit()) =>
+            {
+                let width = width
+                    .parse()
+                    .map_err(|e| format!("failed to parse the width in {s:?}: {e}"))?;
+                (path, Some(width))
+            }
+            _ => (s, None),
+        };
+        Ok(Target {
+            path: PathBuf::from(path),
+            width,
+        })
+    }
+}
+
+// Output is a file being written, or stdout, either as plain SVG, gzipped,
+// or rasterized once the SVG is complete.
+pub enum Output {
+    Plain(BufWriter<Sink>),
+    Gzip(GzEncoder<BufWriter<Sink>>),
+    Raster {
+        file: BufWriter<Sink>,
+        format: RasterFormat,
+        args: RasterArgs,
+        svg: Vec<u8>,
+    },
+}
+
+// Sink is stdout, or a file, held in memory until it is finished,
+// so that a file that comes out the same isn't written again.
+// That keeps its modification time from changing
+// on every render of --watch mode, for the programs that watch it.
+pub enum Sink {
+    Stdout(io::Stdout),
+    File { path: PathBuf, bytes: Vec<u8> },
+}
+
+impl Sink {
+    // commit writes the file, unless it is unchanged.
+    fn commit(self) -> Result<(), Box<dyn Error>> {
+        let Sink::File { path, bytes } = self else {
+            return Ok(());
+        };
+        if is_unchanged(&path, &bytes) {
+            log::debug!("{path:?} is unchanged, not writing it");
+            return Ok(());
+        }
+        fs::write(&path, &bytes)?;
+        Ok(())
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Stdout(w) => w.write(buf),
+            Sink::File { bytes, .. } => bytes.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Stdout(w) => w.flush(),
+            Sink::File { .. } => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum RasterFormat {
+    Png,
+    Ico,
+}
+
+impl Output {
+    // create opens a file for writing,
+    // gzipping it if the name ends in .svgz,
+    // and rasterizing it if the name ends in .png or .ico.
+    // For -, it writes plain SVG to stdout.
+    pub fn create(path: &Path, raster_args: &RasterArgs) -> Result<Output, Box<dyn Error>> {
+        if path == Path::new(STDIO) {
+            return Ok(Output::Plain(BufWriter::new(Sink::Stdout(io::stdout()))));
+        }
+        let output = BufWriter::new(Sink::File {
+            path: path.to_path_buf(),
+            bytes: Vec::new(),
+        });
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let format = match extension {
+            Some("svgz") => {
+                return Ok(Output::Gzip(GzEncoder::new(output, Compression::default())))
+            }
+            Some("png") => RasterFormat::Png,
+            Some("ico") => RasterFormat::Ico,
+            _ => return Ok(Output::Plain(output)),
+        };
+        Ok(Output::Raster {
+            file: output,
+            format,
+            args: raster_args.clone(),
+            svg: Vec::new(),
+        })
+    }
+
+    // finish writes out everything left in the buffers.
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        let file = match self {
+            Output::Plain(w) => w,
+            Output::Gzip(w) => w.finish()?,
+            Output::Raster {
+                mut file,
+                format,
+                args,
+                svg,
+            } => {
+                let pixmap = raster::rasterize_with(&svg, &args)?;
+                let bytes = match format {
+                    RasterFormat::Png => pixmap
+                        .encode_png()
+                        .map_err(|e| format!("failed to encode PNG: {e}"))?,
+                    RasterFormat::Ico => raster::encode_ico(&pixmap)?,
+                };
+                file.write_all(&bytes)?;
+                file
+            }
+        };
+        file.into_inner().map_err(|e| e.into_error())?.commit()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(w) => w.write(buf),
+            Output::Gzip(w) => w.write(buf),
+            Output::Raster { svg, .. } => svg.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(w) => w.flush(),
+            Output::Gzip(w) => w.flush(),
+            Output::Raster { .. } => Ok(()),
+        }
+    }
+}
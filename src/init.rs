@@ -0,0 +1,71 @@
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use battery_icon::{files, templates};
+
+/// Settings for the init subcommand.
+#[derive(clap::Args, Debug)]
+pub struct InitArgs {
+    /// Path to write the starter SVG to, or - to print it.
+    svg: PathBuf,
+
+    /// The built-in template to start from.
+    #[arg(
+        long,
+        default_value = "horizontal",
+        value_parser = clap::builder::PossibleValuesParser::new(templates::TEMPLATES.map(|(name, _)| name))
+    )]
+    style: String,
+
+    /// Replace the file if it already exists.
+    #[arg(long)]
+    force: bool,
+}
+
+// HEADER goes at the top of a starter SVG, to say which parts battery-icon changes.
+const HEADER: &str = "  <!--
+    A battery-icon template. Edit the drawing freely; battery-icon changes these parts:
+      #fraction      the bar, whose width is scaled to the charge,
+                     or its height with data-orient=\"vertical\"
+      #bolt          shown only while charging, with everything inside of it
+      #not-charging  shown while plugged in but not charging, like at a charge limit
+      #pct           text replaced with the percentage, like 42%, as --pct-format says
+    Attributes like data-show-if=\"charge < 20\" and data-bind-x=\"4 + 30 * level\"
+    compute from the placeholders; see the README for the rest.
+  -->
+";
+
+// run writes the SVG of a built-in template to start a design from,
+// with the ids and data attributes that battery-icon looks for,
+// explained in a comment at the top.
+pub fn run(args: &InitArgs) -> Result<(), Box<dyn Error>> {
+    let svg = templates::get(&args.style)?;
+    let (root, rest) = svg.split_at(svg.find('>').map_or(0, |i| i + 1));
+    let starter = format!("{root}\n{HEADER}{}", rest.trim_start_matches('\n'));
+    if args.svg == std::path::Path::new(files::STDIO) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(starter.as_bytes())?;
+        return Ok(stdout.flush()?);
+    }
+    let mut f = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .create_new(!args.force)
+        .open(&args.svg)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => {
+                format!("{:?} already exists; use --force to replace it", args.svg)
+            }
+            _ => format!("failed to create {:?}: {e}", args.svg),
+        })?;
+    f.write_all(starter.as_bytes())
+        .map_err(|e| format!("failed to write {:?}: {e}", args.svg))?;
+    println!(
+        "wrote {:?}; try battery-icon --level 0.5 {:?} battery.svg",
+        args.svg, args.svg
+    );
+    Ok(())
+}
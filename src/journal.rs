@@ -0,0 +1,264 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+/// Settings for logging power events in --watch mode.
+#[derive(clap::Args, Clone, Debug)]
+pub struct JournalArgs {
+    /// Log power events, like plugging in and charge thresholds,
+    /// to "journald", or as lines of JSON appended to a file.
+    /// Needs --watch, or the watch subcommand.
+    #[arg(long)]
+    pub journal: Option<String>,
+
+    /// Charge percentages whose crossings are logged.
+    #[arg(long, value_delimiter = ',', default_values_t = [5.0, 15.0, 30.0])]
+    pub journal_thresholds: Vec<f64>,
+}
+
+// Sink is where the events go.
+enum Sink {
+    File(File),
+    #[cfg(unix)]
+    Journald(std::os::unix::net::UnixDatagram),
+}
+
+// Journal compares each reading of watch mode to the one before,
+// and logs the power events in between.
+pub struct Journal {
+    sink: Sink,
+    thresholds: Vec<f64>,
+    last: Option<Sample>,
+}
+
+struct Sample {
+    charge: f64,
+    charging: bool,
+    wall: SystemTime,
+    monotonic: Instant,
+}
+
+// A suspend shows up as the wall clock getting ahead of the monotonic clock,
+// which stops while the machine sleeps. Smaller gaps are just scheduling.
+const SUSPEND_GAP: Duration = Duration::from_secs(5);
+
+impl Journal {
+    // open returns the journal that --journal asks for, if any.
+    pub fn open(args: &JournalArgs) -> Result<Option<Journal>, Box<dyn Error>> {
+        let Some(target) = &args.journal else {
+            return Ok(None);
+        };
+        let sink = if target == "journald" {
+            journald()?
+        } else {
+            let path = PathBuf::from(target);
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| format!("journal {path:?} should be writable: {e}"))?;
+            Sink::File(file)
+        };
+        Ok(Some(Journal {
+            sink,
+            thresholds: args.journal_thresholds.clone(),
+            last: None,
+        }))
+    }
+
+    // record logs the events since the previous reading.
+    pub fn record(&mut self, level: f64, charging: bool) -> Result<(), Box<dyn Error>> {
+        let now = Sample {
+            charge: level * 100.0,
+            charging,
+            wall: SystemTime::now(),
+            monotonic: Instant::now(),
+        };
+        if let Some(last) = &self.last {
+            for (message, fields) in events(&self.thresholds, last, &now) {
+                self.write(&message, &fields)?;
+            }
+        }
+        self.last = Some(now);
+        Ok(())
+    }
+
+    fn write(&mut self, message: &str, fields: &Value) -> Result<(), Box<dyn Error>> {
+        match &mut self.sink {
+            Sink::File(file) => {
+                writeln!(file, "{fields}").map_err(|e| format!("failed to write journal: {e}"))?
+            }
+            #[cfg(unix)]
+            Sink::Journald(socket) => {
+                // The native journal protocol: one FIELD=value per line.
+                let mut entry = format!(
+                    "MESSAGE=battery {message}\nPRIORITY=5\nSYSLOG_IDENTIFIER=battery-icon\n"
+                );
+                if let Some(fields) = fields.as_object() {
+                    for (key, value) in fields {
+                        let value = value.as_str().map_or(value.to_string(), str::to_string);
+                        entry.push_str(&format!("BATTERY_{}={value}\n", key.to_uppercase()));
+                    }
+                }
+                socket
+                    .send(entry.as_bytes())
+                    .map_err(|e| format!("failed to write to journald: {e}"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// events lists the power events between two samples,
+// each as a message for journald and the fields of its JSON line.
+fn events(thresholds: &[f64], last: &Sample, now: &Sample) -> Vec<(String, Value)> {
+    let mut events = Vec::new();
+
+    let wall = now.wall.duration_since(last.wall).unwrap_or_default();
+    let monotonic = now.monotonic.duration_since(last.monotonic);
+    if wall > monotonic + SUSPEND_GAP {
+        let asleep = (wall - monotonic).as_secs();
+        events.push((
+            format!("resumed after {asleep}s asleep"),
+            json!({"event": "resume", "asleep_seconds": asleep}),
+        ));
+    }
+    if now.charging != last.charging {
+        let event = if now.charging { "plugged" } else { "unplugged" };
+        events.push((
+            format!("{event} at {:.0}%", now.charge),
+            json!({"event": event}),
+        ));
+    }
+    for &threshold in thresholds {
+        let direction = if last.charge >= threshold && now.charge < threshold {
+            "below"
+        } else if last.charge < threshold && now.charge >= threshold {
+            "above"
+        } else {
+            continue;
+        };
+        events.push((
+            format!("charge went {direction} {threshold}%"),
+            json!({"event": "threshold", "threshold": threshold, "direction": direction}),
+        ));
+    }
+
+    let time = now
+        .wall
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    for (_, fields) in &mut events {
+        fields["time"] = time.into();
+        fields["charge"] = (now.charge.round() as i64).into();
+        fields["charging"] = now.charging.into();
+    }
+    events
+}
+
+#[cfg(unix)]
+fn journald() -> Result<Sink, Box<dyn Error>> {
+    let path = "/run/systemd/journal/socket";
+    let socket = std::os::unix::net::UnixDatagram::unbound()?;
+    socket
+        .connect(path)
+        .map_err(|e| format!("failed to connect to journald at {path}: {e}"))?;
+    Ok(Sink::Journald(socket))
+}
+
+#[cfg(not(unix))]
+fn journald() -> Result<Sink, Box<dyn Error>> {
+    Err("journald is only available on Linux; give a file path to --journal instead".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sample is a reading taken the seconds after the first one, by both clocks.
+    fn sample(start: Instant, seconds: u64, charge: f64, charging: bool) -> Sample {
+        Sample {
+            charge,
+            charging,
+            wall: UNIX_EPOCH + Duration::from_secs(1_700_000_000 + seconds),
+            monotonic: start + Duration::from_secs(seconds),
+        }
+    }
+
+    // messages lists the events between the samples, by their messages.
+    fn messages(last: &Sample, now: &Sample) -> Vec<String> {
+        events(&[5.0, 15.0, 30.0], last, now)
+            .into_iter()
+            .map(|(message, _)| message)
+            .collect()
+    }
+
+    #[test]
+    fn thresholds() {
+        let start = Instant::now();
+        let (high, low) = (sample(start, 0, 31.0, false), sample(start, 5, 14.6, false));
+        assert_eq!(
+            messages(&high, &low),
+            ["charge went below 15%", "charge went below 30%"]
+        );
+        let charged = sample(start, 10, 15.0, false);
+        assert_eq!(messages(&low, &charged), ["charge went above 15%"]);
+        // Staying on either side of the thresholds is nothing to log.
+        assert!(messages(&charged, &sample(start, 15, 29.9, false)).is_empty());
+        assert_eq!(
+            messages(&low, &sample(start, 15, 4.9, false)),
+            ["charge went below 5%"]
+        );
+    }
+
+    #[test]
+    fn plugging_in() {
+        let start = Instant::now();
+        let unplugged = sample(start, 0, 50.0, false);
+        let plugged = sample(start, 5, 50.4, true);
+        assert_eq!(messages(&unplugged, &plugged), ["plugged at 50%"]);
+        assert_eq!(messages(&plugged, &unplugged), ["unplugged at 50%"]);
+    }
+
+    #[test]
+    fn resumes() {
+        let start = Instant::now();
+        let last = sample(start, 0, 50.0, false);
+        // The monotonic clock stood still for the hour the machine slept.
+        let now = Sample {
+            wall: last.wall + Duration::from_secs(3605),
+            ..sample(start, 5, 50.0, false)
+        };
+        assert_eq!(messages(&last, &now), ["resumed after 3600s asleep"]);
+        let now = Sample {
+            wall: last.wall + Duration::from_secs(9),
+            ..sample(start, 5, 50.0, false)
+        };
+        assert!(messages(&last, &now).is_empty());
+    }
+
+    #[test]
+    fn json_lines() {
+        let start = Instant::now();
+        let lines: Vec<String> = events(
+            &[30.0],
+            &sample(start, 0, 30.2, true),
+            &sample(start, 60, 29.6, false),
+        )
+        .into_iter()
+        .map(|(_, fields)| fields.to_string())
+        .collect();
+        assert_eq!(
+            lines,
+            [
+                r#"{"charge":30,"charging":false,"event":"unplugged","time":1700000060}"#,
+                r#"{"charge":30,"charging":false,"direction":"below","event":"threshold","threshold":30.0,"time":1700000060}"#,
+            ]
+        );
+    }
+}
@@ -0,0 +1,1390 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io::BufRead;
+use std::vec;
+
+use indexmap::IndexMap;
+use quick_xml::events::attributes::Attribute;
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::reader::Reader;
+
+pub mod bar;
+pub mod battery;
+pub mod bluez;
+pub mod bsd;
+pub mod config;
+pub mod css;
+pub mod data;
+pub mod dom;
+pub mod expr;
+pub mod files;
+pub mod locale;
+pub mod macos;
+pub mod matcher;
+pub mod mqtt;
+pub mod overlay;
+pub mod ramp;
+pub mod raster;
+pub mod rule;
+pub mod source;
+pub mod tag;
+pub mod templates;
+pub mod vars;
+pub mod vector_drawable;
+pub mod windows;
+
+use matcher::StackMatcher;
+use source::{LevelColors, Reading, Source};
+
+// Options are the settings of a render that don't come from the config.
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub source: Source,
+    pub charging: bool,
+    // The colour of text, as a hex code without the leading '#'.
+    pub foreground: String,
+    // The color of currentColor, as a hex code without the leading '#'.
+    pub color: Option<String>,
+    // CSS custom properties for the root element, by name without the leading --,
+    // which also answer the placeholders of that name.
+    pub css_vars: Vec<(String, String)>,
+    // How the numbers in {{percent}} and text#watts are written.
+    pub locale: locale::Locale,
+    // How many decimal places to round computed numbers to, like the scaled width of the bar,
+    // so that float error doesn't show up as 23.450000000000003.
+    pub precision: usize,
+    pub strip_editor: bool,
+    pub xlink: bool,
+    // Print how the rules matched each element to stderr.
+    pub explain: bool,
+    // Whether the battery is hotter than it should be.
+    pub hot: bool,
+    // The color of the bar while the battery is hot, as a CSS color.
+    pub hot_color: Option<String>,
+    // Whether the battery is critically low.
+    pub critical: bool,
+    // Whether the battery is charged up to the full threshold,
+    // so that it can be drawn apart from one that is almost full.
+    pub full: bool,
+    // The colors of the bar by level, and while charging.
+    // The critical color is also that of a critically low battery.
+    pub colors: LevelColors,
+    // Hide the bar while the battery is critical.
+    pub critical_hide_bar: bool,
+    // The selector of the bar, like rect#fraction.
+    pub selector: String,
+    // Which way the bar fills, unless it has a data-orient attribute.
+    pub orientation: bar::Orientation,
+    // Which edge the bar shrinks toward, unless it has a data-anchor attribute,
+    // or else the left, or the bottom of a vertical bar.
+    pub anchor: Option<bar::Anchor>,
+    // The text of the text#pct and tspan#pct elements, with placeholders, like {{charge}}%,
+    // or None to leave them as they are.
+    pub pct_format: Option<String>,
+}
+
+// The default Options render like the command line does without any options,
+// from a reading given with --level.
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            source: Source::Level,
+            charging: false,
+            foreground: "000000".to_string(),
+            color: None,
+            css_vars: Vec::new(),
+            locale: locale::Locale::default(),
+            precision: 6,
+            strip_editor: false,
+            xlink: false,
+            explain: false,
+            hot: false,
+            hot_color: None,
+            critical: false,
+            full: false,
+            colors: LevelColors::default(),
+            critical_hide_bar: false,
+            selector: "rect#fraction".to_string(),
+            orientation: bar::Orientation::default(),
+            anchor: None,
+            pct_format: Some("{{percent}}".to_string()),
+        }
+    }
+}
+
+// Render holds what a render pass needs to know, besides the template.
+pub struct Render<'a> {
+    options: &'a Options,
+    reading: Reading,
+    vars: vars::Vars<'a>,
+    // The config's bindings and rules, in order.
+    edits: Vec<rule::Compiled>,
+    // How many of the edits are the built-in texts, which come first.
+    builtin_edits: usize,
+    // The matcher of the bar's selector.
+    bar: matcher::AndMatcher,
+    // What the render has done so far.
+    tally: RefCell<Tally>,
+}
+
+#[derive(Default)]
+struct Tally {
+    matched: usize,
+    modified: usize,
+    bar: usize,
+    // The elements that each edit matched.
+    hits: Vec<usize>,
+}
+
+// Report sums up what a render did to the template.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    // The elements that a binding or rule matched.
+    pub matched: usize,
+    // The elements whose attributes or text were changed.
+    pub modified: usize,
+    // The elements that the bar's selector matched.
+    pub bar: usize,
+    // The selectors of the bindings and rules that matched no element.
+    pub unmatched: Vec<String>,
+}
+
+impl<'a> Render<'a> {
+    // new compiles the bindings and the rules against the reading.
+    pub fn new(
+        options: &'a Options,
+        reading: Reading,
+        data: &'a data::Data,
+        binds: &[config::Bind],
+        rules: impl IntoIterator<Item = &'a rule::Rule>,
+    ) -> Result<Render<'a>, Box<dyn Error>> {
+        if !(0.0..=1.0).contains(&reading.level) {
+            return Err(format!("the level should be from 0 to 1, got {}", reading.level).into());
+        }
+        let vars = vars::Vars::new(
+            reading,
+            options.charging,
+            data,
+            &options.css_vars,
+            &options.locale,
+        );
+        let pct = match &options.pct_format {
+            Some(format) => Some(
+                vars.expand(format)
+                    .map_err(|e| format!("in the percentage format {format:?}: {e}"))?
+                    .into_owned(),
+            ),
+            None => None,
+        };
+        let mut edits = builtin_texts(reading, &options.locale, pct)?;
+        let builtin_edits = edits.len();
+        edits.extend(data::bind(binds, data)?);
+        for rule in rules {
+            edits.push(rule.compile(&vars)?);
+        }
+        Ok(Render {
+            options,
+            reading,
+            vars,
+            edits,
+            builtin_edits,
+            bar: matcher::new_tag_matcher(&options.selector)?,
+            tally: RefCell::default(),
+        })
+    }
+
+    // report sums up what the render did, once its events have been read.
+    pub fn report(&self) -> Report {
+        let tally = self.tally.borrow();
+        let unmatched = self.edits[self.builtin_edits..]
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| {
+                tally
+                    .hits
+                    .get(self.builtin_edits + i)
+                    .is_none_or(|&n| n == 0)
+            })
+            .map(|(_, edit)| edit.selector.clone())
+            .collect();
+        Report {
+            matched: tally.matched,
+            modified: tally.modified,
+            bar: tally.bar,
+            unmatched,
+        }
+    }
+
+    // count tallies the element at the top of the tag stack,
+    // with its tag before and after the render, and whether its text was replaced.
+    fn count(&self, tag_stack: &[tag::Tag], before: &BytesStart, after: &BytesStart, text: bool) {
+        let mut tally = self.tally.borrow_mut();
+        tally.hits.resize(self.edits.len(), 0);
+        let mut matched = false;
+        for (edit, hits) in self.edits.iter().zip(tally.hits.iter_mut()) {
+            if edit.matcher.matches(tag_stack) {
+                *hits += 1;
+                matched = true;
+            }
+        }
+        if matched {
+            tally.matched += 1;
+        }
+        if self.bar.matches(tag_stack) {
+            tally.bar += 1;
+        }
+        // Compare the attributes themselves,
+        // since the whitespace between them and the escaping of their values aren't kept.
+        let attributes = |tag: &BytesStart| {
+            tag.attributes()
+                .flatten()
+                .map(|attr| {
+                    let value = attr.unescape_value().map(Cow::into_owned);
+                    (attr.key.as_ref().to_vec(), value.ok())
+                })
+                .collect::<Vec<_>>()
+        };
+        if text || attributes(before) != attributes(after) {
+            tally.modified += 1;
+        }
+    }
+
+    // transform applies the render to a stream of template events.
+    // The events can come from read_events, or from another filter.
+    // When a rule needs the whole document, like inline,
+    // the events are read into a Document first.
+    pub fn transform<I>(&'a self, events: I) -> TransformedEvents<'a, I>
+    where
+        I: Iterator<Item = Result<Event<'static>, Box<dyn Error>>>,
+    {
+        let input = if self.inline_matchers().is_empty() {
+            Input::Stream(events)
+        } else {
+            Input::Whole(Some(events))
+        };
+        TransformedEvents {
+            render: self,
+            input,
+            output: VecDeque::new(),
+            done: false,
+            tag_stack: tag::Stack::default(),
+            replacing: None,
+            repeating: None,
+            dropping: 0,
+            stylesheet: css::Stylesheet::default(),
+        }
+    }
+
+    fn inline_matchers(&self) -> Vec<&matcher::AndMatcher> {
+        self.edits
+            .iter()
+            .filter(|c| matches!(c.edit, Some(rule::Edit::Inline)))
+            .map(|c| &c.matcher)
+            .collect()
+    }
+
+    // document makes the edits that need the whole document,
+    // returning its events for the streaming pass.
+    fn document<I>(&self, events: I) -> Result<Vec<Event<'static>>, Box<dyn Error>>
+    where
+        I: Iterator<Item = Result<Event<'static>, Box<dyn Error>>>,
+    {
+        if self.options.explain {
+            eprintln!("reading the whole template, for the inline rules");
+        }
+        let mut doc = dom::Document::parse(events)?;
+        doc.inline_uses(&self.inline_matchers())?;
+        Ok(doc.into_events())
+    }
+
+    // explain prints the tag stack of the current element,
+    // and whether each edit's selector matched it.
+    fn explain(&self, tag_stack: &[tag::Tag]) {
+        eprintln!("{}", tag::path(tag_stack));
+        for edit in &self.edits {
+            match edit.matcher.mismatch(tag_stack) {
+                None => eprintln!("  match  {}", edit.selector),
+                Some(why) => eprintln!("  no     {}: {why}", edit.selector),
+            }
+        }
+    }
+
+    // text_binding returns the text that should replace the content
+    // of the current tag, if any. Later rules take precedence.
+    fn text_binding(&self, tag_stack: &[tag::Tag]) -> Option<&str> {
+        self.edits.iter().rev().find_map(|c| match &c.edit {
+            Some(rule::Edit::SetText(text)) if c.matcher.matches(tag_stack) => Some(text.as_str()),
+            _ => None,
+        })
+    }
+}
+
+// xml_reader makes a reader of a template.
+// A strict one fails at the first flaw, like an end tag that doesn't match,
+// a -- in a comment, or a document that ends inside an element.
+// A lenient one lets the flaws that are easy to mend through for read_events to mend,
+// like </Rect> closing a <rect>, a stray end tag, or a bare & in text.
+pub fn xml_reader<R: BufRead>(input: R, strict: bool) -> Reader<R> {
+    let mut reader = Reader::from_reader(input);
+    let config = reader.config_mut();
+    config.check_end_names = strict;
+    config.check_comments = strict;
+    config.allow_unmatched_ends = !strict;
+    config.allow_dangling_amp = !strict;
+    reader
+}
+
+// read_events yields the owned events of a document, up to its end.
+// From a lenient reader, the events are mended to balance, with a warning for each fix:
+// an end tag closes the start tag of its name in any case, and any left open inside it,
+// a stray end tag is dropped, and the elements still open at the end are closed.
+pub fn read_events<R: BufRead>(reader: Reader<R>) -> ReadEvents<R> {
+    ReadEvents {
+        lenient: reader.config().allow_unmatched_ends,
+        reader,
+        buf: Vec::new(),
+        open: Vec::new(),
+        mended: VecDeque::new(),
+    }
+}
+
+// ReadEvents is the iterator returned by read_events.
+pub struct ReadEvents<R> {
+    reader: Reader<R>,
+    buf: Vec<u8>,
+    lenient: bool,
+    // The names of the open elements.
+    open: Vec<String>,
+    // End tags added to mend the document, to yield before reading on.
+    mended: VecDeque<Event<'static>>,
+}
+
+impl<R: BufRead> Iterator for ReadEvents<R> {
+    type Item = Result<Event<'static>, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.mended.pop_front() {
+                return Some(Ok(event));
+            }
+            self.buf.clear();
+            let event = match self.reader.read_event_into(&mut self.buf) {
+                Ok(event) => event.into_owned(),
+                Err(e) => {
+                    return Some(Err(format!(
+                        "error at position {}: {e}",
+                        self.reader.error_position()
+                    )
+                    .into()))
+                }
+            };
+            match event {
+                Event::Eof if self.open.is_empty() => return None,
+                Event::Eof if !self.lenient => {
+                    let open = std::mem::take(&mut self.open);
+                    return Some(Err(format!(
+                        "error at position {}: the document ended inside <{}>",
+                        self.reader.buffer_position(),
+                        open.join("> <")
+                    )
+                    .into()));
+                }
+                Event::Eof => {
+                    log::warn!(
+                        "closed <{}> at the end of the document",
+                        self.open.join("> <")
+                    );
+                    self.close(0);
+                }
+                Event::Start(e) => {
+                    self.open
+                        .push(String::from_utf8_lossy(e.name().as_ref()).into_owned());
+                    return Some(Ok(Event::Start(e)));
+                }
+                Event::End(e) if !self.lenient => {
+                    self.open.pop();
+                    return Some(Ok(Event::End(e)));
+                }
+                Event::End(e) => {
+                    let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                    let position = self.reader.buffer_position();
+                    match self
+                        .open
+                        .iter()
+                        .rposition(|open| open.eq_ignore_ascii_case(&name))
+                    {
+                        Some(i) => {
+                            if self.open[i..] != [name.clone()] {
+                                log::warn!(
+                                    "at position {position}, </{name}> closed <{}>",
+                                    self.open[i..].join("> <")
+                                );
+                            }
+                            self.close(i);
+                        }
+                        None => log::warn!("at position {position}, dropped a stray </{name}>"),
+                    }
+                }
+                // A bare & comes through as text, which is escaped to write it back out.
+                Event::Text(e) if self.lenient && e.contains(&b'&') => {
+                    log::warn!(
+                        "at position {}, escaped a bare &",
+                        self.reader.buffer_position()
+                    );
+                    let text = String::from_utf8_lossy(&e).replace('&', "&amp;");
+                    return Some(Ok(Event::Text(BytesText::from_escaped(text))));
+                }
+                event => return Some(Ok(event)),
+            }
+        }
+    }
+}
+
+impl<R> ReadEvents<R> {
+    // close queues the end tags of the elements open from depth i in, innermost first.
+    fn close(&mut self, i: usize) {
+        for name in self.open.drain(i..).rev() {
+            self.mended.push_back(Event::End(BytesEnd::new(name)));
+        }
+    }
+}
+
+// TransformedEvents is a streaming pass over the template,
+// yielding the events of the rendered icon.
+// It is made by Render::transform.
+pub struct TransformedEvents<'a, I> {
+    render: &'a Render<'a>,
+    input: Input<I>,
+    // Events ready to be yielded, since one input event can make several.
+    output: VecDeque<Event<'static>>,
+    // Set after the end of the input, or after an error.
+    done: bool,
+    tag_stack: tag::Stack,
+    // While replacing the content of a tag, this is its depth in the tag_stack.
+    replacing: Option<usize>,
+    // While reading a tag with data-repeat, this collects its events.
+    repeating: Option<Repeat>,
+    // While dropping an editor tag, this counts the open dropped tags.
+    dropping: usize,
+    // The rules of the <style> blocks read so far.
+    stylesheet: css::Stylesheet,
+}
+
+// Input is where TransformedEvents reads the template from.
+enum Input<I> {
+    Stream(I),
+    // To be read into a Document on the first call to next.
+    Whole(Option<I>),
+    Document(vec::IntoIter<Event<'static>>),
+}
+
+impl<I> TransformedEvents<'_, I>
+where
+    I: Iterator<Item = Result<Event<'static>, Box<dyn Error>>>,
+{
+    fn next_input(&mut self) -> Option<Result<Event<'static>, Box<dyn Error>>> {
+        if let Input::Whole(events) = &mut self.input {
+            let events = events.take()?;
+            match self.render.document(events) {
+                Ok(events) => self.input = Input::Document(events.into_iter()),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        match &mut self.input {
+            Input::Stream(events) => events.next(),
+            Input::Whole(_) => None,
+            Input::Document(events) => events.next().map(Ok),
+        }
+    }
+}
+
+impl<'a, I> Iterator for TransformedEvents<'a, I>
+where
+    I: Iterator<Item = Result<Event<'static>, Box<dyn Error>>>,
+{
+    type Item = Result<Event<'static>, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.output.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.done {
+                return None;
+            }
+            let result = match self.next_input() {
+                Some(Ok(event)) => self.event(event),
+                Some(Err(e)) => Err(e),
+                None => {
+                    self.done = true;
+                    if self.repeating.is_some() {
+                        return Some(Err(
+                            "unexpected end of document inside a data-repeat tag".into()
+                        ));
+                    }
+                    continue;
+                }
+            };
+            if let Err(e) = result {
+                self.done = true;
+                self.output.clear();
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+impl<I> TransformedEvents<'_, I> {
+    fn event(&mut self, event: Event<'static>) -> Result<(), Box<dyn Error>> {
+        let render = self.render;
+        let output = &mut self.output;
+        let tag_stack = &mut self.tag_stack;
+
+        // Drop editor tags and everything inside of them.
+        if self.dropping > 0 {
+            match &event {
+                Event::Start(_) => self.dropping += 1,
+                Event::End(_) => self.dropping -= 1,
+                _ => {}
+            }
+            return Ok(());
+        }
+        if render.options.strip_editor {
+            match &event {
+                Event::Start(e) if is_editor_name(e.name().into_inner()) => {
+                    self.dropping = 1;
+                    return Ok(());
+                }
+                Event::Empty(e) if is_editor_name(e.name().into_inner()) => return Ok(()),
+                _ => {}
+            }
+        }
+
+        // Collect a repeated tag up to its end, then write out the copies.
+        if let Some(repeat) = &mut self.repeating {
+            match &event {
+                Event::Start(_) => repeat.depth += 1,
+                Event::End(_) => repeat.depth -= 1,
+                _ => {}
+            }
+            repeat.events.push(event);
+            if repeat.depth == 0 {
+                let repeat = self.repeating.take().ok_or("lost the repeated tag")?;
+                self.repeat(repeat)?;
+            }
+            return Ok(());
+        }
+
+        // Drop the old content of a replaced tag, but keep the tag_stack balanced.
+        if let Some(depth) = self.replacing {
+            match &event {
+                Event::Start(e) => tag_stack.push(tag::Tag::new(e)?),
+                Event::End(_) if tag_stack.len() > depth => {
+                    tag_stack.pop();
+                }
+                Event::End(_) => self.replacing = None,
+                _ => {}
+            }
+            if self.replacing.is_some() {
+                return Ok(());
+            }
+        }
+
+        match event {
+            Event::Empty(e) => {
+                if let Some(repeat) = Repeat::new(&e)? {
+                    return self.repeat(Repeat {
+                        events: vec![Event::Empty(e)],
+                        ..repeat
+                    });
+                }
+
+                // For the sake of the matcher, push the current tag onto the stack.
+                tag_stack.push(tag::Tag::new(&e)?);
+
+                // If any rule matches, modify the attributes and return the resulting tag.
+                let new_tag =
+                    process_attributes(tag_stack, &e, render, &self.stylesheet).map_err(|e| {
+                        format!("failed to process attributes of self-closing tag: {e}")
+                    })?;
+
+                let text = render.text_binding(tag_stack);
+                render.count(tag_stack, &e, &new_tag, text.is_some());
+                if let Some(text) = text {
+                    // Expand the tag to hold the bound text.
+                    let end = BytesEnd::new(tag_stack[tag_stack.len() - 1].name.clone());
+                    output.extend([
+                        Event::Start(new_tag.into_owned()),
+                        Event::Text(BytesText::new(text).into_owned()),
+                        Event::End(end),
+                    ]);
+                } else {
+                    // Write the modified elem back into the document.
+                    output.push_back(Event::Empty(new_tag.into_owned()));
+                }
+
+                // Pop the current tag again, since Empty tags have no children.
+                tag_stack
+                    .pop()
+                    .ok_or("unexpected error while popping a self-closing tag")?;
+            }
+
+            Event::Start(e) => {
+                if let Some(repeat) = Repeat::new(&e)? {
+                    self.repeating = Some(Repeat {
+                        events: vec![Event::Start(e)],
+                        depth: 1,
+                        ..repeat
+                    });
+                    return Ok(());
+                }
+
+                // Push a tag onto the stack, which might have children.
+                tag_stack.push(tag::Tag::new(&e)?);
+                log::trace!(">> {}", tag::path(tag_stack));
+
+                // If any rule matches, modify the attributes and return the resulting tag.
+                let new_tag = process_attributes(tag_stack, &e, render, &self.stylesheet)
+                    .map_err(|e| format!("failed to process attributes of Start tag: {e}"))?;
+
+                let text = render.text_binding(tag_stack);
+                render.count(tag_stack, &e, &new_tag, text.is_some());
+
+                // Write the modified elem back into the document.
+                output.push_back(Event::Start(new_tag.into_owned()));
+
+                // Replace the content with the bound text, up to the end tag.
+                if let Some(text) = text {
+                    output.push_back(Event::Text(BytesText::new(text).into_owned()));
+                    self.replacing = Some(tag_stack.len());
+                }
+            }
+
+            Event::End(e) => {
+                log::trace!("<< {}", tag::path(tag_stack));
+
+                // Pop the tag off of the stack,
+                // and verify that it matches the start tag.
+                let Some(last_tag) = tag_stack.pop() else {
+                    return Err("unexpected close tag".into());
+                };
+                let tag = tag::Tag::new(&e)?;
+                if tag.name != last_tag.name {
+                    return Err(format!(
+                        "unexpected {:?} close tag, current tag is {:?}",
+                        tag.name, last_tag.name,
+                    )
+                    .into());
+                }
+                output.push_back(Event::End(e));
+            }
+
+            Event::Text(e) => {
+                // Expand any placeholders in the text.
+                let text = str::from_utf8(&e).map_err(|e| format!("failed to decode text: {e}"))?;
+                let text =
+                    render
+                        .vars
+                        .expand_escaped(text)
+                        .map_err(|e| match tag_stack.last() {
+                            Some(tag) => format!("in text of {tag}: {e}"),
+                            None => format!("in text: {e}"),
+                        })?;
+                if tag_stack.last().is_some_and(|tag| tag.name == "style") {
+                    let css = quick_xml::escape::unescape(&text)
+                        .map_err(|e| format!("failed to decode <style>: {e}"))?;
+                    self.stylesheet.parse(&css)?;
+                }
+                output.push_back(Event::Text(BytesText::from_escaped(text).into_owned()));
+            }
+
+            Event::CData(e) => {
+                if tag_stack.last().is_some_and(|tag| tag.name == "style") {
+                    let css =
+                        str::from_utf8(&e).map_err(|e| format!("failed to decode <style>: {e}"))?;
+                    self.stylesheet.parse(css)?;
+                }
+                output.push_back(Event::CData(e));
+            }
+
+            e => output.push_back(e),
+        }
+        Ok(())
+    }
+
+    // repeat writes out the collected events of a data-repeat tag once per copy.
+    fn repeat(&mut self, repeat: Repeat) -> Result<(), Box<dyn Error>> {
+        let precision = self.render.options.precision;
+        for i in 0..repeat.count {
+            for (j, event) in repeat.events.iter().enumerate() {
+                let event = match event {
+                    Event::Start(e) => Event::Start(repeat.copy_tag(e, i, j == 0, precision)?),
+                    Event::Empty(e) => Event::Empty(repeat.copy_tag(e, i, j == 0, precision)?),
+                    e => e.clone(),
+                };
+                self.event(event)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Repeat describes a tag marked with data-repeat="N",
+// which is written out N times, each offset from the last
+// by data-repeat-dx and data-repeat-dy.
+struct Repeat {
+    count: usize,
+    dx: f64,
+    dy: f64,
+    events: Vec<Event<'static>>,
+    depth: usize,
+}
+
+impl Repeat {
+    fn new(e: &BytesStart) -> Result<Option<Repeat>, Box<dyn Error>> {
+        let number = |name: &str| -> Result<Option<String>, Box<dyn Error>> {
+            let Some(attr) = e
+                .try_get_attribute(name)
+                .map_err(|e| format!("failed to read [{name}]: {e}"))?
+            else {
+                return Ok(None);
+            };
+            Ok(Some(attr.unescape_value()?.trim().to_string()))
+        };
+        let Some(count) = number("data-repeat")? else {
+            return Ok(None);
+        };
+        let count = count
+            .parse()
+            .map_err(|e| format!("invalid data-repeat {count:?}: {e}"))?;
+        let offset = |name: &str| -> Result<f64, Box<dyn Error>> {
+            let Some(value) = number(name)? else {
+                return Ok(0.0);
+            };
+            Ok(value
+                .parse()
+                .map_err(|e| format!("invalid {name} {value:?}: {e}"))?)
+        };
+        Ok(Some(Repeat {
+            count,
+            dx: offset("data-repeat-dx")?,
+            dy: offset("data-repeat-dy")?,
+            events: Vec::new(),
+            depth: 0,
+        }))
+    }
+
+    // copy_tag returns the ith copy of a tag in the repeated subtree.
+    // Ids get the copy number appended, to keep them unique.
+    // The root of each copy is translated into place,
+    // and becomes the (i+1)th of N data-segments, unless it already was one.
+    fn copy_tag(
+        &self,
+        e: &BytesStart,
+        i: usize,
+        root: bool,
+        precision: usize,
+    ) -> Result<BytesStart<'static>, Box<dyn Error>> {
+        let name = str::from_utf8(e.name().into_inner())?.to_string();
+        let mut tag = BytesStart::new(name);
+        let mut has_transform = false;
+        let mut has_segment = false;
+        let translate = format!(
+            "translate({},{})",
+            format_number(self.dx * i as f64, precision),
+            format_number(self.dy * i as f64, precision)
+        );
+        for attr in e.attributes() {
+            let attr = attr?;
+            let key = str::from_utf8(attr.key.into_inner())?;
+            let value = attr.unescape_value()?;
+            match key {
+                "data-repeat" | "data-repeat-dx" | "data-repeat-dy" if root => {}
+                "id" => tag.push_attribute((key, copy_id(&value, i).as_str())),
+                "transform" if root => {
+                    has_transform = true;
+                    tag.push_attribute((key, format!("{translate} {value}").as_str()));
+                }
+                _ => {
+                    has_segment |= root && key == "data-segment";
+                    tag.push_attribute((key, value.as_ref()));
+                }
+            }
+        }
+        if root && !has_transform {
+            tag.push_attribute(("transform", translate.as_str()));
+        }
+        if root && !has_segment {
+            let segment = format!("{}/{}", i + 1, self.count);
+            tag.push_attribute(("data-segment", segment.as_str()));
+        }
+        Ok(tag)
+    }
+}
+
+// copy_id numbers the id of the ith copy, like segment3.
+// An id that already ends in a digit, like that of a copy in a nested repeat,
+// gets a dash before the number, so that segment1-11 and segment11-1 stay apart.
+fn copy_id(id: &str, i: usize) -> String {
+    if id.ends_with(|c: char| c.is_ascii_digit()) {
+        format!("{id}-{}", i + 1)
+    } else {
+        format!("{id}{}", i + 1)
+    }
+}
+
+fn process_attributes<'a>(
+    tag_stack: &[tag::Tag],
+    tag_in: &'a dyn tag::TagBytes,
+    render: &Render,
+    stylesheet: &css::Stylesheet,
+) -> Result<BytesStart<'a>, Box<dyn Error>> {
+    let options = render.options;
+    let reading = render.reading;
+    let tag = tag::Tag::new(tag_in)?;
+    if options.explain {
+        render.explain(tag_stack);
+        for rule in stylesheet.matching(tag_stack) {
+            eprintln!("  css    {}", rule.selector);
+        }
+    }
+    let mut tag_out = BytesStart::new(tag.name.clone());
+
+    // Build the attr_map so that we can read and modify the attributes.
+    let attrs: Vec<Attribute> = tag_in
+        .attributes()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("failed to collect attributes of {}: {e}", tag.name))?;
+    let Ok(mut attr_map) = new_attr_map(&attrs) else {
+        return Ok(tag_out.with_attributes(attrs));
+    };
+
+    if options.strip_editor {
+        strip_editor(&mut attr_map).map_err(|e| format!("strip_editor failed: {e}"))?;
+    }
+
+    // Expand any placeholders in the attribute values.
+    for (key, value) in attr_map.iter_mut() {
+        if let Cow::Owned(expanded) = render
+            .vars
+            .expand(value)
+            .map_err(|e| format!("in {}[{key}]: {e}", tag.name))?
+        {
+            *value = expanded;
+        }
+    }
+
+    // Set the color of the text elements.
+    text_color(tag_stack, &mut attr_map, &options.foreground, stylesheet)
+        .map_err(|e| format!("text_color failed: {e}"))?;
+
+    // Set the bar graph width and color.
+    // A critical battery outranks a hot one, which outranks a full one,
+    // which outranks a charging one, which outranks the level, on the ramp if there is one.
+    let colors = &options.colors;
+    let ramp_fill = colors.ramp.as_ref().map(|ramp| ramp.at(reading.level));
+    let fill = if options.critical {
+        Some(colors.critical.as_str())
+    } else if options.hot && options.hot_color.is_some() {
+        options.hot_color.as_deref()
+    } else if options.full && colors.full.is_some() {
+        colors.full.as_deref()
+    } else if options.charging && colors.charging.is_some() {
+        colors.charging.as_deref()
+    } else if ramp_fill.is_some() {
+        ramp_fill.as_deref()
+    } else {
+        options.source.level_fill(reading.level, colors)
+    };
+    battery_fraction(
+        tag_stack,
+        &mut attr_map,
+        options,
+        reading.level,
+        fill,
+        stylesheet,
+    )
+    .map_err(|e| format!("battery_fraction failed: {e}"))?;
+
+    // Set the health bar width, or hide it if the health is unknown.
+    battery_health(tag_stack, &mut attr_map, reading.health, options.precision)
+        .map_err(|e| format!("battery_health failed: {e}"))?;
+
+    // Turn off the elements that only show in some states, like the charging icon.
+    // The bar is hidden in place of #ac on machines without a battery,
+    // and while the battery is critical with --critical-hide-bar.
+    let not_charging = !options.charging && reading.state == source::BatteryState::NotCharging;
+    let bar = options.selector.as_str();
+    let toggles = [
+        ("text#icon", options.charging),
+        ("#bolt", options.charging),
+        ("#limit", reading.charge_limit.is_some()),
+        ("#ac", reading.ac_only),
+        (bar, !reading.ac_only),
+        ("#critical", options.critical),
+        (bar, !(options.critical && options.critical_hide_bar)),
+        ("#full", options.full),
+        ("#temp", options.hot),
+        ("#not-charging", not_charging),
+        ("#saver", reading.power_saver),
+        ("#mute", reading.muted),
+    ];
+    for (spec, visible) in toggles {
+        hide_unless(tag_stack, &mut attr_map, spec, visible)
+            .map_err(|e| format!("hide_unless failed: {e}"))?;
+    }
+
+    // Turn off the segments above the level.
+    segment_toggle(&mut attr_map, reading.level)
+        .map_err(|e| format!("segment_toggle failed: {e}"))?;
+
+    // Hide the elements whose data-show-if condition is false.
+    show_if(&mut attr_map, &render.vars).map_err(|e| format!("show_if failed: {e}"))?;
+
+    // Compute the attributes given by data-bind-ATTR expressions.
+    bind_attributes(&mut attr_map, &render.vars, options.precision)
+        .map_err(|e| format!("bind_attributes failed: {e}"))?;
+
+    // Apply the config's bindings and rules.
+    for compiled in &render.edits {
+        if !compiled.matcher.matches(tag_stack) {
+            continue;
+        }
+        apply_edit(&mut attr_map, compiled.edit.as_ref(), options.precision)
+            .map_err(|e| format!("in rule for {}: {e}", tag))?;
+    }
+
+    // Set the color that currentColor refers to, on the root element.
+    if let (Some(color), 1) = (&options.color, tag_stack.len()) {
+        attr_map.insert("color".to_string(), format!("#{color}"));
+    }
+    if tag_stack.len() == 1 && !options.css_vars.is_empty() {
+        css_vars(&mut attr_map, &options.css_vars).map_err(|e| format!("css_vars failed: {e}"))?;
+    }
+
+    // Write references in a single style, whichever way they were read.
+    normalize_href(&mut attr_map, options.xlink, tag_stack.len() == 1);
+
+    // Write the modified attributes into the result.
+    for (key, value) in attr_map {
+        tag_out.push_attribute((key.as_str(), value.as_str()));
+    }
+    Ok(tag_out)
+}
+
+fn text_color(
+    tag_stack: &[tag::Tag],
+    attr_map: &mut IndexMap<String, String>,
+    foreground: &str,
+    stylesheet: &css::Stylesheet,
+) -> Result<(), Box<dyn Error>> {
+    let spec = "tspan";
+    if !matcher::new_tag_matcher(spec)?.matches(tag_stack) {
+        return Ok(());
+    }
+
+    let style = attr_map.get("style").map_or("", String::as_str);
+    let mut style_map: IndexMap<String, String> =
+        parse_style_map(style).map_err(|e| format!("in {spec}: {e}"))?;
+
+    set_fill(
+        tag_stack,
+        &mut style_map,
+        &format!("#{foreground}"),
+        stylesheet,
+    );
+
+    let new_style = map_as_style(&style_map);
+    attr_map.insert("style".to_string(), new_style);
+    Ok(())
+}
+
+// battery_fraction adjusts a IndexMap of attributes for the bar,
+// the tag matching the selector, like <rect id="fraction" />.
+// It scales its width from 100% to the percentage of the remaining charge,
+// or its height for a vertical bar.
+// It also changes its color to fill, if the source calls for one.
+fn battery_fraction(
+    tag_stack: &[tag::Tag],
+    attr_map: &mut IndexMap<String, String>,
+    options: &Options,
+    charge: f64,
+    fill: Option<&str>,
+    stylesheet: &css::Stylesheet,
+) -> Result<(), Box<dyn Error>> {
+    let fraction_spec = options.selector.as_str();
+    if !matcher::new_tag_matcher(fraction_spec)?.matches(tag_stack) {
+        return Ok(());
+    }
+
+    let orientation = bar::Orientation::of(attr_map, options.orientation)?;
+    let anchor = bar::Anchor::of(attr_map, options.anchor, orientation)?;
+    bar::scale(
+        attr_map,
+        &tag::path(tag_stack),
+        charge,
+        orientation,
+        anchor,
+        options.precision,
+    )?;
+
+    // Change the color if the level is out of the normal range.
+    if let Some(new_fill) = fill {
+        let style = attr_map.get("style").map_or("", String::as_str);
+        let mut style_map: IndexMap<String, String> =
+            parse_style_map(style).map_err(|e| format!("in {fraction_spec}: {e}"))?;
+
+        // A template colored by a fill attribute is recolored there,
+        // unless the style or a stylesheet overrides it.
+        if attr_map.contains_key("fill")
+            && !style_map.contains_key("fill")
+            && stylesheet.get(tag_stack, "fill").is_none()
+        {
+            log::debug!("changing the fill of {fraction_spec} to {new_fill:?}");
+            attr_map.insert("fill".to_string(), new_fill.to_string());
+            return Ok(());
+        }
+
+        log::debug!(
+            "changing the fill of {fraction_spec} from {:?} to {new_fill:?}",
+            style_map.get("fill").map_or("", String::as_str)
+        );
+        set_fill(tag_stack, &mut style_map, new_fill, stylesheet);
+
+        let new_style = map_as_style(&style_map);
+        attr_map.insert("style".to_string(), new_style);
+    }
+    Ok(())
+}
+
+// battery_health scales the width of a <rect id="health" /> tag
+// to how much of its design capacity the battery still holds,
+// and hides it if the source doesn't say.
+// New batteries can hold more than their design, but the bar stops at full.
+fn battery_health(
+    tag_stack: &[tag::Tag],
+    attr_map: &mut IndexMap<String, String>,
+    health: Option<f64>,
+    precision: usize,
+) -> Result<(), Box<dyn Error>> {
+    let spec = "rect#health";
+    if !matcher::new_tag_matcher(spec)?.matches(tag_stack) {
+        return Ok(());
+    }
+    let Some(health) = health else {
+        return hide(attr_map).map_err(|e| format!("in #health: {e}").into());
+    };
+    let width: f64 = attr_map
+        .get("width")
+        .ok_or("#health had no [width]")?
+        .parse()
+        .map_err(|e| format!("failed to parse #health[width]: {e}"))?;
+    attr_map.insert(
+        "width".to_string(),
+        format_number(width * health.min(1.0), precision),
+    );
+    Ok(())
+}
+
+// set_fill sets the fill in a style map.
+// An inline style outranks the template's stylesheet,
+// except for !important declarations, which it then has to match.
+fn set_fill(
+    tag_stack: &[tag::Tag],
+    style_map: &mut IndexMap<String, String>,
+    fill: &str,
+    stylesheet: &css::Stylesheet,
+) {
+    let important = stylesheet
+        .get(tag_stack, "fill")
+        .is_some_and(|declaration| declaration.important);
+    let fill = match important {
+        true => format!("{fill} !important"),
+        false => fill.to_string(),
+    };
+    style_map.insert("fill".to_string(), fill);
+}
+
+// css_vars sets custom properties in the style attribute,
+// overriding any of the same name.
+fn css_vars(
+    attr_map: &mut IndexMap<String, String>,
+    vars: &[(String, String)],
+) -> Result<(), Box<dyn Error>> {
+    let style = attr_map.get("style").map_or("", String::as_str);
+    let mut style_map = parse_style_map(style)?;
+    for (name, value) in vars {
+        style_map.insert(format!("--{name}"), value.clone());
+    }
+    attr_map.insert("style".to_string(), map_as_style(&style_map));
+    Ok(())
+}
+
+// builtin_texts fills in the text of the elements that show a reading:
+// text#pct and tspan#pct with the percentage, in the format of the options, like 42%,
+// text#time with the time remaining, like 2h 14m,
+// text#watts with the power draw, like 8.3 W, or 8,3 W in German,
+// and text#cycles with the cycle count, when the battery source knows them.
+// Bindings and rules come after these, so they can replace them.
+fn builtin_texts(
+    reading: Reading,
+    locale: &locale::Locale,
+    pct: Option<String>,
+) -> Result<Vec<rule::Compiled>, Box<dyn Error>> {
+    let texts = [
+        ("text#pct", pct.clone()),
+        ("tspan#pct", pct),
+        (
+            "text#time",
+            reading.time_remaining.map(vars::format_duration),
+        ),
+        (
+            "text#watts",
+            reading
+                .power
+                .map(|watts| format!("{} W", locale.number(&format!("{watts:.1}")))),
+        ),
+        (
+            "text#cycles",
+            reading.cycles.map(|cycles| cycles.to_string()),
+        ),
+    ];
+    let mut edits = Vec::new();
+    for (selector, text) in texts {
+        let Some(text) = text else {
+            continue;
+        };
+        edits.push(rule::Compiled {
+            selector: selector.to_string(),
+            matcher: matcher::new_tag_matcher(selector)?,
+            edit: Some(rule::Edit::SetText(text)),
+        });
+    }
+    Ok(edits)
+}
+
+// hide_unless hides the element, if it matches the spec, unless it should be visible,
+// like #temp unless the battery is hot.
+fn hide_unless(
+    tag_stack: &[tag::Tag],
+    attr_map: &mut IndexMap<String, String>,
+    spec: &str,
+    visible: bool,
+) -> Result<(), Box<dyn Error>> {
+    if visible || !matcher::new_tag_matcher(spec)?.matches(tag_stack) {
+        return Ok(());
+    }
+    hide(attr_map).map_err(|e| format!("in {spec}: {e}").into())
+}
+
+// segment_toggle hides segments of a segmented gauge, like the arcs of a Wi-Fi icon.
+// Each segment is marked with data-segment="K/N", meaning the Kth of N segments.
+// The segment stays visible if the level rounds to at least K of N segments.
+fn segment_toggle(
+    attr_map: &mut IndexMap<String, String>,
+    level: f64,
+) -> Result<(), Box<dyn Error>> {
+    let Some(segment) = attr_map.get("data-segment").cloned() else {
+        return Ok(());
+    };
+    let parse_segment = || -> Option<(f64, f64)> {
+        let (k, n) = segment.split_once('/')?;
+        Some((k.trim().parse().ok()?, n.trim().parse().ok()?))
+    };
+    let Some((k, n)) = parse_segment() else {
+        return Err(format!("invalid data-segment {segment:?}, expected K/N").into());
+    };
+    if (level * n).round() < k {
+        hide(attr_map).map_err(|e| format!("in data-segment={segment:?}: {e}"))?;
+    }
+    Ok(())
+}
+
+// show_if hides an element marked with data-show-if="EXPR",
+// unless the expression is true, e.g. data-show-if="charging && charge < 20".
+fn show_if(
+    attr_map: &mut IndexMap<String, String>,
+    vars: &vars::Vars,
+) -> Result<(), Box<dyn Error>> {
+    let Some(condition) = attr_map.get("data-show-if") else {
+        return Ok(());
+    };
+    if !expr::eval(condition, vars)?.truthy() {
+        hide(attr_map)?;
+    }
+    Ok(())
+}
+
+// bind_attributes sets each attribute ATTR given by a data-bind-ATTR expression,
+// e.g. data-bind-width="38.25 * level".
+// Numbers are rounded to the precision.
+fn bind_attributes(
+    attr_map: &mut IndexMap<String, String>,
+    vars: &vars::Vars,
+    precision: usize,
+) -> Result<(), Box<dyn Error>> {
+    let mut bound = Vec::new();
+    for (key, expression) in attr_map.iter() {
+        let Some(attr) = key.strip_prefix("data-bind-") else {
+            continue;
+        };
+        let value = expr::eval(expression, vars).map_err(|e| format!("in {key}: {e}"))?;
+        let value = match value {
+            expr::Value::Number(n) => format_number(n, precision),
+            value => value.to_string(),
+        };
+        bound.push((attr.to_string(), value));
+    }
+    attr_map.extend(bound);
+    Ok(())
+}
+
+// format_number writes a computed number with up to precision decimal places,
+// without trailing zeros, like 19.125 or 12 rather than 12.000000.
+pub fn format_number(value: f64, precision: usize) -> String {
+    let mut s = format!("{value:.precision$}");
+    if s.contains('.') {
+        s.truncate(s.trim_end_matches('0').trim_end_matches('.').len());
+    }
+    match s.as_str() {
+        "-0" => "0".to_string(),
+        _ => s,
+    }
+}
+
+// apply_edit applies the attribute changes of a rule.
+// Text changes are made by the streaming pass instead.
+fn apply_edit(
+    attr_map: &mut IndexMap<String, String>,
+    edit: Option<&rule::Edit>,
+    precision: usize,
+) -> Result<(), Box<dyn Error>> {
+    match edit {
+        Some(rule::Edit::Scale { attr, factor }) => {
+            let value: f64 = attr_map
+                .get(attr)
+                .ok_or_else(|| format!("no [{attr}] to scale"))?
+                .parse()
+                .map_err(|e| format!("failed to parse [{attr}]: {e}"))?;
+            attr_map.insert(attr.clone(), format_number(value * factor, precision));
+        }
+        Some(rule::Edit::Hide) => hide(attr_map)?,
+        Some(rule::Edit::SetAttr { attr, value }) => {
+            attr_map.insert(attr.clone(), value.clone());
+        }
+        // Inline edits are made on the whole document, before the stream.
+        Some(rule::Edit::SetText(_)) | Some(rule::Edit::Inline) | None => {}
+    }
+    Ok(())
+}
+
+// The namespace prefixes of the editor-specific markup that Inkscape saves.
+const EDITOR_PREFIXES: [&str; 2] = ["inkscape", "sodipodi"];
+
+// is_editor_name reports whether a tag or attribute name is in an editor namespace,
+// or declares one, like sodipodi:namedview or xmlns:inkscape.
+fn is_editor_name(name: &[u8]) -> bool {
+    let prefix = match name.strip_prefix(b"xmlns:") {
+        Some(declared) => declared,
+        None => match name.iter().position(|&c| c == b':') {
+            Some(i) => &name[..i],
+            None => return false,
+        },
+    };
+    EDITOR_PREFIXES.iter().any(|p| p.as_bytes() == prefix)
+}
+
+// strip_editor drops the editor attributes and namespace declarations,
+// and the editor properties like -inkscape-font-specification from the style.
+fn strip_editor(attr_map: &mut IndexMap<String, String>) -> Result<(), Box<dyn Error>> {
+    attr_map.retain(|key, _| !is_editor_name(key.as_bytes()));
+    let Some(style) = attr_map.get("style") else {
+        return Ok(());
+    };
+    let mut style_map = parse_style_map(style)?;
+    style_map.retain(|key, _| {
+        !EDITOR_PREFIXES
+            .iter()
+            .any(|p| key.starts_with(&format!("-{p}-")))
+    });
+    attr_map.insert("style".to_string(), map_as_style(&style_map));
+    Ok(())
+}
+
+// normalize_href writes a reference as href, or as xlink:href for SVG 1.1.
+// If a tag has both, the href wins, as in SVG 2.
+// For xlink:href, the root tag declares the xlink namespace.
+fn normalize_href(attr_map: &mut IndexMap<String, String>, xlink: bool, root: bool) {
+    let (from, to) = if xlink {
+        ("href", "xlink:href")
+    } else {
+        ("xlink:href", "href")
+    };
+    if let Some(value) = attr_map.shift_remove(from) {
+        if xlink || !attr_map.contains_key(to) {
+            attr_map.insert(to.to_string(), value);
+        }
+    }
+    if xlink && root {
+        attr_map
+            .entry("xmlns:xlink".to_string())
+            .or_insert_with(|| "http://www.w3.org/1999/xlink".to_string());
+    }
+}
+
+// hide sets display:none in the style attribute.
+fn hide(attr_map: &mut IndexMap<String, String>) -> Result<(), Box<dyn Error>> {
+    let style = attr_map.get("style").map_or("", String::as_str);
+    let mut style_map: IndexMap<String, String> = parse_style_map(style)?;
+    style_map.insert("display".to_string(), "none".to_string());
+    let new_style = map_as_style(&style_map);
+    attr_map.insert("style".to_string(), new_style);
+    Ok(())
+}
+
+fn new_attr_map(attrs: &[Attribute]) -> Result<IndexMap<String, String>, Box<dyn Error>> {
+    let mut attr_map: IndexMap<String, String> = IndexMap::new();
+    // Read the attributes out for modification.
+    for attr in attrs {
+        let key = str::from_utf8(attr.key.into_inner())?.to_string();
+        let value = attr.unescape_value()?.to_string();
+        attr_map.insert(key, value);
+    }
+    Ok(attr_map)
+}
+
+// parse_style_map converts an SVG style attribute into a key-value map.
+fn parse_style_map(style: &str) -> Result<IndexMap<String, String>, Box<dyn Error>> {
+    let mut map = IndexMap::new();
+    for kv in style.split(";") {
+        // Allow empty styles and trailing semicolons.
+        if kv.trim().is_empty() {
+            continue;
+        }
+        let kv: Vec<&str> = kv.trim().splitn(2, ":").collect();
+        if kv.len() != 2 {
+            return Err(format!("failed to parse style kv: {kv:?}").into());
+        }
+        let key = kv[0];
+        let value = kv[1];
+        map.insert(key.to_string(), value.to_string());
+    }
+    Ok(map)
+}
+
+// map_as_style converts a key-value map into an SVG style attribute.
+fn map_as_style(map: &IndexMap<String, String>) -> String {
+    let mut style = String::new();
+    for (k, v) in map {
+        style.push(';');
+        style.push_str(k);
+        style.push(':');
+        style.push_str(v);
+    }
+    if style.is_empty() {
+        return "".to_string();
+    }
+    style.trim_start_matches(';').to_string()
+}
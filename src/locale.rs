@@ -0,0 +1,158 @@
+use std::str::FromStr;
+
+/// How a language writes the numbers put into a template's text,
+/// like 42 % and 8,3 W in German, or ٤٢٪ in Arabic.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Locale {
+    /// The decimal separator, like , in 8,3.
+    pub decimal: char,
+    /// The percent sign, which is ٪ in Arabic script.
+    pub percent_sign: char,
+    /// Whether the percent sign goes before the number, as in Turkish.
+    pub percent_first: bool,
+    /// What goes between the number and the percent sign, like a no-break space.
+    pub percent_space: &'static str,
+    pub digits: Digits,
+}
+
+/// The digits that numbers are written in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Digits {
+    /// 0123456789
+    #[default]
+    Latin,
+    /// ٠١٢٣٤٥٦٧٨٩, as in Arabic.
+    ArabicIndic,
+    /// ۰۱۲۳۴۵۶۷۸۹, as in Persian and Urdu.
+    Persian,
+}
+
+// The default is the C locale, which writes numbers as battery-icon always has, like 42%.
+impl Default for Locale {
+    fn default() -> Locale {
+        Locale {
+            decimal: '.',
+            percent_sign: '%',
+            percent_first: false,
+            percent_space: "",
+            digits: Digits::Latin,
+        }
+    }
+}
+
+// A locale is parsed from a language tag, like de, fr-CA or de_DE.UTF-8,
+// by its language alone, besides a Unicode numbering system like ar-u-nu-arab,
+// which picks the digits: latn, arab or arabext.
+// Arabic and Persian are written in their own digits, unless the tag says latn.
+// Languages that aren't known are written like the C locale.
+impl FromStr for Locale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Locale, String> {
+        let tag = s.split(['.', '@']).next().unwrap_or(s).replace('_', "-");
+        let subtags: Vec<String> = tag.split('-').map(str::to_ascii_lowercase).collect();
+        let language = subtags[0].as_str();
+        if language.is_empty() || !language.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(format!(
+                "expected a language tag like de or fr-CA, got {s:?}"
+            ));
+        }
+        let c = Locale::default();
+        // Between the number and the percent sign, a no-break space, or a narrow one in French.
+        let (nbsp, narrow_nbsp) = ("\u{a0}", "\u{202f}");
+        let mut locale = match language {
+            "de" | "es" | "sv" | "nb" | "nn" | "no" | "fi" | "da" | "ru" | "uk" | "cs" | "sk"
+            | "bg" | "el" => Locale {
+                decimal: ',',
+                percent_space: nbsp,
+                ..c
+            },
+            "fr" => Locale {
+                decimal: ',',
+                percent_space: narrow_nbsp,
+                ..c
+            },
+            "it" | "pt" | "nl" | "pl" | "ro" | "hu" | "hr" | "sl" | "sr" | "id" | "vi" => {
+                Locale { decimal: ',', ..c }
+            }
+            "tr" => Locale {
+                decimal: ',',
+                percent_first: true,
+                ..c
+            },
+            "ar" => Locale {
+                decimal: '٫',
+                percent_sign: '٪',
+                digits: Digits::ArabicIndic,
+                ..c
+            },
+            "fa" | "ur" => Locale {
+                decimal: '٫',
+                percent_sign: '٪',
+                digits: Digits::Persian,
+                ..c
+            },
+            _ => c,
+        };
+        if let Some(i) = subtags.iter().position(|subtag| subtag == "nu") {
+            locale.digits = match subtags.get(i + 1).map(String::as_str) {
+                Some("latn") => Digits::Latin,
+                Some("arab") => Digits::ArabicIndic,
+                Some("arabext") => Digits::Persian,
+                numbering => {
+                    return Err(format!(
+                        "expected the numbering latn, arab or arabext in {s:?}, got {numbering:?}"
+                    ))
+                }
+            };
+        }
+        // Latin digits take the Latin signs, as in ar-u-nu-latn.
+        if locale.digits == Digits::Latin && locale.percent_sign == '٪' {
+            locale.decimal = '.';
+            locale.percent_sign = '%';
+        }
+        Ok(locale)
+    }
+}
+
+impl Locale {
+    // from_env reads the locale of numbers from LC_ALL, LC_NUMERIC or LANG,
+    // like de_DE.UTF-8, or else returns the C locale.
+    pub fn from_env() -> Locale {
+        ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .find_map(|var| std::env::var(var).ok().filter(|value| !value.is_empty()))
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default()
+    }
+
+    // number writes a number, formatted like 8.3, in the digits and decimal separator of the locale.
+    pub fn number(&self, number: &str) -> String {
+        number
+            .chars()
+            .map(|c| match (c, c.to_digit(10)) {
+                ('.', _) => self.decimal,
+                (_, Some(digit)) => self.digit(digit),
+                _ => c,
+            })
+            .collect()
+    }
+
+    // percent writes a percentage, formatted like 42, with the percent sign of the locale.
+    pub fn percent(&self, number: &str) -> String {
+        let (number, space, sign) = (self.number(number), self.percent_space, self.percent_sign);
+        match self.percent_first {
+            true => format!("{sign}{space}{number}"),
+            false => format!("{number}{space}{sign}"),
+        }
+    }
+
+    fn digit(&self, digit: u32) -> char {
+        let zero = match self.digits {
+            Digits::Latin => '0',
+            Digits::ArabicIndic => '\u{660}',
+            Digits::Persian => '\u{6f0}',
+        };
+        char::from_u32(zero as u32 + digit).unwrap_or(zero)
+    }
+}
@@ -0,0 +1,95 @@
+use std::error::Error;
+use std::str::FromStr;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+// The environment variable that sets the level per module,
+// like battery_icon::battery=debug,info.
+const LOG_VAR: &str = "BATTERY_ICON_LOG";
+
+// Logger writes the log to stderr, each line starting with battery-icon:,
+// and the level and module too for the debugging levels.
+struct Logger {
+    level: LevelFilter,
+    // The levels of the modules in BATTERY_ICON_LOG, by module path.
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Logger {
+    // level_of returns the level of the module that most closely contains the target.
+    fn level_of(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|(module, _)| target == module || target.starts_with(&format!("{module}::")))
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_of(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Error | Level::Warn | Level::Info => {
+                eprintln!("battery-icon: {}", record.args())
+            }
+            level => eprintln!(
+                "battery-icon: {} {}: {}",
+                level.as_str().to_lowercase(),
+                record.target(),
+                record.args()
+            ),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+// init logs warnings and errors, or more with each -v, down to trace,
+// or less with each -q, down to nothing.
+// BATTERY_ICON_LOG can set the level of each module, like battery_icon::battery=debug,
+// and of the rest without a module, like warn.
+pub fn init(verbose: u8, quiet: u8) -> Result<(), Box<dyn Error>> {
+    let levels = [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ];
+    let i = (2 + verbose as usize).saturating_sub(quiet as usize);
+    let mut logger = Logger {
+        level: levels[i.min(levels.len() - 1)],
+        modules: Vec::new(),
+    };
+    if let Ok(spec) = std::env::var(LOG_VAR) {
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let parse = |level: &str| {
+                LevelFilter::from_str(level)
+                    .map_err(|e| format!("in {LOG_VAR}, bad level {level:?}: {e}"))
+            };
+            match directive.split_once('=') {
+                Some((module, level)) => logger
+                    .modules
+                    .push((module.trim().replace('-', "_"), parse(level.trim())?)),
+                None => logger.level = parse(directive)?,
+            }
+        }
+    }
+    let max = logger
+        .modules
+        .iter()
+        .map(|(_, level)| *level)
+        .fold(logger.level, Ord::max);
+    log::set_boxed_logger(Box::new(logger))
+        .map_err(|e| format!("failed to set up the log: {e}"))?;
+    log::set_max_level(max);
+    Ok(())
+}
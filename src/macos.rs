@@ -0,0 +1,76 @@
+use std::error::Error;
+use std::time::Duration;
+
+use crate::battery::BatterySource;
+use crate::source::{command_output, BatteryState, Reading};
+
+// Pmset reads the battery from `pmset -g batt`,
+// which reports what IOKit's power sources do.
+pub struct Pmset {
+    pub battery: Option<String>,
+}
+
+impl BatterySource for Pmset {
+    fn name(&self) -> &'static str {
+        "pmset"
+    }
+
+    fn read(&self) -> Result<Reading, Box<dyn Error>> {
+        let output = command_output("pmset", &["-g", "batt"])?;
+        parse_pmset(&output, self.battery.as_deref())
+    }
+}
+
+// parse_pmset reads the line of the named battery, or of the first one:
+//
+//	Now drawing from 'Battery Power'
+//	 -InternalBattery-0 (id=4653155)	72%; discharging; 4:01 remaining present: true
+//
+// The state may also be charging, charged, finishing charge or AC attached,
+// and the time may be (no estimate).
+pub fn parse_pmset(output: &str, battery: Option<&str>) -> Result<Reading, Box<dyn Error>> {
+    let line = output
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix('-'))
+        .find(|line| battery.is_none_or(|battery| line.starts_with(battery)))
+        .ok_or("no battery in pmset")?;
+    if line.contains("present: false") {
+        return Err(format!("{line:?} is not present").into());
+    }
+    let (_, status) = line
+        .split_once('\t')
+        .ok_or_else(|| format!("unexpected pmset line {line:?}"))?;
+    let mut fields = status.split(';').map(str::trim);
+    let percent = fields.next().unwrap_or_default();
+    let percent: f64 = percent
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|e| format!("failed to parse percentage {percent:?}: {e}"))?;
+    let state = fields.next().unwrap_or_default();
+    let time_remaining = fields
+        .next()
+        .and_then(|time| time.strip_suffix(" present: true").or(Some(time)))
+        .and_then(|time| time.strip_suffix(" remaining"))
+        .and_then(parse_hours_minutes)
+        // A charged battery has 0:00 remaining.
+        .filter(|time| !time.is_zero());
+    Ok(Reading {
+        level: percent / 100.0,
+        state: match state {
+            "charging" | "finishing charge" => BatteryState::Charging,
+            "discharging" => BatteryState::Discharging,
+            "charged" => BatteryState::Full,
+            "AC attached" => BatteryState::NotCharging,
+            _ => BatteryState::Unknown,
+        },
+        time_remaining,
+        ..Default::default()
+    })
+}
+
+// parse_hours_minutes reads a time like 4:01.
+fn parse_hours_minutes(time: &str) -> Option<Duration> {
+    let (hours, minutes) = time.split_once(':')?;
+    let minutes = hours.parse::<u64>().ok()? * 60 + minutes.parse::<u64>().ok()?;
+    Some(Duration::from_secs(minutes * 60))
+}
@@ -1,299 +1,1182 @@
-use std::collections::HashMap;
 use std::error::Error;
-use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::ffi::OsString;
+use std::io::{BufRead, Write};
+use std::process::ExitCode;
+use std::time::Duration;
 
-use clap::Parser;
-use quick_xml::events::attributes::Attribute;
-use quick_xml::events::{BytesStart, Event};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use quick_xml::reader::Reader;
 use quick_xml::writer::Writer;
 
-mod matcher;
-mod tag;
+use battery_icon::bar;
+use battery_icon::battery::{self, Batteries, BatterySource};
+use battery_icon::locale::Locale;
+use battery_icon::ramp::{ColorSpace, Ramp};
+use battery_icon::source::{self, BatteryState, LevelColors, Reading, Source};
+use battery_icon::{
+    config, data, files, matcher, mqtt, overlay, raster, read_events, rule, templates, Options,
+    Render, Report,
+};
 
-use matcher::StackMatcher;
+mod archive;
+mod batch;
+mod check;
+mod critical;
+mod doctor;
+mod eww;
+mod exit;
+mod init;
+mod journal;
+mod logging;
+mod man;
+mod preview;
+mod profile;
+mod query;
+mod report;
+mod selftest;
+mod service;
+mod status;
+mod sweep;
+mod tui;
+mod watch;
+
+use exit::{Code, OrExit};
 
 /// Generates a battery icon with charging status.
-#[derive(Parser, Debug)]
+#[derive(Parser, Clone, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// Path to the base SVG.
+    /// Path to the base SVG, or - to read it from stdin,
+    /// or a built-in template: builtin:horizontal, builtin:vertical, builtin:circle or builtin:minimal.
+    /// It is left out for --template.
+    #[arg(
+        required_unless_present = "template",
+        default_value = files::STDIO,
+        hide_default_value = true
+    )]
     svg: std::path::PathBuf,
-    /// Path to the result image.
+    /// A built-in template to render instead of an SVG file, like builtin:circle or circle,
+    /// with the first path after it as the OUTPUT.
+    #[arg(long, value_name = "NAME", value_parser = parse_template)]
+    template: Option<String>,
+    /// Path to the result image, or - to write the SVG to stdout.
+    /// It can be left out for --output, --in-place or --check.
+    #[arg(default_value = files::STDIO, hide_default_value = true)]
     output: std::path::PathBuf,
+    /// Another path to write the result to, besides or instead of OUTPUT,
+    /// like tray.png@24 for a PNG 24 pixels wide. The template is rendered once for them all.
+    #[arg(
+        long = "output",
+        value_name = "PATH[@WIDTH]",
+        alias = "outputs",
+        conflicts_with_all = ["in_place", "profile"]
+    )]
+    outputs: Vec<files::Target>,
+
+    /// Charge from 0 to 1. Without it, the battery is read like --source auto,
+    /// and shown full if there is none.
+    #[arg(short, long)]
+    level: Option<f64>,
+
+    /// Charge percentage from 0 to 100, instead of --level,
+    /// or - to read a line of stdin for each render, like 40,
+    /// or {"percent": 40, "state": "charging"}.
+    #[arg(long, value_name = "PERCENT|-", value_parser = parse_charge, conflicts_with = "level")]
+    charge: Option<Charge>,
+
+    /// What to do with a charge below 0 or above full, from the source or the command line:
+    /// draw it at the nearest end, with a warning, or fail.
+    #[arg(long = "clamp", value_name = "POLICY", value_enum, default_value_t = OutOfRange::Clamp)]
+    out_of_range: OutOfRange,
+
+    /// Where to read the level from, instead of --level.
+    /// file:PATH is short for --source file --file PATH,
+    /// bluez:MAC for --source bluez --device MAC,
+    /// mock:SETTINGS for --source mock --mock SETTINGS,
+    /// and a URL for --source http or mqtt --url URL.
+    #[arg(short, long, value_parser = source::SourceParser, default_value = "level")]
+    source: Source,
+
+    #[command(flatten)]
+    source_args: source::SourceArgs,
+
+    #[command(flatten)]
+    raster_args: raster::RasterArgs,
+
+    /// Write the set of files a platform needs into the output directory,
+    /// instead of a single icon.
+    /// The name of a [profile.NAME] in the config selects its defaults instead.
+    #[arg(long, value_enum)]
+    profile: Option<profile::Profile>,
+
+    /// Overwrite the base SVG with the result, instead of writing to an output path,
+    /// through a temporary file that replaces it once complete.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["output", "profile"])]
+    in_place: bool,
+
+    /// Print a summary of each render to stdout, like the charge,
+    /// how many elements the rules matched and changed, and the rules that matched none.
+    #[arg(long, value_enum, conflicts_with = "eww")]
+    report: Option<report::Format>,
+
+    /// Log more, like the backend in use with -v, or debugging with -vv and -vvv.
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Log less: only errors with -q, or nothing with -qq.
+    #[arg(short, long, action = clap::ArgAction::Count, global = true, conflicts_with = "verbose")]
+    quiet: u8,
 
-    #[arg(short, long, default_value_t = 1.0)]
-    level: f64,
+    /// Render without writing anything, and print a unified diff of the attributes
+    /// and elements that would change, exiting with 1 if any would.
+    #[arg(long, default_value_t = false, conflicts_with = "profile")]
+    check: bool,
+
+    /// Keep running, and re-render the output when the level changes.
+    #[arg(short, long, default_value_t = false)]
+    watch: bool,
+
+    /// Seconds between checks of the source in --watch mode.
+    #[arg(long, default_value_t = 5.0)]
+    interval: f64,
+
+    #[command(flatten)]
+    critical: critical::CriticalArgs,
+
+    #[command(flatten)]
+    journal: journal::JournalArgs,
 
     #[arg(short, long, default_value_t = false)]
     charging: bool,
 
+    /// What the battery is doing, instead of what the source says,
+    /// for the {{state}} placeholder. --charging is short for --state charging.
+    #[arg(long, value_enum)]
+    state: Option<BatteryState>,
+
     #[arg(short, long, default_value_t = String::from("000000"))]
     foreground: String,
+
+    /// Color that currentColor resolves to, as a hex code like --foreground.
+    /// It is set as the color attribute of the root element.
+    #[arg(long)]
+    color: Option<String>,
+
+    /// Battery temperature in degrees Celsius above which to show #temp.
+    #[arg(long, value_name = "CELSIUS", default_value_t = 45.0)]
+    hot_temperature: f64,
+
+    /// Color for the bar while the battery is hotter than --hot-temperature,
+    /// as a hex code or a color name like --color-low.
+    #[arg(long, value_parser = parse_color)]
+    hot_color: Option<String>,
+
+    /// Charge percentage at or above which the battery is full,
+    /// and drawn with #full shown and the bar in --color-full.
+    #[arg(long, value_name = "PERCENT", default_value_t = 100.0)]
+    full_charge: f64,
+
+    /// Color for the bar while the level is normal, as a hex code or a color name
+    /// like --color-low. The template's color is kept by default.
+    #[arg(long, value_parser = parse_color)]
+    color_normal: Option<String>,
+
+    /// Color for the bar while the level is low, as a hex code like ff8000
+    /// or a CSS color name like orange.
+    #[arg(long, value_parser = parse_color, default_value = "ff8000")]
+    color_low: String,
+
+    /// Color for the bar while the level is critical,
+    /// or the battery is below --critical-charge.
+    #[arg(long, alias = "critical-color", value_parser = parse_color, default_value = "ff0000")]
+    color_critical: String,
+
+    /// Color for the bar while charging, whatever the level.
+    /// The level's color is used by default.
+    #[arg(long, value_parser = parse_color)]
+    color_charging: Option<String>,
+
+    /// Color for the bar while the battery is full, by --full-charge,
+    /// so that it stands apart from one that is almost full.
+    /// The level's color is used by default.
+    #[arg(long, value_parser = parse_color)]
+    color_full: Option<String>,
+
+    /// Colors to blend the bar between by the level, instead of the steps of
+    /// --color-normal, --color-low and --color-critical, like red,orange,green.
+    /// Each can be placed at a percentage, like red@15,orange@30,green@100.
+    #[arg(long, value_name = "COLORS", value_parser = clap::value_parser!(Ramp))]
+    color_ramp: Option<Ramp>,
+
+    /// How --color-ramp blends its colors. hsl keeps the midpoints bright,
+    /// where rgb can muddy them.
+    #[arg(long, value_enum, default_value_t = ColorSpace::Rgb)]
+    color_space: ColorSpace,
+
+    /// Selector of the bar, whose width is scaled to the level and which is recolored,
+    /// for templates that don't draw it as rect#fraction, like 'rect#level' or 'g#bar rect'.
+    #[arg(long, default_value = DEFAULT_SELECTOR, value_parser = parse_selector)]
+    selector: String,
+
+    /// Which way the bar fills up: horizontal, from the left by its width,
+    /// or vertical, from the bottom by its height.
+    /// A data-orient attribute on the bar overrides it.
+    #[arg(long, value_enum, default_value_t = bar::Orientation::Horizontal)]
+    orientation: bar::Orientation,
+
+    /// The edge of the bar that stays in place as it empties:
+    /// left or right, or top or bottom if it is vertical, or center.
+    /// It is the left, or the bottom, by default.
+    /// A data-anchor attribute on the bar overrides it.
+    #[arg(long, value_enum)]
+    anchor: Option<bar::Anchor>,
+
+    /// The text to put in a <text id="pct"> or <tspan id="pct">, with placeholders,
+    /// like '{{charge}} %'. The percentage of the --locale by default, like 42%.
+    /// An empty format leaves the text of the template as it is.
+    #[arg(long, value_name = "FORMAT", default_value = "{{percent}}")]
+    pct_format: String,
+
+    /// A CSS custom property to set on the root element, like charge-color=#22aa44,
+    /// for templates styled with var(--charge-color),
+    /// and the value of its {{charge-color}} placeholders.
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+    vars: Vec<(String, String)>,
+
+    /// Language to write the numbers of {{percent}} and text#watts in,
+    /// like de for 42 % and 8,3 W, or ar for ٤٢٪,
+    /// or auto to follow LC_ALL, LC_NUMERIC or LANG.
+    #[arg(long, value_name = "TAG|auto", value_parser = parse_locale, default_value = "C", hide_default_value = true)]
+    locale: Locale,
+
+    /// Decimal places to round the computed numbers of the SVG to,
+    /// like the bar's width or a data-bind-ATTR value, leaving off trailing zeros.
+    #[arg(long, value_name = "PLACES", default_value_t = 6, value_parser = clap::value_parser!(u8).range(0..=15))]
+    precision: u8,
+
+    /// Path to a TOML config file.
+    #[arg(long)]
+    config: Option<std::path::PathBuf>,
+
+    /// Path to a JSON object of values to bind into the template,
+    /// according to the [[bind]] rules of the config.
+    #[arg(long)]
+    data: Option<std::path::PathBuf>,
+
+    /// A rule to apply after those of the config,
+    /// like 'rect#fraction width*=0.9*level' or 'text#pct: text = "{{charge}}%"'.
+    /// Repeat it to apply several.
+    #[arg(short, long, visible_alias = "rule")]
+    transform: Vec<rule::Rule>,
+
+    /// An SVG to draw on top of the icon, like a badge.
+    /// With a selector, like badge.svg:#bolt, it goes just before
+    /// the first element matching it instead, for the icon to draw over it.
+    #[arg(long, value_name = "SVG[:SELECTOR]")]
+    overlay: Vec<overlay::Spec>,
+
+    /// Drop the Inkscape and Sodipodi elements, attributes and namespaces,
+    /// leaving only standard SVG.
+    #[arg(long, default_value_t = false)]
+    strip_editor: bool,
+
+    /// Fail at the first flaw in the template's XML, with its position,
+    /// rather than mending those that are easy to, like </Rect> closing a <rect>,
+    /// with a warning.
+    #[arg(long, default_value_t = false)]
+    strict: bool,
+
+    /// Write references as legacy xlink:href instead of href,
+    /// for renderers that only support SVG 1.1.
+    #[arg(long, default_value_t = false)]
+    xlink: bool,
+
+    /// Print each element of the template to stderr,
+    /// with whether each selector of the config and --transform matched it,
+    /// and why not.
+    #[arg(long, default_value_t = false)]
+    explain: bool,
+
+    /// After each render, print the icon path and reading
+    /// as a line of JSON, for an eww deflisten variable.
+    #[arg(long, default_value_t = false)]
+    eww: bool,
 }
 
-impl Args {
-    fn input(&self) -> Result<Reader<BufReader<File>>, Box<dyn Error>> {
-        let f = File::open(&self.svg)
-            .map_err(|e| format!("SVG file {:?} should be readable: {e}", &self.svg))?;
-        let input = BufReader::new(f);
-        let reader = Reader::from_reader(input);
-        Ok(reader)
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Render the template once, like without a subcommand.
+    Render(Args),
+    /// Keep rendering the template as the level changes, like --watch.
+    Watch(Args),
+    /// Print the reading of a source, without rendering anything.
+    Status(status::StatusArgs),
+    /// Check the battery backends, the template and the output directory,
+    /// and report what is wrong.
+    Doctor(doctor::DoctorArgs),
+    /// Render the template at a range of charges, charging and not,
+    /// in light and dark themes, to check how it looks.
+    Selftest(selftest::SelftestArgs),
+    /// List the elements of a template matching a selector,
+    /// with the values of their attributes.
+    Query(query::QueryArgs),
+    /// Preview the template in the terminal,
+    /// with keys to change the charge and charging state.
+    Tui(tui::TuiArgs),
+    /// Show the template at several sizes in a web page,
+    /// optionally served live as it changes.
+    Preview(preview::PreviewArgs),
+    /// Print a systemd unit, launchd agent or scheduled task
+    /// that runs --watch mode at login.
+    Service(service::ServiceArgs),
+    /// Render the template at evenly spaced charges, charging and not,
+    /// to see how it looks across the whole range.
+    Sweep(sweep::SweepArgs),
+    /// Render every step of the charge in each state into a directory,
+    /// for linking to the icon of the moment instead of rendering it.
+    Batch(batch::BatchArgs),
+    /// Print the man page, generated from these options.
+    Man(man::ManArgs),
+    /// Write a starter SVG, with the ids and attributes of a template, to design from.
+    Init(init::InitArgs),
+}
+
+// cli accepts either the Args of a render, or a subcommand.
+// The two are parsed separately, since the SVG and output paths
+// are only required without a subcommand.
+// The render and watch subcommands take the same Args,
+// for a clearer command line than the flat flags.
+fn cli() -> clap::Command {
+    let mut command = Command::augment_subcommands(with_env(Args::command()))
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true);
+    for name in ["render", "watch", "status"] {
+        command = command.mut_subcommand(name, with_env);
     }
+    command
+}
 
-    fn output(&self) -> Result<BufWriter<File>, Box<dyn Error>> {
-        let f = File::create(&self.output)
-            .map_err(|e| format!("output file {:?} should be writable: {e}", &self.output))?;
-        let output = BufWriter::new(f);
-        Ok(output)
+// with_env lets an environment variable named after each option of a command
+// give it a value when the command line doesn't,
+// like BATTERY_ICON_COLOR_LOW for --color-low, or BATTERY_ICON_SVG for the SVG path.
+// They override the config's defaults.
+// An option with the name of a positional argument goes by its field instead,
+// like BATTERY_ICON_OUTPUTS for --output.
+// The -v and -q counts are left out, since BATTERY_ICON_LOG sets the levels.
+fn with_env(mut command: clap::Command) -> clap::Command {
+    command = pin_positionals(command);
+    let positionals: Vec<_> = command.get_positionals().map(|arg| arg.get_id()).collect();
+    let vars: Vec<_> = command
+        .get_arguments()
+        .filter(|arg| !matches!(arg.get_action(), clap::ArgAction::Count))
+        .filter_map(|arg| {
+            let name = match arg.get_long() {
+                Some(long) if !positionals.iter().any(|id| *id == long) => long.to_string(),
+                Some(_) => arg.get_id().to_string(),
+                None if arg.is_positional() => arg.get_id().to_string(),
+                None => return None,
+            };
+            Some((arg.get_id().clone(), env_var(&name)))
+        })
+        .collect();
+    for (id, var) in vars {
+        command = command.mut_arg(id, |arg| arg.env(var).hide_env(true));
     }
+    command
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
-    // eprintln!("Args: {:?}", args);
+// env_var names the environment variable of an option by its long name.
+fn env_var(name: &str) -> String {
+    format!("BATTERY_ICON_{}", name.to_uppercase().replace('-', "_"))
+}
 
-    let mut reader = args.input()?;
-    let out_file = args.output()?;
-    let mut writer = Writer::new(out_file);
-    let mut buf: Vec<u8> = Vec::new();
-    let mut tag_stack: Vec<tag::Tag> = Vec::new();
+// pin_positionals gives the positional arguments of a command their places,
+// since changing an argument moves it to the end.
+fn pin_positionals(mut command: clap::Command) -> clap::Command {
+    let positionals: Vec<_> = command
+        .get_positionals()
+        .map(|arg| arg.get_id().clone())
+        .collect();
+    for (i, id) in positionals.into_iter().enumerate() {
+        command = command.mut_arg(id, |arg| arg.index(i + 1));
+    }
+    command
+}
 
-    loop {
-        let event = reader
-            .read_event_into(&mut buf)
-            .map_err(|e| format!("error at position {}: {e}", reader.error_position()))?;
-        match event {
-            Event::Eof => break,
-
-            Event::Empty(e) => {
-                // For the sake of the matcher, push the current tag onto the stack.
-                tag_stack.push(tag::Tag::new(&e)?);
-
-                // If any rule matches, modify the attributes and return the resulting tag.
-                let new_tag = process_attributes(&tag_stack, &e, &args).map_err(|e| {
-                    format!("failed to process attributes of self-closing tag: {e}")
-                })?;
-
-                // Write the modified elem back into the document.
-                writer
-                    .write_event(Event::Empty(new_tag))
-                    .map_err(|e| format!("failed to write self-closing tag: {e}"))?;
-
-                // Pop the current tag again, since Empty tags have no children.
-                tag_stack
-                    .pop()
-                    .ok_or_else(|| format!("unexpected error while popping a self-closing tag"))?;
+// with_config_defaults makes the [defaults] of the config file
+// the default values of the options, for the flags to override.
+// The config is the one given with --config or its variable, or else the one at config::default_path,
+// if there is one, which then also applies as though given with --config.
+// A --profile, or its variable, naming a [profile.NAME] of the config adds its defaults over the others,
+// and is taken out of the command line it returns for parsing.
+// Any other --profile is one of the sets of icons in profile.rs.
+fn with_config_defaults(
+    mut command: clap::Command,
+) -> Result<(clap::Command, Vec<OsString>), Box<dyn Error>> {
+    let mut argv: Vec<_> = std::env::args_os().collect();
+    let flag = find_flag(&argv, "--config")
+        .map(|(_, path)| path)
+        .or_else(|| std::env::var_os(env_var("config")).filter(|path| !path.is_empty()))
+        .map(std::path::PathBuf::from);
+    let (path, is_default) = match flag {
+        Some(path) => (path, false),
+        None => match config::default_path() {
+            Some(path) if path.exists() => (path, true),
+            _ => return Ok((command, argv)),
+        },
+    };
+    let config = config::load(&path)?;
+    let mut sections = vec![("[defaults]".to_string(), &config.defaults)];
+    let profile = find_flag(&argv, "--profile")
+        .map(|(range, name)| (Some(range), name))
+        .or_else(|| Some((None, std::env::var_os(env_var("profile"))?)));
+    // Once the profile of the variable is found in the config,
+    // the option of --profile no longer takes it.
+    let mut unset_profile_env = false;
+    if let Some((range, name)) = profile {
+        let name = name.to_string_lossy().into_owned();
+        match config.profile.get(&name) {
+            Some(defaults) => {
+                sections.push((format!("[profile.{name}]"), defaults));
+                match range {
+                    Some(range) => drop(argv.drain(range)),
+                    None => unset_profile_env = true,
+                }
+            }
+            None if profile::Profile::from_str(&name, false).is_err() => {
+                let names: Vec<&str> = config.profile.keys().map(String::as_str).collect();
+                return Err(format!(
+                    "in config {path:?}: no [profile.{name}], only {}",
+                    names.join(", ")
+                )
+                .into());
             }
+            None => {}
+        }
+    }
+    let apply = |command| -> Result<clap::Command, Box<dyn Error>> {
+        let command = apply_defaults(command, &sections, &path, is_default)?;
+        Ok(match unset_profile_env {
+            true => command.mut_arg("profile", |arg| arg.env(None)),
+            false => command,
+        })
+    };
+    // The render and watch subcommands take the same defaults.
+    for name in ["render", "watch"] {
+        if let Some(subcommand) = command.find_subcommand(name).cloned() {
+            let subcommand = apply(subcommand)?;
+            command = command.mut_subcommand(name, |_| subcommand);
+        }
+    }
+    Ok((apply(command)?, argv))
+}
 
-            Event::Start(e) => {
-                // Push a tag onto the stack, which might have children.
-                tag_stack.push(tag::Tag::new(&e)?);
-                // eprintln!(">> {}", stack.join(">"));
+// find_flag finds a flag given as --flag VALUE or --flag=VALUE,
+// before any -- that ends the options,
+// and returns the arguments it takes up, along with its value.
+fn find_flag(argv: &[OsString], flag: &str) -> Option<(std::ops::Range<usize>, OsString)> {
+    let end = argv
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(argv.len());
+    argv[..end].iter().enumerate().find_map(|(i, arg)| {
+        let arg = arg.to_str()?;
+        match arg.strip_prefix(flag) {
+            Some("") => argv.get(i + 1).map(|value| (i..i + 2, value.clone())),
+            Some(value) => value
+                .strip_prefix('=')
+                .map(|value| (i..i + 1, value.into())),
+            None => None,
+        }
+    })
+}
 
-                // If any rule matches, modify the attributes and return the resulting tag.
-                let new_tag = process_attributes(&tag_stack, &e, &args)
-                    .map_err(|e| format!("failed to process attributes of Start tag: {e}"))?;
+// apply_defaults sets the defaults of the sections of the config at path on the Args of a command,
+// each overriding those before it,
+// and makes it the default --config if it was found at the default path.
+fn apply_defaults(
+    mut command: clap::Command,
+    sections: &[(String, &toml::Table)],
+    path: &std::path::Path,
+    is_default: bool,
+) -> Result<clap::Command, Box<dyn Error>> {
+    command = pin_positionals(command);
+    if is_default {
+        let default = path.to_string_lossy().into_owned();
+        command = command.mut_arg("config", |arg| arg.default_value(default));
+    }
+    for (section, defaults) in sections {
+        command = apply_section(command, section, defaults, path)?;
+    }
+    Ok(command)
+}
 
-                // Write the modified elem back into the document.
-                writer
-                    .write_event(Event::Start(new_tag))
-                    .map_err(|e| format!("failed to write start tag: {e}"))?;
-            }
+// apply_section sets the defaults of one section of the config on a command.
+fn apply_section(
+    mut command: clap::Command,
+    section: &str,
+    defaults: &toml::Table,
+    path: &std::path::Path,
+) -> Result<clap::Command, Box<dyn Error>> {
+    for (name, value) in defaults {
+        // The positional arguments come first, so that output is OUTPUT,
+        // and outputs, the alias of --output, is the list.
+        let id = command
+            .get_positionals()
+            .chain(command.get_arguments())
+            .find(|arg| {
+                (arg.is_positional() && arg.get_id() == name.as_str())
+                    || arg.get_long() == Some(name.as_str())
+                    || arg
+                        .get_all_aliases()
+                        .is_some_and(|aliases| aliases.contains(&name.as_str()))
+            })
+            .map(|arg| arg.get_id().clone())
+            .ok_or_else(|| format!("in config {path:?}: no option --{name} for {section}"))?;
+        let values = match value {
+            toml::Value::Array(items) => items.iter().map(default_value).collect(),
+            value => vec![default_value(value)],
+        };
+        // A default also fills in a required argument, like the SVG path.
+        command = command.mut_arg(id, |arg| arg.default_values(values).required(false));
+    }
+    Ok(command)
+}
 
-            Event::End(e) => {
-                // eprintln!("<< {}", stack.join(">"));
-
-                // Pop the tag off of the stack,
-                // and verify that it matches the start tag.
-                let Some(last_tag) = tag_stack.pop() else {
-                    return Err("unexpected close tag".into());
-                };
-                let tag = tag::Tag::new(&e)?;
-                if tag.name != last_tag.name {
-                    return Err(format!(
-                        "unexpected {:?} close tag, current tag is {:?}",
-                        tag.name, last_tag.name,
-                    )
-                    .into());
-                }
-                writer
-                    .write_event(Event::End(e))
-                    .map_err(|e| format!("failed to write end tag: {e}"))?;
-            }
+// default_value writes a value of the config's [defaults] as on the command line,
+// with a leading ~/ for the home directory.
+fn default_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => match (s.strip_prefix("~/"), std::env::var("HOME")) {
+            (Some(rest), Ok(home)) => format!("{home}/{rest}"),
+            _ => s.clone(),
+        },
+        value => value.to_string(),
+    }
+}
 
-            e => writer
-                .write_event(e)
-                .map_err(|e| format!("failed to write other element: {e}"))?,
+impl Args {
+    fn input(&self) -> Result<Reader<Box<dyn BufRead>>, Box<dyn Error>> {
+        let input = files::open_input(&self.svg)?;
+        Ok(battery_icon::xml_reader(input, self.strict))
+    }
+
+    fn config(&self) -> Result<config::Config, Box<dyn Error>> {
+        match &self.config {
+            Some(path) => config::load(path),
+            None => Ok(config::Config::default()),
         }
-        buf.clear();
     }
-    Ok(())
+
+    fn data(&self) -> Result<data::Data, Box<dyn Error>> {
+        match &self.data {
+            Some(path) => data::load(path),
+            None => Ok(data::Data::new()),
+        }
+    }
+
+    fn options(&self, reading: Reading) -> Options {
+        Options {
+            source: self.source,
+            charging: self.is_charging(reading),
+            foreground: self.foreground.clone(),
+            color: self.color.clone(),
+            css_vars: self.vars.clone(),
+            locale: self.locale.clone(),
+            precision: self.precision.into(),
+            strip_editor: self.strip_editor,
+            xlink: self.xlink,
+            explain: self.explain,
+            hot: reading
+                .temperature
+                .is_some_and(|degrees| degrees > self.hot_temperature),
+            hot_color: self.hot_color.clone(),
+            critical: critical::is_critical(
+                &self.critical,
+                reading.level,
+                self.is_charging(reading),
+            ),
+            full: reading.level * 100.0 >= self.full_charge,
+            colors: LevelColors {
+                normal: self.color_normal.clone(),
+                low: self.color_low.clone(),
+                critical: self.color_critical.clone(),
+                charging: self.color_charging.clone(),
+                full: self.color_full.clone(),
+                ramp: self.color_ramp.clone().map(|ramp| Ramp {
+                    space: self.color_space,
+                    ..ramp
+                }),
+            },
+            critical_hide_bar: self.critical.critical_hide_bar,
+            selector: self.selector.clone(),
+            orientation: self.orientation,
+            anchor: self.anchor,
+            pct_format: Some(self.pct_format.clone()).filter(|format| !format.is_empty()),
+        }
+    }
+
+    // targets lists the paths to write a render to:
+    // OUTPUT, or else those of --output, along with it if it was given.
+    fn targets(&self) -> Vec<files::Target> {
+        if !self.outputs.is_empty() {
+            return self.outputs.clone();
+        }
+        vec![files::Target {
+            path: self.output.clone(),
+            width: None,
+        }]
+    }
+
+    // is_charging says whether to draw the icon as charging,
+    // either because of --charging or because the battery said so.
+    fn is_charging(&self, reading: Reading) -> bool {
+        self.charging || reading.state == BatteryState::Charging
+    }
+
+    // read_source reads the source, with the state of --state or --charging
+    // in place of the one it reported.
+    fn read_source(&self) -> Result<Reading, Box<dyn Error>> {
+        let mut reading = match self.charge {
+            Some(Charge::Percent(percent)) => battery::Fixed(percent / 100.0).read()?,
+            Some(Charge::Stdin) => battery::ChargeStdin
+                .read()
+                .map_err(|e| format!("failed to read --charge: {e}"))?,
+            None => self
+                .source
+                .read(self.level, &self.source_args)
+                .map_err(|e| format!("failed to read {:?} source: {e}", self.source))?,
+        };
+        // A bad charge from the command line is a usage error, not the backend's.
+        let given = matches!(self.charge, Some(Charge::Percent(_)))
+            || (self.source == Source::Level && self.level.is_some());
+        reading.level = self.out_of_range.check(reading.level).or_exit(if given {
+            Code::Usage
+        } else {
+            Code::Backend
+        })?;
+        if let Some(state) = self.state {
+            reading.state = state;
+        }
+        if self.charging {
+            reading.state = BatteryState::Charging;
+        }
+        Ok(reading)
+    }
 }
 
-fn process_attributes<'a>(
-    tag_stack: &Vec<tag::Tag>,
-    tag_in: &'a dyn tag::TagBytes,
-    args: &Args,
-) -> Result<BytesStart<'a>, Box<dyn Error>> {
-    let tag = tag::Tag::new(tag_in)?;
-    let mut tag_out = BytesStart::new(tag.name.clone());
-
-    // Build the attr_map so that we can read and modify the attributes.
-    let attrs: Vec<Attribute> = tag_in
-        .attributes()
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| format!("failed to collect attributes of {}: {e}", tag.name))?;
-    let Ok(mut attr_map) = new_attr_map(&attrs) else {
-        return Ok(tag_out.with_attributes(attrs));
-    };
+/// What to do with a charge outside 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutOfRange {
+    /// Draw it as empty or full, with a warning.
+    Clamp,
+    /// Fail, for a source that shouldn't be giving such charges.
+    Error,
+}
 
-    // Set the color of the text elements.
-    text_color(tag_stack, &mut attr_map, &args.foreground)
-        .map_err(|e| format!("text_color failed: {e}"))?;
+impl OutOfRange {
+    // check returns the level to draw, or the error for one outside 0 to 1.
+    // A level that isn't a number can't be drawn at all.
+    fn check(self, level: f64) -> Result<f64, Box<dyn Error>> {
+        if level.is_nan() {
+            return Err("the charge is not a number".into());
+        }
+        if (0.0..=1.0).contains(&level) {
+            return Ok(level);
+        }
+        match self {
+            OutOfRange::Clamp => {
+                let clamped = level.clamp(0.0, 1.0);
+                log::warn!("the charge {level} is outside 0 to 1, showing it as {clamped}");
+                Ok(clamped)
+            }
+            OutOfRange::Error => {
+                Err(format!("the charge {level} is outside 0 to 1, and --clamp is error").into())
+            }
+        }
+    }
+}
 
-    // Set the bar graph width and color.
-    battery_fraction(tag_stack, &mut attr_map, args.level)
-        .map_err(|e| format!("battery_fraction failed: {e}"))?;
+// Charge is a --charge, given on the command line or read from stdin.
+#[derive(Clone, Copy, Debug)]
+enum Charge {
+    Percent(f64),
+    Stdin,
+}
 
-    // Turn off the charging icon if not charging.
-    charging_icon(tag_stack, &mut attr_map, args.charging)
-        .map_err(|e| format!("charging_icon failed: {e}"))?;
+fn parse_charge(s: &str) -> Result<Charge, String> {
+    if s == "-" {
+        return Ok(Charge::Stdin);
+    }
+    let percent = s.strip_suffix('%').unwrap_or(s);
+    let percent = percent
+        .parse()
+        .map_err(|e| format!("expected a percentage or -, got {s:?}: {e}"))?;
+    Ok(Charge::Percent(percent))
+}
 
-    // Write the modified attributes into the result.
-    for (key, value) in attr_map {
-        tag_out.push_attribute((key.as_str(), value.as_str()));
+// parse_var splits a --var into its name and value.
+// The leading -- of the name is optional.
+fn parse_var(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got {s:?}"))?;
+    let name = name.trim().trim_start_matches("--");
+    if name.is_empty() {
+        return Err(format!("missing a name in {s:?}"));
     }
-    Ok(tag_out)
+    Ok((name.to_string(), value.trim().to_string()))
 }
 
-fn text_color(
-    tag_stack: &Vec<tag::Tag>,
-    attr_map: &mut HashMap<String, String>,
-    foreground: &str,
-) -> Result<(), Box<dyn Error>> {
-    let spec = "tspan";
-    if !matcher::new_tag_matcher(spec)?.matches(tag_stack) {
-        // eprintln!("no match at {tag_stack:?}");
-        return Ok(());
+// parse_locale reads --locale as a language tag, or from the environment for auto.
+// C and POSIX write numbers as they would be without a locale, like 42%.
+fn parse_locale(s: &str) -> Result<Locale, String> {
+    match s {
+        "auto" => Ok(Locale::from_env()),
+        "C" | "POSIX" => Ok(Locale::default()),
+        _ => s.parse(),
     }
+}
 
-    let style = attr_map.get("style").map_or("", String::as_str);
-    let mut style_map: HashMap<String, String> =
-        parse_style_map(style).map_err(|e| format!("in {spec}: {e}"))?;
+// parse_template checks that --template names a built-in template,
+// with or without the builtin: prefix, and returns it as the path of one, like builtin:circle.
+fn parse_template(s: &str) -> Result<String, String> {
+    let name = s.strip_prefix(templates::PREFIX).unwrap_or(s);
+    templates::get(name).map_err(|e| e.to_string())?;
+    Ok(format!("{}{name}", templates::PREFIX))
+}
 
-    style_map.insert("fill".to_string(), format!("#{foreground}"));
+// parse_selector checks that --selector is one that the matcher understands.
+fn parse_selector(s: &str) -> Result<String, String> {
+    matcher::new_tag_matcher(s).map_err(|e| format!("invalid selector {s:?}: {e}"))?;
+    Ok(s.to_string())
+}
 
-    let new_style = map_as_style(&style_map);
-    attr_map.insert("style".to_string(), new_style);
-    Ok(())
+// parse_color reads a color for the bar, either a hex code like ff8000 or #f80,
+// or a CSS color name like orange, and returns it as a CSS color.
+fn parse_color(s: &str) -> Result<String, String> {
+    let hex = s.trim().trim_start_matches('#');
+    if matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(format!("#{hex}"));
+    }
+    s.trim()
+        .parse::<svgtypes::Color>()
+        .map_err(|e| format!("expected a hex code or a color name, got {s:?}: {e}"))?;
+    Ok(s.trim().to_string())
 }
 
-// battery_fraction adjusts a HashMap of attributes for a <rect /> tag.
-// It scales its width from 100% to the percentage of the remaining charge.
-// It also changes its color if the remaining charge is too low.
-fn battery_fraction(
-    tag_stack: &Vec<tag::Tag>,
-    attr_map: &mut HashMap<String, String>,
-    charge: f64,
-) -> Result<(), Box<dyn Error>> {
-    let fraction_spec = "rect#fraction";
-    if !matcher::new_tag_matcher(fraction_spec)?.matches(tag_stack) {
-        // eprintln!("no match at {tag_stack:?}");
-        return Ok(());
-    }
-    // eprintln!("battery_fraction match at {tag_stack:?}");
-
-    // Check the width.
-    let mut width: f64 = attr_map
-        .get("width")
-        .ok_or_else(|| "#fraction had no [width]")?
-        .parse()
-        .map_err(|e| format!("failed to parse #fraction[width]: {e}"))?;
-    // eprintln!("old width = {:?}", width);
-    width *= charge;
-    attr_map.insert("width".to_string(), width.to_string());
-    // eprintln!("new width = {:?}", width);
-
-    // Change the color if low battery.
-    if charge < 0.3 {
-        let style = attr_map.get("style").map_or("", String::as_str);
-        let mut style_map: HashMap<String, String> =
-            parse_style_map(style).map_err(|e| format!("in #fraction: {e}"))?;
-
-        // let fill = style_map.get("fill").map_or("", String::as_str);
-        let new_fill = if charge < 0.15 { "#ff0000" } else { "#ff8000" };
-        // eprintln!("changing fraction fill {fill:?} -> {new_fill:?}");
-        style_map.insert("fill".to_string(), new_fill.to_string());
-
-        let new_style = map_as_style(&style_map);
-        attr_map.insert("style".to_string(), new_style);
+// main exits with a Code that says what went wrong, if anything did.
+fn main() -> ExitCode {
+    match try_main() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            exit::code_of(&*e).into()
+        }
     }
-    Ok(())
 }
 
-// charging_icon turns the lightning bolt icon on and off,
-// depending on whether we are charging the battery.
-fn charging_icon(
-    tag_stack: &Vec<tag::Tag>,
-    attr_map: &mut HashMap<String, String>,
-    charging: bool,
-) -> Result<(), Box<dyn Error>> {
-    if charging {
-        return Ok(());
+fn try_main() -> Result<(), Box<dyn Error>> {
+    let (command, argv) = with_config_defaults(cli())?;
+    let matches = command.get_matches_from(argv);
+    logging::init(matches.get_count("verbose"), matches.get_count("quiet"))?;
+    if let Some((_, sub_matches)) = matches.subcommand() {
+        let command = Command::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        return match command {
+            Command::Render(args) => run(args, sub_matches),
+            Command::Watch(args) => run(
+                Args {
+                    watch: true,
+                    ..args
+                },
+                sub_matches,
+            ),
+            Command::Status(mut status_args) => {
+                apply_source_specs(sub_matches, &mut status_args.source_args);
+                status::run(&status_args)
+            }
+            Command::Doctor(doctor_args) => doctor::run(&doctor_args),
+            Command::Selftest(selftest_args) => selftest::run(&selftest_args),
+            Command::Query(query_args) => query::run(&query_args),
+            Command::Tui(tui_args) => tui::run(&tui_args),
+            Command::Preview(preview_args) => preview::run(&preview_args),
+            Command::Service(service_args) => service::run(&service_args),
+            Command::Sweep(sweep_args) => sweep::run(&sweep_args),
+            Command::Batch(batch_args) => batch::run(&batch_args),
+            Command::Man(man_args) => man::run(&man_args),
+            Command::Init(init_args) => init::run(&init_args),
+        };
     }
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    run(args, &matches)
+}
 
-    let spec = "text#icon";
-    if !matcher::new_tag_matcher(spec)?.matches(tag_stack) {
-        return Ok(());
+// apply_source_specs fills in the settings given along with each --source,
+// like the path of a file:PATH.
+fn apply_source_specs(matches: &clap::ArgMatches, source_args: &mut source::SourceArgs) {
+    for spec in matches.get_raw("source").into_iter().flatten() {
+        if let Some(spec) = spec.to_str() {
+            source_args.apply_spec(spec);
+        }
+    }
+}
+
+// run renders the icon once, or keeps rendering it with --watch.
+fn run(mut args: Args, matches: &clap::ArgMatches) -> Result<(), Box<dyn Error>> {
+    apply_source_specs(matches, &mut args.source_args);
+    // With --template, the one path given is the OUTPUT, which clap took for the SVG.
+    let mut output_source = matches.value_source("output");
+    if let Some(template) = &args.template {
+        if output_source != Some(ValueSource::DefaultValue) {
+            return Err(
+                "--template takes the place of the SVG path, so give only the OUTPUT".into(),
+            );
+        }
+        if matches.value_source("svg") != Some(ValueSource::DefaultValue) {
+            args.output = std::mem::take(&mut args.svg);
+            output_source = matches.value_source("svg");
+        }
+        args.svg = template.into();
+    }
+    // Checked here rather than by clap, so that defaults from the config count.
+    let left_out = output_source == Some(ValueSource::DefaultValue)
+        && args.output == std::path::Path::new(files::STDIO);
+    if left_out && !args.in_place && !args.check && args.outputs.is_empty() {
+        return Err("an OUTPUT path is needed, or --output, --in-place or --check".into());
+    }
+    let stdin_svg = args.svg == std::path::Path::new(files::STDIO);
+    if args.in_place {
+        if stdin_svg {
+            return Err("--in-place needs an SVG file, not stdin".into());
+        }
+        if templates::name(&args.svg).is_some() {
+            return Err("--in-place needs an SVG file, not a built-in template".into());
+        }
+        args.output = args.svg.clone();
+    }
+    // OUTPUT can be left out for --output, and otherwise is written along with them.
+    if !args.outputs.is_empty() {
+        if !left_out {
+            let output = files::Target {
+                path: args.output.clone(),
+                width: None,
+            };
+            args.outputs.insert(0, output);
+        }
+        args.output = args.outputs[0].path.clone();
+    }
+    if stdin_svg && (args.source == Source::Stdin || matches!(args.charge, Some(Charge::Stdin))) {
+        return Err("the SVG and the level can't both be read from stdin".into());
+    }
+    // Checked here rather than by clap, so that the watch subcommand counts as --watch.
+    if args.watch {
+        if args.in_place {
+            return Err("--in-place can't be used with --watch, or the watch subcommand".into());
+        }
+        if args.check {
+            return Err("--check can't be used with --watch, or the watch subcommand".into());
+        }
+    } else {
+        if args.critical.on_critical_action.is_some() {
+            return Err("--on-critical-action needs --watch, or the watch subcommand".into());
+        }
+        if args.journal.journal.is_some() {
+            return Err("--journal needs --watch, or the watch subcommand".into());
+        }
+    }
+    if args.report.is_some() && args.output == std::path::Path::new(files::STDIO) {
+        return Err("--report prints to stdout, so the SVG needs an output path".into());
+    }
+    if args.profile.is_some() && args.output == std::path::Path::new(files::STDIO) {
+        return Err("--profile writes a directory of icons, so it can't write to stdout".into());
+    }
+    log::debug!("{args:?}");
+    let config = args.config()?;
+    let runs = per_battery(&args)?;
+    if args.watch {
+        if let [run] = runs.as_slice() {
+            return watch_loop(run, config);
+        }
+        // Each battery gets a watch of its own.
+        return std::thread::scope(|scope| {
+            let watches: Vec<_> = runs
+                .iter()
+                .map(|run| {
+                    scope.spawn(move || {
+                        run.config()
+                            .and_then(|config| watch_loop(run, config))
+                            .map_err(|e| format!("{:?}: {e}", run.output))
+                    })
+                })
+                .collect();
+            for watch in watches {
+                watch.join().map_err(|_| "a watch panicked")??;
+            }
+            Ok(())
+        });
+    }
+    let mut changed = false;
+    let mut unmatched = Vec::new();
+    for run in &runs {
+        let reading = run.read_source().or_exit(Code::Backend)?;
+        let data = run.data()?;
+        if run.check {
+            changed |= check::run(run, &config, reading, &data)?;
+            continue;
+        }
+        let report = render(run, &config, reading, &data)?;
+        unmatched.extend(no_match(run, &report));
+        if run.eww {
+            eww::print_state(&run.output, reading, run.is_charging(reading))
+                .or_exit(Code::Write)?;
+        }
+    }
+    if changed {
+        std::process::exit(Code::Changed as i32);
+    }
+    if !unmatched.is_empty() {
+        // The outputs are written all the same, since the rest of the render may be right.
+        return Err(unmatched.join("; ")).or_exit(Code::NoMatch);
     }
-    let style = attr_map.get("style").map_or("", String::as_str);
-    let mut style_map: HashMap<String, String> =
-        parse_style_map(style).map_err(|e| format!("in #icon: {e}"))?;
-    style_map.insert("display".to_string(), "none".to_string());
-    let new_style = map_as_style(&style_map);
-    attr_map.insert("style".to_string(), new_style);
     Ok(())
 }
 
-fn new_attr_map(attrs: &Vec<Attribute>) -> Result<HashMap<String, String>, Box<dyn Error>> {
-    let mut attr_map: HashMap<String, String> = HashMap::new();
-    // Read the attributes out for modification.
-    for attr in attrs {
-        let key = str::from_utf8(attr.key.into_inner())?.to_string();
-        let value = str::from_utf8(&attr.value)?.to_string();
-        attr_map.insert(key, value);
+// DEFAULT_SELECTOR is the bar of a template, unless --selector says otherwise.
+const DEFAULT_SELECTOR: &str = "rect#fraction";
+
+// no_match describes the --selector, bindings and rules that matched no element of the template.
+// A template without the default bar, like a gauge of segments, only gets a warning.
+fn no_match(args: &Args, report: &Report) -> Vec<String> {
+    if report.bar == 0 && args.selector == DEFAULT_SELECTOR {
+        log::warn!("no element matched {DEFAULT_SELECTOR:?}, so no bar was scaled");
+    }
+    let bar = Some(&args.selector).filter(|_| report.bar == 0 && args.selector != DEFAULT_SELECTOR);
+    bar.into_iter()
+        .chain(&report.unmatched)
+        .map(|selector| format!("no element matched {selector:?}"))
+        .collect()
+}
+
+// per_battery returns the Args of a render for each battery
+// of --batteries all or a list of them,
+// with the {battery} in the output path replaced by its name.
+// Otherwise, it returns the Args as they are.
+fn per_battery(args: &Args) -> Result<Vec<Args>, Box<dyn Error>> {
+    let names = match &args.source_args.batteries {
+        Some(Batteries::All) => battery::names().or_exit(Code::Backend)?,
+        Some(Batteries::Named(names)) => names.clone(),
+        Some(Batteries::Combined) | None => return Ok(vec![args.clone()]),
+    };
+    let output = args.output.to_string_lossy();
+    let targets = args
+        .outputs
+        .iter()
+        .map(|target| target.path.to_string_lossy());
+    if let Some(output) = std::iter::once(output.clone())
+        .chain(targets)
+        .find(|output| !output.contains("{battery}"))
+    {
+        return Err(format!(
+            "--batteries needs {{battery}} in the output path, like battery-{{battery}}.svg, got {output:?}"
+        )
+        .into());
     }
-    Ok(attr_map)
+    let runs = names
+        .iter()
+        .map(|name| {
+            let mut run = args.clone();
+            run.source_args.battery = Some(name.clone());
+            run.source_args.batteries = None;
+            run.output = output.replace("{battery}", name).into();
+            for target in &mut run.outputs {
+                let path = target.path.to_string_lossy().replace("{battery}", name);
+                target.path = path.into();
+            }
+            run
+        })
+        .collect();
+    Ok(runs)
 }
 
-// parse_style_map converts an SVG style attribute into a key-value map.
-fn parse_style_map(style: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
-    let mut map = HashMap::new();
-    for kv in style.split(";") {
-        let kv: Vec<&str> = kv.trim().splitn(2, ":").collect();
-        if kv.len() != 2 {
-            return Err(format!("failed to parse style kv: {kv:?}").into());
+// watch_loop re-renders the output whenever the reading changes.
+// It checks the source every --interval seconds,
+// and sooner if the source has a way to announce changes.
+// Saving the template, config, an overlay or a --source file re-renders right away.
+fn watch_loop(args: &Args, mut config: config::Config) -> Result<(), Box<dyn Error>> {
+    let interval = Duration::try_from_secs_f64(args.interval)
+        .map_err(|e| format!("invalid --interval {}: {e}", args.interval))?;
+    let mut trigger = watch::Trigger::new(interval);
+    let files: Vec<&std::path::Path> = [Some(&args.svg), args.config.as_ref()]
+        .into_iter()
+        .flatten()
+        .filter(|path| path.as_path() != std::path::Path::new(files::STDIO))
+        .filter(|path| templates::name(path).is_none())
+        .chain(args.overlay.iter().map(|spec| &spec.path))
+        .chain(
+            args.source_args
+                .file
+                .iter()
+                .filter(|_| args.source == Source::File),
+        )
+        .map(|path| path.as_path())
+        .collect();
+    if let Err(e) = trigger.watch_files(&files) {
+        log::warn!("not reloading on changes: {e}");
+    }
+    if args.source == Source::Brightness {
+        let udevadm = ["monitor", "--udev", "--subsystem-match=backlight"];
+        if let Err(e) = trigger.watch_command("udevadm", &udevadm, |line| line.starts_with("UDEV"))
+        {
+            log::warn!("polling every {interval:?}: {e}");
+        }
+    }
+    if matches!(args.source, Source::Auto | Source::Upower) {
+        // [12:00:00.000]	device changed:     /org/freedesktop/UPower/devices/battery_BAT0
+        let is_event = |line: &str| line.contains("device changed");
+        if let Err(e) = trigger.watch_command("upower", &["--monitor"], is_event) {
+            log::warn!("polling every {interval:?}: {e}");
+        }
+    }
+    if let (Source::Mqtt, Some(url)) = (args.source, &args.source_args.url) {
+        match mqtt::subscribe(url) {
+            Ok(subscription) => subscription.on_message(trigger.notifier()),
+            Err(e) => log::warn!("polling every {interval:?}: {e}"),
+        }
+    }
+
+    let mut last_input = None;
+    let mut smoother = battery::Smoother::new(args.source_args.smooth);
+    let mut guard = critical::Guard::default();
+    let mut journal = journal::Journal::open(&args.journal)?;
+    loop {
+        // Keep going after errors, since the source or template may recover.
+        let input = args
+            .read_source()
+            .and_then(|reading| Ok((reading, args.data()?)));
+        if let Ok((reading, _)) = &input {
+            if let Err(e) = guard.check(&args.critical, reading.level, args.is_charging(*reading)) {
+                log::error!("critical action failed: {e}");
+            }
+            if let Some(journal) = &mut journal {
+                if let Err(e) = journal.record(reading.level, args.is_charging(*reading)) {
+                    log::error!("{e}");
+                }
+            }
+        }
+        // Smooth only what is drawn, so that the critical actions don't lag.
+        let input = input.map(|(reading, data)| (smoother.smooth(reading), data));
+        match input {
+            Ok(input) if last_input.as_ref() != Some(&input) => {
+                let (reading, data) = &input;
+                let rendered = render(args, &config, *reading, data).and_then(|report| {
+                    if args.eww {
+                        eww::print_state(&args.output, *reading, args.is_charging(*reading))?;
+                    }
+                    Ok(report)
+                });
+                match rendered {
+                    Ok(report) => {
+                        for warning in no_match(args, &report) {
+                            log::warn!("{warning}");
+                        }
+                        last_input = Some(input);
+                    }
+                    Err(e) => log::error!("{e}"),
+                }
+            }
+            Ok(_) => {}
+            Err(e) => log::error!("{e}"),
+        }
+        if trigger.wait() {
+            // Render again even if the reading is the same.
+            last_input = None;
+            match args.config() {
+                Ok(reloaded) => config = reloaded,
+                Err(e) => log::warn!("keeping the previous config: {e}"),
+            }
         }
-        let key = kv[0];
-        let value = kv[1];
-        map.insert(key.to_string(), value.to_string());
     }
-    Ok(map)
 }
 
-// map_as_style converts a key-value map into an SVG style attribute.
-fn map_as_style(map: &HashMap<String, String>) -> String {
-    let mut style = String::new();
-    for (k, v) in map {
-        style.push(';');
-        style.push_str(k);
-        style.push(':');
-        style.push_str(v);
+// render writes the transformed SVG to the output, and returns the report of the render.
+fn render(
+    args: &Args,
+    config: &config::Config,
+    reading: Reading,
+    data: &data::Data,
+) -> Result<Report, Box<dyn Error>> {
+    if let Some(profile) = args.profile {
+        let (svg, report) = render_report(args, config, reading, data, Vec::new())?;
+        profile::write(profile, &svg, &args.svg, &args.output, &args.raster_args)
+            .or_exit(Code::Write)?;
+        return Ok(report);
+    }
+    let (svg, report) = render_report(args, config, reading, data, Vec::new())?;
+    for target in args.targets() {
+        let raster_args = raster::RasterArgs {
+            width: target.width.or(args.raster_args.width),
+            ..args.raster_args.clone()
+        };
+        let write = |path: &std::path::Path| -> Result<(), Box<dyn Error>> {
+            let mut output = files::Output::create(path, &raster_args).or_exit(Code::Write)?;
+            output
+                .write_all(&svg)
+                .map_err(Box::from)
+                .and_then(|()| output.finish())
+                .map_err(|e| format!("failed to write output file {:?}: {e}", target.path))
+                .or_exit(Code::Write)
+        };
+        match args.in_place {
+            true => files::replace(&target.path, write).or_exit(Code::Write)?,
+            false => write(&target.path)?,
+        }
+    }
+    if args.report.is_some() {
+        let charging = args.is_charging(reading);
+        report::print(
+            &args.svg,
+            &args.output,
+            args.source,
+            reading,
+            charging,
+            &report,
+        )
+        .or_exit(Code::Write)?;
     }
-    if style.len() == 0 {
-        return "".to_string();
+    Ok(report)
+}
+
+// render_to renders the template into the given output, and returns it.
+fn render_to<W: Write>(
+    args: &Args,
+    config: &config::Config,
+    reading: Reading,
+    data: &data::Data,
+    output: W,
+) -> Result<W, Box<dyn Error>> {
+    render_report(args, config, reading, data, output).map(|(output, _)| output)
+}
+
+// render_report is render_to, also returning the report of the render.
+fn render_report<W: Write>(
+    args: &Args,
+    config: &config::Config,
+    reading: Reading,
+    data: &data::Data,
+    output: W,
+) -> Result<(W, Report), Box<dyn Error>> {
+    let options = args.options(reading);
+    let rules = config.rule.iter().chain(&args.transform);
+    let render = Render::new(&options, reading, data, &config.bind, rules)?;
+    let overlays = overlay::load_all(&args.overlay).or_exit(Code::Template)?;
+    let events = render.transform(read_events(args.input().or_exit(Code::Template)?));
+    let mut writer = Writer::new(output);
+    for event in overlay::composite(events, &overlays) {
+        writer
+            .write_event(event.or_exit(Code::Template)?)
+            .map_err(|e| format!("failed to write output: {e}"))
+            .or_exit(Code::Write)?;
     }
-    style.trim_start_matches(';').to_string()
+    Ok((writer.into_inner(), render.report()))
 }
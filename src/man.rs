@@ -0,0 +1,30 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Settings for the man subcommand.
+#[derive(clap::Args, Debug)]
+pub struct ManArgs {
+    /// Directory to write battery-icon.1 into,
+    /// along with a page for each subcommand, like battery-icon-watch.1,
+    /// instead of printing battery-icon.1.
+    #[arg(long)]
+    out_dir: Option<PathBuf>,
+}
+
+// run prints the man page of battery-icon in roff, from the definition of its command line,
+// or writes it and those of the subcommands into --out-dir.
+pub fn run(args: &ManArgs) -> Result<(), Box<dyn Error>> {
+    let command = crate::cli();
+    let Some(dir) = &args.out_dir else {
+        let mut stdout = io::stdout().lock();
+        clap_mangen::Man::new(command).render(&mut stdout)?;
+        stdout.flush()?;
+        return Ok(());
+    };
+    fs::create_dir_all(dir).map_err(|e| format!("failed to create directory {dir:?}: {e}"))?;
+    clap_mangen::generate_to(command, dir)
+        .map_err(|e| format!("failed to write the man pages into {dir:?}: {e}"))?;
+    Ok(())
+}
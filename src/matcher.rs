@@ -2,7 +2,11 @@ use crate::tag;
 use std::error::Error;
 
 pub trait StackMatcher {
-    fn matches(&self, stack: &Vec<tag::Tag>) -> bool;
+    fn matches(&self, stack: &[tag::Tag]) -> bool;
+
+    // mismatch explains why the matcher does not match, for --explain,
+    // or returns None if it does.
+    fn mismatch(&self, stack: &[tag::Tag]) -> Option<String>;
 }
 
 pub struct IdMatcher {
@@ -10,12 +14,50 @@ pub struct IdMatcher {
 }
 
 impl StackMatcher for IdMatcher {
-    fn matches(&self, stack: &Vec<tag::Tag>) -> bool {
+    fn matches(&self, stack: &[tag::Tag]) -> bool {
         let Some(last) = stack.last() else {
             return false;
         };
         last.id == self.id
     }
+
+    fn mismatch(&self, stack: &[tag::Tag]) -> Option<String> {
+        let last = stack.last()?;
+        if last.id == self.id {
+            return None;
+        }
+        if last.id.is_empty() {
+            return Some(format!("id mismatch: no id, wanted {:?}", self.id));
+        }
+        Some(format!("id mismatch: {:?} is not {:?}", last.id, self.id))
+    }
+}
+
+pub struct ClassMatcher {
+    pub class: String,
+}
+
+impl StackMatcher for ClassMatcher {
+    fn matches(&self, stack: &[tag::Tag]) -> bool {
+        let Some(last) = stack.last() else {
+            return false;
+        };
+        last.classes().any(|class| class == self.class)
+    }
+
+    fn mismatch(&self, stack: &[tag::Tag]) -> Option<String> {
+        let last = stack.last()?;
+        if self.matches(stack) {
+            return None;
+        }
+        match last.attr("class") {
+            None => Some(format!("class mismatch: no class, wanted {:?}", self.class)),
+            Some(classes) => Some(format!(
+                "class mismatch: {classes:?} has no {:?}",
+                self.class
+            )),
+        }
+    }
 }
 
 pub struct NameMatcher {
@@ -23,12 +65,23 @@ pub struct NameMatcher {
 }
 
 impl StackMatcher for NameMatcher {
-    fn matches(&self, stack: &Vec<tag::Tag>) -> bool {
+    fn matches(&self, stack: &[tag::Tag]) -> bool {
         let Some(last) = stack.last() else {
             return false;
         };
         last.name == self.name
     }
+
+    fn mismatch(&self, stack: &[tag::Tag]) -> Option<String> {
+        let last = stack.last()?;
+        if last.name == self.name {
+            return None;
+        }
+        Some(format!(
+            "name mismatch: {:?} is not {:?}",
+            last.name, self.name
+        ))
+    }
 }
 
 pub struct AndMatcher {
@@ -36,7 +89,7 @@ pub struct AndMatcher {
 }
 
 impl StackMatcher for AndMatcher {
-    fn matches(&self, stack: &Vec<tag::Tag>) -> bool {
+    fn matches(&self, stack: &[tag::Tag]) -> bool {
         for m in &self.matchers {
             if !m.matches(stack) {
                 return false;
@@ -44,25 +97,463 @@ impl StackMatcher for AndMatcher {
         }
         true
     }
+
+    fn mismatch(&self, stack: &[tag::Tag]) -> Option<String> {
+        self.matchers.iter().find_map(|m| m.mismatch(stack))
+    }
+}
+
+pub struct NthChildMatcher {
+    pub n: usize,
+    /// Count only the siblings with the same name, like :nth-of-type.
+    pub of_type: bool,
 }
 
+impl StackMatcher for NthChildMatcher {
+    fn matches(&self, stack: &[tag::Tag]) -> bool {
+        let Some(last) = stack.last() else {
+            return false;
+        };
+        let index = if self.of_type {
+            last.type_index
+        } else {
+            last.index
+        };
+        index == self.n
+    }
+
+    fn mismatch(&self, stack: &[tag::Tag]) -> Option<String> {
+        if self.matches(stack) {
+            return None;
+        }
+        let last = stack.last()?;
+        let (index, kind) = if self.of_type {
+            (last.type_index, " of its type")
+        } else {
+            (last.index, "")
+        };
+        Some(format!(
+            "position mismatch: child {index}{kind}, not {}",
+            self.n
+        ))
+    }
+}
+
+// AncestorMatcher matches if any tag above the current one matches.
+pub struct AncestorMatcher {
+    pub ancestor: Box<dyn StackMatcher>,
+}
+
+impl StackMatcher for AncestorMatcher {
+    fn matches(&self, stack: &[tag::Tag]) -> bool {
+        (1..stack.len()).any(|depth| self.ancestor.matches(&stack[..depth]))
+    }
+
+    fn mismatch(&self, stack: &[tag::Tag]) -> Option<String> {
+        if self.matches(stack) {
+            return None;
+        }
+        // Explain with the parent, which is the likeliest one to have been meant.
+        let parent = &stack[..stack.len().saturating_sub(1)];
+        match self.ancestor.mismatch(parent) {
+            Some(why) if !parent.is_empty() => {
+                Some(format!("wrong ancestor: none matches (parent: {why})"))
+            }
+            _ => Some("wrong ancestor: none matches".to_string()),
+        }
+    }
+}
+
+// AttrMatcher matches a tag with the attribute,
+// and with the given value if there is one.
+pub struct AttrMatcher {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl StackMatcher for AttrMatcher {
+    fn matches(&self, stack: &[tag::Tag]) -> bool {
+        let Some(last) = stack.last() else {
+            return false;
+        };
+        let value = match self.key.as_str() {
+            "href" | "xlink:href" => last.href(),
+            key => last.attr(key),
+        };
+        match (value, &self.value) {
+            (Some(value), Some(want)) => value == want,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    fn mismatch(&self, stack: &[tag::Tag]) -> Option<String> {
+        if self.matches(stack) {
+            return None;
+        }
+        let last = stack.last()?;
+        let value = match self.key.as_str() {
+            "href" | "xlink:href" => last.href(),
+            key => last.attr(key),
+        };
+        match (value, &self.value) {
+            (Some(value), Some(want)) => Some(format!(
+                "attribute mismatch: [{}] is {value:?}, not {want:?}",
+                self.key
+            )),
+            _ => Some(format!("attribute mismatch: no [{}]", self.key)),
+        }
+    }
+}
+
+// new_tag_matcher parses a selector like "rect#fraction",
+// "g#segments rect:nth-of-type(2)" or "text[inkscape:label=\"fill\"]".
+// Separating selectors with spaces matches descendants.
 pub fn new_tag_matcher(spec: &str) -> Result<AndMatcher, Box<dyn Error>> {
-    let (name, id) = spec.split_once("#").unwrap_or((spec, ""));
+    new_descendant_matcher(&split_compounds(spec)?)
+}
+
+// new_descendant_matcher matches the last compound selector,
+// inside of tags matching the ones before it.
+fn new_descendant_matcher(compounds: &[&str]) -> Result<AndMatcher, Box<dyn Error>> {
+    let Some((last, ancestors)) = compounds.split_last() else {
+        return Err("new_tag_matcher: empty spec".into());
+    };
+    let mut result = new_compound_matcher(last)?;
+    if !ancestors.is_empty() {
+        result.matchers.push(Box::new(AncestorMatcher {
+            ancestor: Box::new(new_descendant_matcher(ancestors)?),
+        }));
+    }
+    Ok(result)
+}
+
+// split_compounds splits a selector at the spaces
+// that are not inside of brackets or quotes.
+fn split_compounds(spec: &str) -> Result<Vec<&str>, Box<dyn Error>> {
+    let mut compounds = Vec::new();
+    let mut start = None;
+    let mut in_brackets = false;
+    let mut quote = None;
+    for (i, c) in spec.char_indices() {
+        match (c, quote) {
+            (_, Some(q)) if c == q => quote = None,
+            (_, Some(_)) => {}
+            ('"' | '\'', None) if in_brackets => quote = Some(c),
+            ('[', None) => in_brackets = true,
+            (']', None) => in_brackets = false,
+            (c, None) if c.is_whitespace() && !in_brackets => {
+                if let Some(s) = start.take() {
+                    compounds.push(&spec[s..i]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(i);
+    }
+    if in_brackets || quote.is_some() {
+        return Err(format!("unclosed [ in selector {spec:?}").into());
+    }
+    if let Some(s) = start {
+        compounds.push(&spec[s..]);
+    }
+    Ok(compounds)
+}
+
+// PSEUDO_CLASSES are the names that make a colon start a pseudo-class,
+// supported or not, so that a colon anywhere else is part of a name,
+// like that of <sodipodi:namedview>, or of an id.
+const PSEUDO_CLASSES: [&str; 16] = [
+    "first-child",
+    "first-of-type",
+    "nth-child",
+    "nth-of-type",
+    "last-child",
+    "last-of-type",
+    "nth-last-child",
+    "nth-last-of-type",
+    "only-child",
+    "only-of-type",
+    "not",
+    "is",
+    "where",
+    "has",
+    "root",
+    "empty",
+];
+
+// part_end finds where the selector part starting at from ends:
+// at the next #, ., [ or pseudo-class colon that isn't escaped with a backslash.
+fn part_end(spec: &str, from: usize) -> usize {
+    let mut escaped = false;
+    for (i, c) in spec[from..].char_indices() {
+        let i = from + i;
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '#' | '.' | '[' => return i,
+            ':' if is_pseudo(&spec[i + 1..]) => return i,
+            _ => {}
+        }
+    }
+    spec.len()
+}
+
+// is_pseudo reports whether the selector after a colon starts with a pseudo-class.
+fn is_pseudo(rest: &str) -> bool {
+    let end = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+        .unwrap_or(rest.len());
+    PSEUDO_CLASSES.contains(&&rest[..end])
+}
+
+// unescape drops the backslashes before escaped characters, like the one in #layer1\.5.
+fn unescape(value: &str) -> String {
+    let mut result = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+// new_compound_matcher parses a selector for a single tag,
+// made of a name, then any number of #id, .class, :pseudo-class and [attr] parts.
+fn new_compound_matcher(spec: &str) -> Result<AndMatcher, Box<dyn Error>> {
     let mut result = AndMatcher {
         matchers: Vec::new(),
     };
-    if name != "" {
-        result.matchers.push(Box::new(NameMatcher {
-            name: name.to_string(),
-        }));
+    let name_end = part_end(spec, 0);
+    let name = unescape(&spec[..name_end]);
+    if !name.is_empty() {
+        result.matchers.push(Box::new(NameMatcher { name }));
     }
-    if id != "" {
-        result
-            .matchers
-            .push(Box::new(IdMatcher { id: id.to_string() }));
+    let mut rest = &spec[name_end..];
+    while !rest.is_empty() {
+        if rest.starts_with('[') {
+            let end = attr_end(rest).ok_or_else(|| format!("unclosed [ in {spec:?}"))?;
+            result.matchers.push(new_attr_matcher(&rest[1..end])?);
+            rest = &rest[end + 1..];
+            continue;
+        }
+        let end = part_end(rest, 1);
+        let (part, value) = (&rest[..1], &rest[1..end]);
+        if value.is_empty() {
+            return Err(format!("empty {part} in selector {spec:?}").into());
+        }
+        if part == "#" {
+            result.matchers.push(Box::new(IdMatcher {
+                id: unescape(value),
+            }));
+        } else if part == "." {
+            result.matchers.push(Box::new(ClassMatcher {
+                class: unescape(value),
+            }));
+        } else {
+            result.matchers.push(new_pseudo_matcher(value)?);
+        }
+        rest = &rest[end..];
     }
-    if result.matchers.len() == 0 {
+    if result.matchers.is_empty() {
         return Err("new_tag_matcher: failed to parse spec".into());
     }
     Ok(result)
 }
+
+// attr_end finds the ] closing the [ at the start of the selector part,
+// skipping over any in quotes.
+fn attr_end(part: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in part.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == ']' => return Some(i),
+            None => {}
+        }
+    }
+    None
+}
+
+// new_attr_matcher parses the inside of [key] or [key="value"].
+fn new_attr_matcher(inner: &str) -> Result<Box<dyn StackMatcher>, Box<dyn Error>> {
+    let (key, value) = match inner.split_once('=') {
+        Some((key, value)) => {
+            let value = value.trim();
+            let unquoted = ['"', '\'']
+                .iter()
+                .find_map(|q| value.strip_prefix(*q)?.strip_suffix(*q))
+                .unwrap_or(value);
+            (key.trim(), Some(unquoted.to_string()))
+        }
+        None => (inner.trim(), None),
+    };
+    if key.is_empty() {
+        return Err(format!("missing attribute name in [{inner}]").into());
+    }
+    Ok(Box::new(AttrMatcher {
+        key: key.to_string(),
+        value,
+    }))
+}
+
+fn new_pseudo_matcher(pseudo: &str) -> Result<Box<dyn StackMatcher>, Box<dyn Error>> {
+    let nth = |of_type: bool, arg: &str| -> Result<Box<dyn StackMatcher>, Box<dyn Error>> {
+        let n = arg
+            .strip_suffix(')')
+            .ok_or_else(|| format!("missing \")\" in :{pseudo}"))?
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid index in :{pseudo}: {e}"))?;
+        Ok(Box::new(NthChildMatcher { n, of_type }))
+    };
+    match pseudo {
+        "first-child" => Ok(Box::new(NthChildMatcher {
+            n: 1,
+            of_type: false,
+        })),
+        "first-of-type" => Ok(Box::new(NthChildMatcher {
+            n: 1,
+            of_type: true,
+        })),
+        _ => {
+            if let Some(arg) = pseudo.strip_prefix("nth-child(") {
+                nth(false, arg)
+            } else if let Some(arg) = pseudo.strip_prefix("nth-of-type(") {
+                nth(true, arg)
+            } else {
+                Err(format!("unsupported pseudo-class :{pseudo}").into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str, attrs: &[(&str, &str)]) -> tag::Tag {
+        let attrs: Vec<(String, String)> = attrs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let id = attrs
+            .iter()
+            .find(|(key, _)| key == "id")
+            .map_or(String::new(), |(_, value)| value.clone());
+        tag::Tag {
+            name: name.to_string(),
+            id,
+            attrs,
+            index: 0,
+            type_index: 0,
+        }
+    }
+
+    fn matches(spec: &str, stack: &[tag::Tag]) -> bool {
+        new_tag_matcher(spec).unwrap().matches(stack)
+    }
+
+    #[test]
+    fn namespaced_names() {
+        let stack = [
+            tag("svg", &[]),
+            tag("sodipodi:namedview", &[("id", "base:view")]),
+        ];
+        assert!(matches("sodipodi:namedview", &stack));
+        assert!(matches("svg sodipodi:namedview#base:view", &stack));
+        assert!(matches("#base:view", &stack));
+        assert!(!matches("sodipodi:guide", &stack));
+        assert!(new_tag_matcher("rect:last-child").is_err());
+        assert!(new_tag_matcher("rect:nth-child(two)").is_err());
+    }
+
+    #[test]
+    fn escaped_ids() {
+        let stack = [tag("g", &[("id", "layer1.5"), ("class", "a.b")])];
+        assert!(matches(r"g#layer1\.5", &stack));
+        assert!(matches(r"g.a\.b", &stack));
+        assert!(!matches("g#layer1.5", &stack));
+    }
+
+    #[test]
+    fn positions() {
+        // <svg><g id="battery"><rect/><circle/><rect/></g></svg>
+        let mut stack = tag::Stack::default();
+        stack.push(tag("svg", &[]));
+        stack.push(tag("g", &[("id", "battery")]));
+        stack.push(tag("rect", &[]));
+        assert!(matches("rect:first-child", &stack));
+        assert!(matches("rect:first-of-type", &stack));
+        stack.pop();
+        stack.push(tag("circle", &[]));
+        assert!(matches("circle:first-of-type", &stack));
+        assert!(!matches("circle:first-child", &stack));
+        stack.pop();
+        stack.push(tag("rect", &[]));
+        assert!(matches("rect:nth-of-type(2)", &stack));
+        assert!(matches("rect:nth-child(3)", &stack));
+        assert!(!matches("rect:nth-child(2)", &stack));
+        assert!(!matches("rect:first-child", &stack));
+
+        // With descendants, the position is of the last tag alone.
+        assert!(matches("g#battery rect:nth-of-type(2)", &stack));
+        assert!(matches("svg rect:nth-child(3)", &stack));
+        assert!(matches("svg:first-child g:first-child rect", &stack));
+        assert!(!matches("g#other rect:nth-of-type(2)", &stack));
+        assert!(!matches("g:nth-child(2) rect", &stack));
+    }
+
+    #[test]
+    fn attributes() {
+        let stack = [
+            tag("svg", &[]),
+            tag(
+                "g",
+                &[
+                    ("inkscape:label", "fill"),
+                    ("inkscape:groupmode", "layer"),
+                    ("data-note", "a b]c"),
+                    ("xlink:href", "#seg"),
+                ],
+            ),
+        ];
+        for spec in [
+            r#"[inkscape:label="fill"]"#,
+            "[inkscape:label='fill']",
+            "[inkscape:label=fill]",
+            "g[ inkscape:label = fill ]",
+            "[inkscape:groupmode]",
+            r#"g[inkscape:groupmode="layer"][inkscape:label="fill"]"#,
+            r#"[data-note="a b]c"]"#,
+            "[href='#seg']",
+            r#"svg [inkscape:label="fill"]"#,
+        ] {
+            assert!(matches(spec, &stack), "{spec}");
+        }
+        for spec in [
+            r#"[inkscape:label="Fill"]"#,
+            "[inkscape:label=fil]",
+            "[sodipodi:type]",
+            r#"rect[inkscape:label="fill"]"#,
+            r#"[inkscape:label="fill"] g"#,
+        ] {
+            assert!(!matches(spec, &stack), "{spec}");
+        }
+        for spec in ["[]", "[=fill]", "[inkscape:label", r#"[a="b]"#] {
+            assert!(new_tag_matcher(spec).is_err(), "{spec}");
+        }
+        let why = new_tag_matcher("[inkscape:label=empty]")
+            .unwrap()
+            .mismatch(&stack);
+        assert_eq!(
+            why.as_deref(),
+            Some(r#"attribute mismatch: [inkscape:label] is "fill", not "empty""#)
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use quick_xml::events::{BytesCData, BytesStart, BytesText, Event};
+use quick_xml::reader::Reader;
+
+use crate::matcher::{self, AndMatcher, StackMatcher};
+use crate::{files, read_events, tag};
+
+// Spec is an --overlay, like badge.svg or badge.svg:#bolt.
+#[derive(Clone, Debug)]
+pub struct Spec {
+    pub path: PathBuf,
+    pub selector: Option<String>,
+}
+
+// The selector follows the first ":" after the SVG extension,
+// since selectors may hold colons of their own, like rect:first-child.
+impl FromStr for Spec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Spec, String> {
+        let split = [".svg:", ".svgz:"]
+            .iter()
+            .find_map(|ext| s.find(ext).map(|i| i + ext.len()));
+        let (path, selector) = match split {
+            Some(i) => (&s[..i - 1], Some(&s[i..])),
+            None => (s, None),
+        };
+        if let Some(selector) = selector {
+            matcher::new_tag_matcher(selector)
+                .map_err(|e| format!("in overlay selector {selector:?}: {e}"))?;
+        }
+        Ok(Spec {
+            path: PathBuf::from(path),
+            selector: selector.map(str::to_string),
+        })
+    }
+}
+
+// Overlay is a loaded layer, ready to be written into the icon.
+pub struct Overlay {
+    // The events of the layer, with its root as a nested <svg>.
+    events: Vec<Event<'static>>,
+    // The layer goes just before the first element that this matches,
+    // or else at the end of the root.
+    matcher: Option<AndMatcher>,
+}
+
+impl Overlay {
+    // load reads an overlay file, prefixing its ids with the prefix
+    // so that they don't clash with those of the icon or other overlays.
+    pub fn load(spec: &Spec, prefix: &str) -> Result<Overlay, Box<dyn Error>> {
+        let reader = Reader::from_reader(files::open_input(&spec.path)?);
+        let mut events = Vec::new();
+        let mut depth = 0;
+        let mut in_style = false;
+        for event in read_events(reader) {
+            let event = match event? {
+                // Leave out what can't go inside of another document.
+                Event::Decl(_) | Event::DocType(_) | Event::PI(_) => continue,
+                Event::Start(e) => {
+                    depth += 1;
+                    in_style = e.name().as_ref() == b"style";
+                    Event::Start(prefix_ids(&e, prefix)?)
+                }
+                Event::Empty(e) => Event::Empty(prefix_ids(&e, prefix)?),
+                Event::End(e) => {
+                    depth -= 1;
+                    in_style = false;
+                    Event::End(e)
+                }
+                Event::Text(e) if depth == 0 => {
+                    if !e.iter().all(u8::is_ascii_whitespace) {
+                        return Err("text outside of the root element".into());
+                    }
+                    continue;
+                }
+                Event::Text(e) if in_style => {
+                    let css = e.xml_content()?;
+                    Event::Text(BytesText::new(&prefix_style(&css, prefix)).into_owned())
+                }
+                Event::CData(e) if in_style => {
+                    let css = str::from_utf8(&e)?;
+                    Event::CData(BytesCData::new(prefix_style(css, prefix)).into_owned())
+                }
+                Event::Text(e) => {
+                    let text = e.xml_content()?;
+                    Event::Text(BytesText::new(&prefix_urls(&text, prefix)).into_owned())
+                }
+                event => event,
+            };
+            events.push(event);
+        }
+        let matcher = match &spec.selector {
+            Some(selector) => Some(matcher::new_tag_matcher(selector)?),
+            None => None,
+        };
+        Ok(Overlay { events, matcher })
+    }
+}
+
+// load_all loads the overlays, each with a prefix from its file name,
+// like vpn-badge- for vpn-badge.svg.
+pub fn load_all(specs: &[Spec]) -> Result<Vec<Overlay>, Box<dyn Error>> {
+    let mut prefixes: Vec<String> = Vec::new();
+    let mut overlays = Vec::new();
+    for spec in specs {
+        let stem = file_stem(&spec.path);
+        let mut prefix = format!("{stem}-");
+        let mut n = 1;
+        while prefixes.contains(&prefix) {
+            n += 1;
+            prefix = format!("{stem}{n}-");
+        }
+        let overlay = Overlay::load(spec, &prefix)
+            .map_err(|e| format!("failed to load overlay {:?}: {e}", spec.path))?;
+        overlays.push(overlay);
+        prefixes.push(prefix);
+    }
+    Ok(overlays)
+}
+
+fn file_stem(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map_or("overlay".into(), |n| n.to_string_lossy());
+    let stem = name.split('.').next().unwrap_or("overlay");
+    stem.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+// prefix_ids renames the id of a tag, and the local references in its attributes.
+fn prefix_ids(tag: &BytesStart, prefix: &str) -> Result<BytesStart<'static>, Box<dyn Error>> {
+    let t = tag::Tag::new(tag)?;
+    let mut result = BytesStart::new(t.name.clone());
+    for (key, value) in &t.attrs {
+        let value = match key.as_str() {
+            "id" => format!("{prefix}{value}"),
+            "href" | "xlink:href" if value.starts_with('#') => {
+                format!("#{prefix}{}", &value[1..])
+            }
+            _ => prefix_urls(value, prefix),
+        };
+        result.push_attribute((key.as_str(), value.as_str()));
+    }
+    Ok(result)
+}
+
+// prefix_urls renames the ids in url(#id) references, like fill="url(#gradient)".
+fn prefix_urls(value: &str, prefix: &str) -> String {
+    value.replace("url(#", &format!("url(#{prefix}"))
+}
+
+// prefix_style renames the ids in the #id selectors of a <style> sheet,
+// and in its url(#id) references.
+// A # inside of a declaration block is a color, like fill: #ff0000,
+// unless the block is that of an at-rule like @media, which holds more rules.
+fn prefix_style(css: &str, prefix: &str) -> String {
+    let mut result = String::new();
+    // Whether each open block holds rules, rather than declarations.
+    let mut blocks: Vec<bool> = Vec::new();
+    let mut prelude = String::new();
+    let mut chars = css.chars().peekable();
+    while let Some(c) = chars.next() {
+        result.push(c);
+        match c {
+            '{' => {
+                blocks.push(prelude.trim_start().starts_with('@'));
+                prelude.clear();
+            }
+            '}' | ';' => {
+                if c == '}' {
+                    blocks.pop();
+                }
+                prelude.clear();
+            }
+            '#' if blocks.last().copied().unwrap_or(true) => {
+                if chars
+                    .peek()
+                    .is_some_and(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
+                {
+                    result.push_str(prefix);
+                }
+                prelude.push(c);
+            }
+            c => prelude.push(c),
+        }
+    }
+    prefix_urls(&result, prefix)
+}
+
+// Composite writes overlays into a stream of icon events.
+pub struct Composite<'a, I> {
+    events: I,
+    overlays: &'a [Overlay],
+    // Whether each overlay has been written yet.
+    written: Vec<bool>,
+    tag_stack: tag::Stack,
+    output: VecDeque<Event<'static>>,
+}
+
+// composite writes each overlay before the first element its selector matches,
+// and the rest just before the root element closes, on top of the icon.
+pub fn composite<I>(events: I, overlays: &[Overlay]) -> Composite<'_, I>
+where
+    I: Iterator<Item = Result<Event<'static>, Box<dyn Error>>>,
+{
+    Composite {
+        events,
+        overlays,
+        written: vec![false; overlays.len()],
+        tag_stack: tag::Stack::default(),
+        output: VecDeque::new(),
+    }
+}
+
+impl<I> Composite<'_, I> {
+    // write_matching queues the overlays meant for the current element.
+    fn write_matching(&mut self) {
+        for (overlay, written) in self.overlays.iter().zip(&mut self.written) {
+            let matches = overlay
+                .matcher
+                .as_ref()
+                .is_some_and(|m| m.matches(&self.tag_stack));
+            // The root itself can't have anything before it.
+            if matches && !*written && self.tag_stack.len() > 1 {
+                self.output.extend(overlay.events.iter().cloned());
+                *written = true;
+            }
+        }
+    }
+
+    // write_rest queues the overlays not written yet.
+    fn write_rest(&mut self) {
+        for (overlay, written) in self.overlays.iter().zip(&mut self.written) {
+            if !*written {
+                self.output.extend(overlay.events.iter().cloned());
+                *written = true;
+            }
+        }
+    }
+}
+
+impl<I> Iterator for Composite<'_, I>
+where
+    I: Iterator<Item = Result<Event<'static>, Box<dyn Error>>>,
+{
+    type Item = Result<Event<'static>, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(event) = self.output.pop_front() {
+            return Some(Ok(event));
+        }
+        let event = match self.events.next()? {
+            Ok(event) => event,
+            Err(e) => return Some(Err(e)),
+        };
+        match &event {
+            Event::Start(e) | Event::Empty(e) => {
+                let tag = match tag::Tag::new(e) {
+                    Ok(tag) => tag,
+                    Err(e) => return Some(Err(e)),
+                };
+                self.tag_stack.push(tag);
+                self.write_matching();
+                if matches!(event, Event::Empty(_)) {
+                    self.tag_stack.pop();
+                }
+            }
+            Event::End(_) => {
+                self.tag_stack.pop();
+                if self.tag_stack.is_empty() {
+                    self.write_rest();
+                }
+            }
+            _ => {}
+        }
+        self.output.push_back(event);
+        self.output.pop_front().map(Ok)
+    }
+}
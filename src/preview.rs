@@ -0,0 +1,248 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use clap::Parser;
+
+use battery_icon::source::Source;
+use battery_icon::templates;
+
+use crate::{render_to, watch, Args};
+
+/// Settings for the preview subcommand.
+#[derive(clap::Args, Debug)]
+pub struct PreviewArgs {
+    /// Path to the base SVG.
+    svg: PathBuf,
+
+    /// Path to a TOML config file.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    #[arg(short, long, default_value_t = 1.0)]
+    level: f64,
+
+    /// Where to read the level from, instead of --level.
+    #[arg(short, long, value_enum, default_value_t = Source::Level)]
+    source: Source,
+
+    #[arg(short, long, default_value_t = false)]
+    charging: bool,
+
+    /// Serve the page on localhost, and reload it whenever
+    /// the template, config or reading changes,
+    /// instead of printing it once.
+    #[arg(long, default_value_t = false)]
+    serve: bool,
+
+    /// Port to serve the page on.
+    #[arg(long, default_value_t = 8000)]
+    port: u16,
+
+    /// Seconds between checks of the source with --serve.
+    #[arg(long, default_value_t = 1.0)]
+    interval: f64,
+}
+
+// The widths to show the icon at, in pixels.
+const SIZES: [u32; 6] = [16, 24, 32, 48, 64, 128];
+
+// Latest is the current rendering, numbered so that clients can tell it changed.
+#[derive(Default)]
+struct Latest {
+    generation: u64,
+    svg: Vec<u8>,
+}
+
+// run prints a page showing the rendered template at several sizes,
+// or serves it with --serve.
+pub fn run(args: &PreviewArgs) -> Result<(), Box<dyn Error>> {
+    let render_args = args.render_args()?;
+    let svg = render_once(&render_args)?;
+    if !args.serve {
+        let src = format!("data:image/svg+xml,{}", percent_encode(&svg));
+        print!("{}", page(&src, false));
+        return Ok(());
+    }
+
+    let latest = Arc::new((Mutex::new(Latest { generation: 0, svg }), Condvar::new()));
+    let listener = TcpListener::bind(("127.0.0.1", args.port))
+        .map_err(|e| format!("failed to listen on port {}: {e}", args.port))?;
+    println!("previewing at http://127.0.0.1:{}/", args.port);
+
+    let interval = Duration::try_from_secs_f64(args.interval)
+        .map_err(|e| format!("invalid --interval {}: {e}", args.interval))?;
+    let mut trigger = watch::Trigger::new(interval);
+    let files: Vec<&std::path::Path> = [Some(&args.svg), args.config.as_ref()]
+        .into_iter()
+        .flatten()
+        .filter(|path| templates::name(path).is_none())
+        .map(|path| path.as_path())
+        .collect();
+    if let Err(e) = trigger.watch_files(&files) {
+        log::warn!("not reloading on changes: {e}");
+    }
+    let watched = latest.clone();
+    thread::spawn(move || loop {
+        trigger.wait();
+        // Keep showing the last good rendering after errors.
+        let svg = match render_once(&render_args) {
+            Ok(svg) => svg,
+            Err(e) => {
+                log::error!("{e}");
+                continue;
+            }
+        };
+        let (lock, changed) = &*watched;
+        let mut current = lock.lock().unwrap_or_else(|e| e.into_inner());
+        if current.svg != svg {
+            current.svg = svg;
+            current.generation += 1;
+            changed.notify_all();
+        }
+    });
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let latest = latest.clone();
+        thread::spawn(move || {
+            if let Err(e) = respond(stream, &latest) {
+                log::warn!("preview request failed: {e}");
+            }
+        });
+    }
+    Ok(())
+}
+
+impl PreviewArgs {
+    // render_args translates the preview settings into those of a render.
+    fn render_args(&self) -> Result<Args, Box<dyn Error>> {
+        let mut argv: Vec<OsString> =
+            vec!["battery-icon".into(), self.svg.clone().into(), "-".into()];
+        if let Some(config) = &self.config {
+            argv.push("--config".into());
+            argv.push(config.clone().into());
+        }
+        let mut args = Args::try_parse_from(argv)?;
+        args.level = Some(self.level);
+        args.source = self.source;
+        args.charging = self.charging;
+        Ok(args)
+    }
+}
+
+fn render_once(args: &Args) -> Result<Vec<u8>, Box<dyn Error>> {
+    let config = args.config()?;
+    let data = args.data()?;
+    render_to(args, &config, args.read_source()?, &data, Vec::new())
+}
+
+// respond answers a single request: the page, the icon, or a stream of
+// server-sent events announcing each new rendering.
+fn respond(mut stream: TcpStream, latest: &(Mutex<Latest>, Condvar)) -> Result<(), Box<dyn Error>> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Skip the headers, up to the blank line.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim() != "" {
+        header.clear();
+    }
+    // GET /icon.svg?v=3 HTTP/1.1
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    let path = path.split('?').next().unwrap_or(path);
+    let (lock, changed) = latest;
+    match path {
+        "/" => write_response(&mut stream, "text/html", page("/icon.svg", true).as_bytes()),
+        "/icon.svg" => {
+            let svg = lock.lock().unwrap_or_else(|e| e.into_inner()).svg.clone();
+            write_response(&mut stream, "image/svg+xml", &svg)
+        }
+        "/events" => {
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n"
+            )?;
+            let mut current = lock.lock().unwrap_or_else(|e| e.into_inner());
+            loop {
+                let seen = current.generation;
+                current = changed
+                    .wait_while(current, |latest| latest.generation == seen)
+                    .unwrap_or_else(|e| e.into_inner());
+                // Writing fails once the page has gone away, ending the stream.
+                write!(stream, "data: {}\n\n", current.generation)?;
+                stream.flush()?;
+            }
+        }
+        _ => {
+            write!(
+                stream,
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+            )?;
+            Ok(())
+        }
+    }
+}
+
+fn write_response(
+    stream: &mut TcpStream,
+    content_type: &str,
+    body: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    Ok(())
+}
+
+// page shows the icon at each of the SIZES, on light and dark backgrounds.
+// When live, it reloads the icons as the server announces new renderings.
+fn page(src: &str, live: bool) -> String {
+    let mut images = String::new();
+    for size in SIZES {
+        images.push_str(&format!(
+            "<img src=\"{src}\" width=\"{size}\" title=\"{size}px\">\n"
+        ));
+    }
+    let script = if live {
+        "<script>\n\
+        new EventSource('/events').onmessage = (event) => {\n\
+        \x20 for (const img of document.images) img.src = '/icon.svg?v=' + event.data;\n\
+        };\n\
+        </script>\n"
+    } else {
+        ""
+    };
+    format!(
+        "<!DOCTYPE html>\n\
+        <html>\n<head>\n<meta charset=\"utf-8\">\n<title>battery-icon preview</title>\n\
+        <style>div {{ padding: 16px; display: flex; gap: 16px; align-items: end; }}</style>\n\
+        </head>\n<body>\n\
+        <div style=\"background: #ffffff\">\n{images}</div>\n\
+        <div style=\"background: #202020\">\n{images}</div>\n\
+        {script}</body>\n</html>\n"
+    )
+}
+
+// percent_encode escapes the bytes that are not safe in a data: URL.
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut result = String::new();
+    for &b in bytes {
+        if b.is_ascii_alphanumeric() || b"-_.~/:=;, ".contains(&b) {
+            result.push(b as char);
+        } else {
+            result.push_str(&format!("%{b:02X}"));
+        }
+    }
+    result
+}
@@ -0,0 +1,147 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use clap::ValueEnum;
+use resvg::tiny_skia::Color;
+
+use battery_icon::raster::{self, RasterArgs};
+use battery_icon::vector_drawable;
+
+/// A set of files to write for a platform, instead of a single icon.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Profile {
+    /// An Android VectorDrawable in drawable/,
+    /// and PNGs in mipmap-mdpi/ through mipmap-xxxhdpi/.
+    Android,
+    /// iconTemplate.png and iconTemplate@2x.png, in black and alpha,
+    /// for macOS menu bar trays like those of Electron and Tauri.
+    MacosTemplate,
+}
+
+// The height in points of a macOS menu bar icon, by default.
+const MACOS_TEMPLATE_HEIGHT: f32 = 16.0;
+
+// The Android screen densities, and their scale from mdpi.
+const DENSITIES: [(&str, f32); 5] = [
+    ("mdpi", 1.0),
+    ("hdpi", 1.5),
+    ("xhdpi", 2.0),
+    ("xxhdpi", 3.0),
+    ("xxxhdpi", 4.0),
+];
+
+// write writes the files of a profile for a rendered SVG into the directory,
+// named after the template.
+pub fn write(
+    profile: Profile,
+    svg: &[u8],
+    template: &Path,
+    dir: &Path,
+    raster_args: &RasterArgs,
+) -> Result<(), Box<dyn Error>> {
+    match profile {
+        Profile::Android => write_android(svg, &resource_name(template), dir, raster_args),
+        Profile::MacosTemplate => write_macos_template(svg, dir, raster_args),
+    }
+}
+
+fn write_android(
+    svg: &[u8],
+    name: &str,
+    dir: &Path,
+    raster_args: &RasterArgs,
+) -> Result<(), Box<dyn Error>> {
+    let drawable = vector_drawable::convert(svg, raster_args.width)?;
+    write_file(
+        &dir.join("drawable").join(format!("{name}.xml")),
+        drawable.as_bytes(),
+    )?;
+
+    // The mdpi size is the dp size of the drawable.
+    let width = match raster_args.width {
+        Some(width) => width,
+        None => raster::size(svg)?.0.ceil() as u32,
+    };
+    for (density, scale) in DENSITIES {
+        let args = RasterArgs {
+            width: Some((width as f32 * scale).round() as u32),
+            pad: (raster_args.pad as f32 * scale).round() as u32,
+            corner_radius: raster_args.corner_radius * scale,
+            ..raster_args.clone()
+        };
+        let png = raster::rasterize_with(svg, &args)?
+            .encode_png()
+            .map_err(|e| format!("failed to encode PNG: {e}"))?;
+        let path = dir
+            .join(format!("mipmap-{density}"))
+            .join(format!("{name}.png"));
+        write_file(&path, &png)?;
+    }
+    Ok(())
+}
+
+// write_macos_template writes the two sizes of a template image,
+// which macOS tints for the menu bar from the alpha alone.
+fn write_macos_template(
+    svg: &[u8],
+    dir: &Path,
+    raster_args: &RasterArgs,
+) -> Result<(), Box<dyn Error>> {
+    let width = match raster_args.width {
+        Some(width) => width,
+        None => {
+            let (w, h) = raster::size(svg)?;
+            // Round down, so the height doesn't round up past the menu bar's.
+            (w * MACOS_TEMPLATE_HEIGHT / h).floor() as u32
+        }
+    };
+    for (name, scale) in [("iconTemplate.png", 1), ("iconTemplate@2x.png", 2)] {
+        // A background would make the whole image opaque, so leave it out.
+        let args = RasterArgs {
+            width: Some(width * scale),
+            background: Color::TRANSPARENT,
+            pad: raster_args.pad * scale,
+            corner_radius: raster_args.corner_radius * scale as f32,
+        };
+        let mut pixmap = raster::rasterize_with(svg, &args)?;
+        for pixel in pixmap.data_mut().chunks_exact_mut(4) {
+            pixel[..3].fill(0);
+        }
+        let png = pixmap
+            .encode_png()
+            .map_err(|e| format!("failed to encode PNG: {e}"))?;
+        write_file(&dir.join(name), &png)?;
+    }
+    Ok(())
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("failed to create directory {parent:?}: {e}"))?;
+    }
+    fs::write(path, bytes).map_err(|e| format!("failed to write {path:?}: {e}"))?;
+    Ok(())
+}
+
+// resource_name makes an Android resource name from the template's file name,
+// like base_src for base-src.svg, since those may only hold [a-z0-9_].
+fn resource_name(template: &Path) -> String {
+    let stem = template
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let stem = stem.split('.').next().unwrap_or_default();
+    let name: String = stem
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9') => c,
+            _ => '_',
+        })
+        .collect();
+    match name.chars().next() {
+        Some('a'..='z') => name,
+        _ => format!("ic_{name}"),
+    }
+}
@@ -0,0 +1,71 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+
+use battery_icon::files;
+use battery_icon::matcher::{self, StackMatcher};
+use battery_icon::tag;
+
+/// Settings for the query subcommand.
+#[derive(clap::Args, Debug)]
+pub struct QueryArgs {
+    /// Path to the SVG to search.
+    svg: PathBuf,
+    /// Selector of the elements to list, like 'rect#fraction' or 'g text'.
+    selector: String,
+
+    /// Attributes to print after each element, separated by tabs.
+    #[arg(long, value_delimiter = ',')]
+    attr: Vec<String>,
+}
+
+// run prints the tag stack of each element matching the selector,
+// one per line, followed by the values of the requested attributes.
+// Missing attributes are printed as empty values.
+pub fn run(args: &QueryArgs) -> Result<(), Box<dyn Error>> {
+    let matcher = matcher::new_tag_matcher(&args.selector)
+        .map_err(|e| format!("in selector {:?}: {e}", args.selector))?;
+    let mut reader = Reader::from_reader(files::open_input(&args.svg)?);
+    let mut tag_stack = tag::Stack::default();
+    let mut buf = Vec::new();
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|e| format!("error at position {}: {e}", reader.error_position()))?;
+        match event {
+            Event::Eof => break,
+            Event::Start(e) => {
+                tag_stack.push(tag::Tag::new(&e)?);
+                print_match(&matcher, &tag_stack, &args.attr);
+            }
+            Event::Empty(e) => {
+                tag_stack.push(tag::Tag::new(&e)?);
+                print_match(&matcher, &tag_stack, &args.attr);
+                tag_stack.pop();
+            }
+            Event::End(_) => {
+                tag_stack.pop().ok_or("unexpected end tag")?;
+            }
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+fn print_match(matcher: &dyn StackMatcher, tag_stack: &[tag::Tag], attrs: &[String]) {
+    let Some(last) = tag_stack.last() else {
+        return;
+    };
+    if !matcher.matches(tag_stack) {
+        return;
+    }
+    let mut line = tag::path(tag_stack);
+    for key in attrs {
+        line.push('\t');
+        line.push_str(last.attr(key).unwrap_or(""));
+    }
+    println!("{line}");
+}
@@ -0,0 +1,185 @@
+use std::str::FromStr;
+
+use clap::ValueEnum;
+
+/// How a Ramp blends its colors between the stops.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorSpace {
+    /// Blend the red, green and blue.
+    #[default]
+    Rgb,
+    /// Blend the hue, saturation and lightness,
+    /// so that red to green passes through yellow instead of brown.
+    Hsl,
+}
+
+/// A color ramp, which colors the bar smoothly by the level,
+/// instead of in steps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ramp {
+    /// The colors, as red, green and blue from 0 to 1, by the level they are at, in order.
+    pub stops: Vec<(f64, [f64; 3])>,
+    pub space: ColorSpace,
+}
+
+// A ramp is parsed from the colors of its stops, separated by commas,
+// each either a hex code or a CSS color name, like red,orange,green.
+// A stop can be given a percentage, like red@15,orange@30,green@100.
+// Without one, the first and last stop are at 0 and 100%,
+// and the others are spread evenly between their neighbors, as in a CSS gradient.
+impl FromStr for Ramp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Ramp, String> {
+        let mut stops: Vec<(Option<f64>, [f64; 3])> = Vec::new();
+        for stop in s.split(',') {
+            let (color, at) = match stop.split_once('@') {
+                Some((color, percent)) => {
+                    let percent: f64 = percent
+                        .trim()
+                        .trim_end_matches('%')
+                        .parse()
+                        .map_err(|e| format!("failed to parse percentage in {stop:?}: {e}"))?;
+                    (color, Some(percent / 100.0))
+                }
+                None => (stop, None),
+            };
+            stops.push((at, parse_rgb(color.trim())?));
+        }
+        if stops.len() < 2 {
+            return Err(format!("expected at least two colors, got {s:?}"));
+        }
+
+        // Spread out the stops without a percentage.
+        let last = stops.len() - 1;
+        stops[0].0 = stops[0].0.or(Some(0.0));
+        stops[last].0 = stops[last].0.or(Some(1.0));
+        let mut i = 1;
+        while i < last {
+            if stops[i].0.is_some() {
+                i += 1;
+                continue;
+            }
+            let start = i - 1;
+            let end = (i..=last).find(|&j| stops[j].0.is_some()).unwrap_or(last);
+            let (from, to) = (stops[start].0.unwrap_or(0.0), stops[end].0.unwrap_or(1.0));
+            for (j, stop) in stops.iter_mut().enumerate().take(end).skip(i) {
+                let t = (j - start) as f64 / (end - start) as f64;
+                stop.0 = Some(from + (to - from) * t);
+            }
+            i = end;
+        }
+
+        let stops: Vec<(f64, [f64; 3])> = stops
+            .into_iter()
+            .map(|(at, rgb)| (at.unwrap_or(0.0), rgb))
+            .collect();
+        if stops.windows(2).any(|pair| pair[1].0 < pair[0].0) {
+            return Err(format!("expected the stops in order, got {s:?}"));
+        }
+        Ok(Ramp {
+            stops,
+            space: ColorSpace::default(),
+        })
+    }
+}
+
+impl Ramp {
+    // at returns the color of the ramp at the level, as a hex code like #80ff00.
+    // Levels before the first stop or after the last take the color of that stop.
+    pub fn at(&self, level: f64) -> String {
+        let first = &self.stops[0];
+        let last = &self.stops[self.stops.len() - 1];
+        let rgb = if level <= first.0 {
+            first.1
+        } else if level >= last.0 {
+            last.1
+        } else {
+            let i = self
+                .stops
+                .windows(2)
+                .position(|pair| level < pair[1].0)
+                .unwrap_or(0);
+            let ((from_at, from), (to_at, to)) = (self.stops[i], self.stops[i + 1]);
+            let t = (level - from_at) / (to_at - from_at);
+            match self.space {
+                ColorSpace::Rgb => mix(from, to, t),
+                ColorSpace::Hsl => hsl_to_rgb(mix_hsl(rgb_to_hsl(from), rgb_to_hsl(to), t)),
+            }
+        };
+        let [r, g, b] = rgb.map(|c| (c * 255.0).round() as u8);
+        format!("#{r:02x}{g:02x}{b:02x}")
+    }
+}
+
+// parse_rgb reads a hex code, with or without the leading '#', or a CSS color name.
+fn parse_rgb(s: &str) -> Result<[f64; 3], String> {
+    let hex = s.trim_start_matches('#');
+    let css = match hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        true => format!("#{hex}"),
+        false => s.to_string(),
+    };
+    let color: svgtypes::Color = css
+        .parse()
+        .map_err(|e| format!("expected a hex code or a color name, got {s:?}: {e}"))?;
+    Ok([color.red, color.green, color.blue].map(|c| c as f64 / 255.0))
+}
+
+fn mix(from: [f64; 3], to: [f64; 3], t: f64) -> [f64; 3] {
+    [0, 1, 2].map(|i| from[i] + (to[i] - from[i]) * t)
+}
+
+// mix_hsl blends the hues the short way around the color wheel.
+// A gray has no hue of its own, so it takes the other color's.
+fn mix_hsl(from: [f64; 3], to: [f64; 3], t: f64) -> [f64; 3] {
+    let (mut from_hue, mut to_hue) = (from[0], to[0]);
+    if from[1] == 0.0 {
+        from_hue = to_hue;
+    } else if to[1] == 0.0 {
+        to_hue = from_hue;
+    }
+    let mut turn = to_hue - from_hue;
+    if turn > 180.0 {
+        turn -= 360.0;
+    } else if turn < -180.0 {
+        turn += 360.0;
+    }
+    let [_, s, l] = mix(from, to, t);
+    [(from_hue + turn * t).rem_euclid(360.0), s, l]
+}
+
+// rgb_to_hsl returns the hue in degrees, and the saturation and lightness from 0 to 1.
+fn rgb_to_hsl([r, g, b]: [f64; 3]) -> [f64; 3] {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    let d = max - min;
+    if d == 0.0 {
+        return [0.0, 0.0, l];
+    }
+    let s = d / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        ((g - b) / d).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+    [h * 60.0, s, l]
+}
+
+fn hsl_to_rgb([h, s, l]: [f64; 3]) -> [f64; 3] {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let h = h / 60.0;
+    let x = c * (1.0 - (h.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c / 2.0;
+    [r + m, g + m, b + m]
+}
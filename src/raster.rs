@@ -0,0 +1,156 @@
+use std::error::Error;
+
+use resvg::tiny_skia::{Color, FillRule, Mask, PathBuilder, Pixmap, Transform};
+use resvg::usvg;
+
+/// Settings for PNG and ICO output.
+#[derive(clap::Args, Clone, Debug)]
+pub struct RasterArgs {
+    /// Width of a PNG or ICO icon in pixels, not counting --pad,
+    /// instead of the template's own width.
+    #[arg(long)]
+    pub width: Option<u32>,
+
+    /// Color behind a PNG or ICO icon, as a hex code like ffffff,
+    /// or transparent.
+    #[arg(long, value_name = "COLOR", value_parser = parse_background, default_value = "transparent")]
+    pub background: Color,
+
+    /// Pixels of space around a PNG or ICO icon, on each side.
+    #[arg(long, default_value_t = 0)]
+    pub pad: u32,
+
+    /// Radius in pixels of the rounded corners of a PNG or ICO icon,
+    /// including its background.
+    #[arg(long, default_value_t = 0.0)]
+    pub corner_radius: f32,
+}
+
+impl Default for RasterArgs {
+    fn default() -> RasterArgs {
+        RasterArgs {
+            width: None,
+            background: Color::TRANSPARENT,
+            pad: 0,
+            corner_radius: 0.0,
+        }
+    }
+}
+
+// parse_background reads a hex code like ffffff, #ffffff or ffffff80, or transparent.
+fn parse_background(s: &str) -> Result<Color, String> {
+    if s == "transparent" {
+        return Ok(Color::TRANSPARENT);
+    }
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+            .ok_or_else(|| format!("expected a hex code like ffffff, or transparent, got {s:?}"))
+    };
+    let alpha = match hex.len() {
+        6 => 255,
+        8 => channel(6)?,
+        _ => return Err(format!("expected 6 or 8 hex digits, got {s:?}")),
+    };
+    Ok(Color::from_rgba8(
+        channel(0)?,
+        channel(2)?,
+        channel(4)?,
+        alpha,
+    ))
+}
+
+// size returns the width and height of an SVG document, in user units.
+pub fn size(svg: &[u8]) -> Result<(f32, f32), Box<dyn Error>> {
+    let tree = usvg::Tree::from_data(svg, &usvg::Options::default())
+        .map_err(|e| format!("failed to parse SVG: {e}"))?;
+    Ok((tree.size().width(), tree.size().height()))
+}
+
+// rasterize renders an SVG document into a pixmap of the given width,
+// keeping its aspect ratio.
+pub fn rasterize(svg: &[u8], width: u32) -> Result<Pixmap, Box<dyn Error>> {
+    rasterize_with(
+        svg,
+        &RasterArgs {
+            width: Some(width),
+            ..RasterArgs::default()
+        },
+    )
+}
+
+// rasterize_with renders an SVG document with a background,
+// padding and rounded corners.
+pub fn rasterize_with(svg: &[u8], args: &RasterArgs) -> Result<Pixmap, Box<dyn Error>> {
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree =
+        usvg::Tree::from_data(svg, &options).map_err(|e| format!("failed to parse SVG: {e}"))?;
+    let size = tree.size();
+    let width = args.width.unwrap_or(size.width().ceil() as u32).max(1);
+    let scale = width as f32 / size.width();
+    let height = (size.height() * scale).ceil().max(1.0) as u32;
+    let (outer_width, outer_height) = (width + 2 * args.pad, height + 2 * args.pad);
+    let mut pixmap = Pixmap::new(outer_width, outer_height)
+        .ok_or_else(|| format!("cannot make a {outer_width}x{outer_height} image"))?;
+    pixmap.fill(args.background);
+    let pad = args.pad as f32;
+    resvg::render(
+        &tree,
+        Transform::from_scale(scale, scale).post_translate(pad, pad),
+        &mut pixmap.as_mut(),
+    );
+    if args.corner_radius > 0.0 {
+        round_corners(&mut pixmap, args.corner_radius)?;
+    }
+    Ok(pixmap)
+}
+
+// round_corners clears the pixmap outside of a rounded rectangle.
+fn round_corners(pixmap: &mut Pixmap, radius: f32) -> Result<(), Box<dyn Error>> {
+    let (w, h) = (pixmap.width() as f32, pixmap.height() as f32);
+    let r = radius.min(w / 2.0).min(h / 2.0);
+    // How far the control points of a cubic quarter circle sit from its ends.
+    let k = r * 0.552_284_8;
+    let mut pb = PathBuilder::new();
+    pb.move_to(r, 0.0);
+    pb.line_to(w - r, 0.0);
+    pb.cubic_to(w - r + k, 0.0, w, r - k, w, r);
+    pb.line_to(w, h - r);
+    pb.cubic_to(w, h - r + k, w - r + k, h, w - r, h);
+    pb.line_to(r, h);
+    pb.cubic_to(r - k, h, 0.0, h - r + k, 0.0, h - r);
+    pb.line_to(0.0, r);
+    pb.cubic_to(0.0, r - k, r - k, 0.0, r, 0.0);
+    pb.close();
+    let path = pb.finish().ok_or("failed to make the rounded corners")?;
+    let mut mask = Mask::new(pixmap.width(), pixmap.height()).ok_or("failed to make a mask")?;
+    mask.fill_path(&path, FillRule::Winding, true, Transform::identity());
+    pixmap.apply_mask(&mask);
+    Ok(())
+}
+
+// encode_ico wraps a PNG-encoded pixmap in an ICO file with a single image,
+// which Windows and most icon readers accept for any size.
+pub fn encode_ico(pixmap: &Pixmap) -> Result<Vec<u8>, Box<dyn Error>> {
+    let png = pixmap
+        .encode_png()
+        .map_err(|e| format!("failed to encode PNG: {e}"))?;
+    // Sizes of 256 and up are written as 0.
+    let dimension = |n: u32| if n >= 256 { 0 } else { n as u8 };
+    let mut ico = Vec::with_capacity(22 + png.len());
+    // ICONDIR: reserved, type 1 for icons, one image.
+    for field in [0u16, 1, 1] {
+        ico.extend(field.to_le_bytes());
+    }
+    // ICONDIRENTRY: size, no palette, 1 plane, 32 bits per pixel,
+    // then the length and offset of the PNG.
+    ico.extend([dimension(pixmap.width()), dimension(pixmap.height()), 0, 0]);
+    ico.extend(1u16.to_le_bytes());
+    ico.extend(32u16.to_le_bytes());
+    ico.extend((png.len() as u32).to_le_bytes());
+    ico.extend(22u32.to_le_bytes());
+    ico.extend(png);
+    Ok(ico)
+}
@@ -0,0 +1,49 @@
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::Path;
+
+use clap::ValueEnum;
+use serde_json::json;
+
+use battery_icon::source::{Reading, Source};
+use battery_icon::Report;
+
+/// The formats of --report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// One line of JSON per render.
+    Json,
+}
+
+// print writes a summary of a render as one line of JSON,
+// for wrapper scripts to check that it went through, like
+//   {"input":"base-src.svg","output":"battery.svg","charge":85,"matched":2,...}
+pub fn print(
+    input: &Path,
+    output: &Path,
+    source: Source,
+    reading: Reading,
+    charging: bool,
+    report: &Report,
+) -> Result<(), Box<dyn Error>> {
+    let warnings: Vec<String> = report
+        .unmatched
+        .iter()
+        .map(|selector| format!("no element matched {selector:?}"))
+        .collect();
+    let summary = json!({
+        "input": input,
+        "output": output,
+        "source": source.to_possible_value().map(|v| v.get_name().to_string()),
+        "charge": (reading.level * 100.0).round() as i64,
+        "level": reading.level,
+        "state": if charging { "charging" } else { reading.state.name() },
+        "charging": charging,
+        "matched": report.matched,
+        "modified": report.modified,
+        "warnings": warnings,
+    });
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{summary}").and_then(|()| stdout.flush())?;
+    Ok(())
+}
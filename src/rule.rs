@@ -0,0 +1,240 @@
+use std::error::Error;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::expr;
+use crate::matcher::{self, AndMatcher};
+use crate::vars::Vars;
+
+/// An action applied to the elements matching a selector,
+/// declared with [[rule]] in the config.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Rule {
+    pub selector: String,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Action {
+    /// Multiplies the width by an expression, the level by default.
+    ScaleWidth { by: Option<String> },
+    /// Sets an attribute to a value, which may contain placeholders.
+    SetAttr { attr: String, value: String },
+    /// Hides the element unless the expression is true.
+    Toggle { when: String },
+    /// Replaces the text content with a value, which may contain placeholders.
+    SetText { value: String },
+    /// Replaces a <use> with a copy of the element it references.
+    Inline,
+}
+
+// Edit is what a rule does to an element, with its values
+// already computed for the current render.
+pub enum Edit {
+    Scale { attr: String, factor: f64 },
+    Hide,
+    SetAttr { attr: String, value: String },
+    SetText(String),
+    Inline,
+}
+
+// Compiled pairs an edit with the matcher for its selector.
+pub struct Compiled {
+    pub selector: String,
+    pub matcher: AndMatcher,
+    pub edit: Option<Edit>,
+}
+
+impl Rule {
+    pub fn compile(&self, vars: &Vars) -> Result<Compiled, Box<dyn Error>> {
+        let matcher = matcher::new_tag_matcher(&self.selector)
+            .map_err(|e| format!("in rule selector {:?}: {e}", self.selector))?;
+        let edit = self
+            .action
+            .edit(vars)
+            .map_err(|e| format!("in rule for {:?}: {e}", self.selector))?;
+        Ok(Compiled {
+            selector: self.selector.clone(),
+            matcher,
+            edit,
+        })
+    }
+}
+
+// Rules can also be written on one line for --transform, like
+//   rect#fraction width*=0.9*level
+//   rect#fraction fill=#00d400
+//   text#pct text={{charge}}%
+//   #bolt toggle=charging
+//   use#bolt-ref inline=true
+// A colon can end the selector, with spaces around the operator, like
+//   rect#fraction: width *= level
+//   text#pct: text = "{{charge}}%"
+// The value starts after the first = outside the selector's brackets,
+// so it can hold any character, like text=[{{charge}}].
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Rule, String> {
+        let start =
+            value_start(s).ok_or_else(|| format!("expected SELECTOR KEY=VALUE, got {s:?}"))?;
+        let (lhs, value) = (s[..start].trim_end(), &s[start + 1..]);
+        let (lhs, scale) = match lhs.strip_suffix('*') {
+            Some(lhs) => (lhs, true),
+            None => (lhs, false),
+        };
+        if let Some(op) = lhs.chars().last().filter(|c| matches!(c, '+' | '-' | '/')) {
+            return Err(format!("expected = or *=, got {op}= in {s:?}"));
+        }
+        let (selector, key) = lhs
+            .trim_end()
+            .rsplit_once(char::is_whitespace)
+            .ok_or_else(|| format!("expected a selector before {lhs:?}"))?;
+        let selector = selector.trim_end();
+        let selector = selector.strip_suffix(':').unwrap_or(selector);
+        let (selector, key) = (selector.trim(), key.trim());
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value)
+            .to_string();
+        let action = match key {
+            "width" if scale => Action::ScaleWidth { by: Some(value) },
+            _ if scale => return Err(format!("only the width can be scaled, not {key:?}")),
+            "text" => Action::SetText { value },
+            "toggle" => Action::Toggle { when: value },
+            "inline" if value == "true" => Action::Inline,
+            "inline" => return Err(format!("expected inline=true, got {value:?}")),
+            attr => Action::SetAttr {
+                attr: attr.to_string(),
+                value,
+            },
+        };
+        Ok(Rule {
+            selector: selector.to_string(),
+            action,
+        })
+    }
+}
+
+// value_start finds the = between the key and the value,
+// the first one outside of the brackets and quotes of attribute selectors,
+// so that the value can hold any character.
+fn value_start(s: &str) -> Option<usize> {
+    let mut in_brackets = false;
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (c, quote) {
+            (_, Some(q)) if c == q => quote = None,
+            (_, Some(_)) => {}
+            ('"' | '\'', None) if in_brackets => quote = Some(c),
+            ('[', None) => in_brackets = true,
+            (']', None) => in_brackets = false,
+            ('=', None) if !in_brackets => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+impl Action {
+    // edit computes the values of the action.
+    // A toggle whose condition is true has nothing to do.
+    fn edit(&self, vars: &Vars) -> Result<Option<Edit>, Box<dyn Error>> {
+        let edit = match self {
+            Action::ScaleWidth { by } => {
+                let factor = match by {
+                    Some(by) => match expr::eval(by, vars)? {
+                        expr::Value::Number(n) => n,
+                        value => return Err(format!("{by:?} is {value:?}, not a number").into()),
+                    },
+                    None => vars.get("level")?.parse()?,
+                };
+                Edit::Scale {
+                    attr: "width".to_string(),
+                    factor,
+                }
+            }
+            Action::SetAttr { attr, value } => Edit::SetAttr {
+                attr: attr.clone(),
+                value: vars.expand(value)?.into_owned(),
+            },
+            Action::Toggle { when } => {
+                if expr::eval(when, vars)?.truthy() {
+                    return Ok(None);
+                }
+                Edit::Hide
+            }
+            Action::SetText { value } => Edit::SetText(vars.expand(value)?.into_owned()),
+            Action::Inline => Edit::Inline,
+        };
+        Ok(Some(edit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> (String, Action) {
+        let rule: Rule = s.parse().unwrap();
+        (rule.selector, rule.action)
+    }
+
+    #[test]
+    fn values_with_any_character() {
+        let (selector, action) = parse("text#x text=[{{charge}}]");
+        assert_eq!(selector, "text#x");
+        assert!(matches!(action, Action::SetText { value } if value == "[{{charge}}]"));
+
+        let (selector, action) = parse(r#"text#pct: text = "a = b c""#);
+        assert_eq!(selector, "text#pct");
+        assert!(matches!(action, Action::SetText { value } if value == "a = b c"));
+
+        let (selector, action) = parse("rect#f data-x=1=2");
+        assert_eq!(selector, "rect#f");
+        assert!(
+            matches!(action, Action::SetAttr { attr, value } if attr == "data-x" && value == "1=2")
+        );
+    }
+
+    #[test]
+    fn attribute_selectors() {
+        let (selector, action) = parse(r#"rect[inkscape:label="fill"] fill=red"#);
+        assert_eq!(selector, r#"rect[inkscape:label="fill"]"#);
+        assert!(
+            matches!(action, Action::SetAttr { attr, value } if attr == "fill" && value == "red")
+        );
+
+        let (selector, _) = parse(r#"g[data-x="a] b=c"] rect: fill = [red]"#);
+        assert_eq!(selector, r#"g[data-x="a] b=c"] rect"#);
+    }
+
+    #[test]
+    fn operators() {
+        let (selector, action) = parse("g#b rect width*=0.9*level");
+        assert_eq!(selector, "g#b rect");
+        assert!(matches!(action, Action::ScaleWidth { by: Some(by) } if by == "0.9*level"));
+        let (_, action) = parse("rect#f: width *= level");
+        assert!(matches!(action, Action::ScaleWidth { by: Some(by) } if by == "level"));
+        let (_, action) = parse("#bolt toggle=charging");
+        assert!(matches!(action, Action::Toggle { when } if when == "charging"));
+        assert!(matches!(parse("use inline=true").1, Action::Inline));
+
+        for bad in [
+            "rect#f fill*=red",
+            "rect#f width+=3",
+            "rect#f width -= 3",
+            "use inline=yes",
+            "width=3",
+            "rect#f width",
+            "rect[x=1] width",
+        ] {
+            assert!(bad.parse::<Rule>().is_err(), "{bad}");
+        }
+    }
+}
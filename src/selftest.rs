@@ -0,0 +1,71 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::{render, Args};
+
+/// Settings for the selftest subcommand.
+#[derive(clap::Args, Debug)]
+pub struct SelftestArgs {
+    /// Path to the base SVG.
+    svg: PathBuf,
+
+    /// Directory to write the icons to.
+    #[arg(short, long)]
+    out: PathBuf,
+
+    /// Path to a TOML config file.
+    #[arg(long)]
+    config: Option<PathBuf>,
+}
+
+// The charges worth looking at: empty, and just below each threshold,
+// that of --critical-charge at 5%, the critical color at 15% and the low color at 30%,
+// then a few ordinary ones.
+const CHARGES: [u32; 7] = [0, 4, 14, 29, 50, 80, 100];
+
+// Foreground colors for light and dark themes,
+// for text and for currentColor.
+const THEMES: [(&str, &str); 2] = [("light", "000000"), ("dark", "ffffff")];
+
+// run renders the template in every combination of charge,
+// charging and theme, into files named like level50-charging-dark.svg.
+pub fn run(args: &SelftestArgs) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(&args.out)
+        .map_err(|e| format!("failed to create directory {:?}: {e}", args.out))?;
+    let mut count = 0;
+    for charge in CHARGES {
+        for charging in [false, true] {
+            for (theme, foreground) in THEMES {
+                let state = if charging { "charging" } else { "discharging" };
+                let output = args.out.join(format!("level{charge}-{state}-{theme}.svg"));
+                let mut argv: Vec<OsString> = vec![
+                    "battery-icon".into(),
+                    args.svg.clone().into(),
+                    output.clone().into(),
+                    format!("--level={}", charge as f64 / 100.0).into(),
+                    format!("--foreground={foreground}").into(),
+                    format!("--color={foreground}").into(),
+                ];
+                if charging {
+                    argv.push("--charging".into());
+                }
+                if let Some(config) = &args.config {
+                    argv.push("--config".into());
+                    argv.push(config.clone().into());
+                }
+                let render_args = Args::try_parse_from(argv)?;
+                let config = render_args.config()?;
+                let data = render_args.data()?;
+                render(&render_args, &config, render_args.read_source()?, &data)
+                    .map_err(|e| format!("failed to render {output:?}: {e}"))?;
+                count += 1;
+            }
+        }
+    }
+    println!("wrote {count} icons to {:?}", args.out);
+    Ok(())
+}
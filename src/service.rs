@@ -0,0 +1,189 @@
+use std::error::Error;
+use std::ffi::OsString;
+
+use clap::{Parser, ValueEnum};
+use quick_xml::escape::escape;
+
+use battery_icon::templates;
+
+use crate::Args;
+
+/// Settings for the service subcommand.
+#[derive(clap::Args, Debug)]
+pub struct ServiceArgs {
+    /// The kind of service definition to print.
+    #[arg(long, value_enum, default_value_t = ServiceFormat::native())]
+    format: ServiceFormat,
+
+    /// The arguments for battery-icon in --watch mode,
+    /// with absolute paths.
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+    args: Vec<OsString>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ServiceFormat {
+    /// A systemd user unit, for ~/.config/systemd/user/battery-icon.service.
+    Systemd,
+    /// A launchd agent, for ~/Library/LaunchAgents/io.github.chaimleib.battery-icon.plist.
+    Launchd,
+    /// A Windows Task Scheduler task, for `schtasks /create /xml`.
+    TaskScheduler,
+}
+
+impl ServiceFormat {
+    // native returns the format of the platform this was built for.
+    fn native() -> ServiceFormat {
+        if cfg!(target_os = "macos") {
+            ServiceFormat::Launchd
+        } else if cfg!(windows) {
+            ServiceFormat::TaskScheduler
+        } else {
+            ServiceFormat::Systemd
+        }
+    }
+}
+
+// run prints a definition for a service
+// that runs battery-icon in --watch mode from login onwards.
+pub fn run(args: &ServiceArgs) -> Result<(), Box<dyn Error>> {
+    let mut argv = args.args.clone();
+    if !argv.iter().any(|arg| arg == "--watch" || arg == "-w") {
+        argv.insert(0, "--watch".into());
+    }
+    // Check the arguments now, rather than when the service fails to start.
+    let render_args =
+        Args::try_parse_from(std::iter::once("battery-icon".into()).chain(argv.clone()))?;
+    let paths = [Some(&render_args.svg), Some(&render_args.output)]
+        .into_iter()
+        .chain([render_args.config.as_ref(), render_args.data.as_ref()])
+        .flatten()
+        .filter(|path| templates::name(path).is_none());
+    for path in paths {
+        if !path.is_absolute() {
+            return Err(format!(
+                "{path:?} should be an absolute path, since the service runs from another directory"
+            )
+            .into());
+        }
+    }
+
+    let exe = std::env::current_exe().map_err(|e| format!("failed to find battery-icon: {e}"))?;
+    let mut command = vec![exe.into_os_string()];
+    command.extend(argv);
+    let command: Vec<String> = command
+        .into_iter()
+        .map(|arg| {
+            arg.into_string()
+                .map_err(|arg| format!("argument {arg:?} should be valid UTF-8"))
+        })
+        .collect::<Result<_, _>>()?;
+    let definition = match args.format {
+        ServiceFormat::Systemd => systemd_unit(&command),
+        ServiceFormat::Launchd => launchd_plist(&command),
+        ServiceFormat::TaskScheduler => scheduled_task(&command),
+    };
+    print!("{definition}");
+    Ok(())
+}
+
+fn systemd_unit(command: &[String]) -> String {
+    // systemd splits ExecStart like a shell, and expands % specifiers.
+    let exec_start: Vec<String> = command
+        .iter()
+        .map(|arg| {
+            let arg = arg.replace('%', "%%");
+            if arg.is_empty() || arg.contains([' ', '\t', '"', '\'', '\\', ';']) {
+                format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+            } else {
+                arg
+            }
+        })
+        .collect();
+    format!(
+        "[Unit]\n\
+        Description=battery-icon\n\
+        PartOf=graphical-session.target\n\
+        After=graphical-session.target\n\
+        \n\
+        [Service]\n\
+        ExecStart={}\n\
+        Restart=on-failure\n\
+        \n\
+        [Install]\n\
+        WantedBy=graphical-session.target\n",
+        exec_start.join(" ")
+    )
+}
+
+fn launchd_plist(command: &[String]) -> String {
+    let mut arguments = String::new();
+    for arg in command {
+        arguments.push_str(&format!(
+            "        <string>{}</string>\n",
+            escape(arg.as_str())
+        ));
+    }
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+        <plist version=\"1.0\">\n\
+        <dict>\n\
+        \x20   <key>Label</key>\n\
+        \x20   <string>io.github.chaimleib.battery-icon</string>\n\
+        \x20   <key>ProgramArguments</key>\n\
+        \x20   <array>\n\
+        {arguments}\
+        \x20   </array>\n\
+        \x20   <key>RunAtLoad</key>\n\
+        \x20   <true/>\n\
+        \x20   <key>KeepAlive</key>\n\
+        \x20   <true/>\n\
+        </dict>\n\
+        </plist>\n"
+    )
+}
+
+fn scheduled_task(command: &[String]) -> String {
+    // Windows programs split their command line at spaces outside of quotes.
+    let arguments: Vec<String> = command[1..]
+        .iter()
+        .map(|arg| {
+            if arg.is_empty() || arg.contains([' ', '\t', '"']) {
+                format!("\"{}\"", arg.replace('"', "\\\""))
+            } else {
+                arg.clone()
+            }
+        })
+        .collect();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <Task version=\"1.2\" xmlns=\"http://schemas.microsoft.com/windows/2004/02/mit/task\">\n\
+        \x20 <RegistrationInfo>\n\
+        \x20   <Description>battery-icon</Description>\n\
+        \x20 </RegistrationInfo>\n\
+        \x20 <Triggers>\n\
+        \x20   <LogonTrigger>\n\
+        \x20     <Enabled>true</Enabled>\n\
+        \x20   </LogonTrigger>\n\
+        \x20 </Triggers>\n\
+        \x20 <Settings>\n\
+        \x20   <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>\n\
+        \x20   <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>\n\
+        \x20   <ExecutionTimeLimit>PT0S</ExecutionTimeLimit>\n\
+        \x20   <RestartOnFailure>\n\
+        \x20     <Interval>PT1M</Interval>\n\
+        \x20     <Count>3</Count>\n\
+        \x20   </RestartOnFailure>\n\
+        \x20 </Settings>\n\
+        \x20 <Actions Context=\"Author\">\n\
+        \x20   <Exec>\n\
+        \x20     <Command>{}</Command>\n\
+        \x20     <Arguments>{}</Arguments>\n\
+        \x20   </Exec>\n\
+        \x20 </Actions>\n\
+        </Task>\n",
+        escape(command[0].as_str()),
+        escape(arguments.join(" ").as_str())
+    )
+}
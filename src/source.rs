@@ -0,0 +1,633 @@
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use clap::builder::{EnumValueParser, PossibleValue, TypedValueParser};
+use clap::ValueEnum;
+
+use crate::battery::{self, Batteries, BatterySource};
+use crate::bluez;
+use crate::mqtt;
+use crate::ramp::Ramp;
+
+/// Settings for the sources that need them.
+#[derive(clap::Args, Clone, Debug)]
+pub struct SourceArgs {
+    /// Filesystem to measure with --source disk.
+    #[arg(long, default_value = "/")]
+    pub path: std::path::PathBuf,
+
+    /// Device in /sys/class/backlight for --source brightness,
+    /// instead of the first one found.
+    #[arg(long)]
+    pub backlight: Option<String>,
+
+    /// Wireless interface for --source wifi,
+    /// instead of the first one found.
+    #[arg(long)]
+    pub interface: Option<String>,
+
+    /// Battery in /sys/class/power_supply to read with --source auto, like BAT1,
+    /// instead of the first one found.
+    #[arg(long)]
+    pub battery: Option<String>,
+
+    /// Which batteries to read with --source auto or sysfs:
+    /// combined, for one reading weighed by their design capacities,
+    /// or all or a list, for an icon each, at an output path
+    /// with {battery} in it for the name.
+    #[arg(long, value_name = "all|combined|BAT0,BAT1")]
+    pub batteries: Option<Batteries>,
+
+    /// File for --source file to read the level from, like 0.4 or 40%,
+    /// or a JSON status, which another program keeps up to date.
+    #[arg(long)]
+    pub file: Option<std::path::PathBuf>,
+
+    /// Charge percentage to show with --source auto, or without --level,
+    /// when no battery is found, like on a desktop.
+    #[arg(long)]
+    pub fallback_charge: Option<f64>,
+
+    /// Bluetooth address of the device for --source bluez,
+    /// like AA:BB:CC:DD:EE:FF.
+    #[arg(long)]
+    pub device: Option<String>,
+
+    /// URL for --source http to fetch a level or a JSON status from,
+    /// or mqtt://BROKER/TOPIC for --source mqtt to subscribe to.
+    #[arg(long)]
+    pub url: Option<String>,
+
+    /// Readings for --source mock to make up, like percent=42,state=charging,rate=-1%/s.
+    #[arg(long, value_name = "SETTINGS")]
+    pub mock: Option<battery::Mock>,
+
+    /// Seconds to wait for each fetch of --source http.
+    #[arg(long, value_name = "SECONDS", default_value_t = 5.0)]
+    pub http_timeout: f64,
+
+    /// Times to try a failed fetch of --source http again.
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    pub http_retries: u32,
+
+    /// Average the level over the last N readings in --watch mode,
+    /// so the bar doesn't twitch when the readings jitter.
+    #[arg(long, value_name = "N")]
+    pub smooth: Option<std::num::NonZeroUsize>,
+}
+
+/// Where the gauge level comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Source {
+    /// The value passed with --level,
+    /// or else the battery's, like auto.
+    Level,
+    /// The battery, from the first of the battery sources that has one.
+    Auto,
+    /// The battery, from UPower.
+    Upower,
+    /// The battery, from /sys/class/power_supply.
+    Sysfs,
+    /// A level, or a JSON status like {"percent": 40, "state": "charging"},
+    /// read from --file whenever the source is checked.
+    File,
+    /// 1-minute load average, relative to the number of CPUs.
+    Load,
+    /// CPU utilization, sampled over a short interval.
+    Cpu,
+    /// Memory in use, relative to total memory.
+    Mem,
+    /// Filesystem usage of --path.
+    Disk,
+    /// Volume of the default audio sink, from PipeWire or PulseAudio.
+    Volume,
+    /// A level read from stdin, like "0.4", "40%" or "40% [MUTED]".
+    Stdin,
+    /// Screen brightness, from /sys/class/backlight.
+    Brightness,
+    /// Wi-Fi link quality, from /proc/net/wireless.
+    Wifi,
+    /// The battery of the Bluetooth --device, like headphones, from BlueZ.
+    Bluez,
+    /// A level or a JSON status, fetched from --url,
+    /// like the battery of another machine.
+    Http,
+    /// A percentage or a JSON status, from the latest message
+    /// of the MQTT topic at --url, like one that a device publishes.
+    Mqtt,
+    /// Made-up readings from --mock, for testing and demos.
+    Mock,
+}
+
+/// A sample taken from a Source.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Reading {
+    /// From 0 to 1.
+    pub level: f64,
+    /// Whether the audio is muted.
+    pub muted: bool,
+    /// Whether the battery is charging, for the battery sources.
+    pub state: BatteryState,
+    /// Whether the machine is on AC power, if the source says.
+    pub plugged: bool,
+    /// Whether the system's battery saver is on, if the source says.
+    pub power_saver: bool,
+    /// The time until the battery is empty, or full while charging,
+    /// if the source estimates it.
+    pub time_remaining: Option<Duration>,
+    /// The power the battery is giving, or taking in while charging,
+    /// in watts, if the source says.
+    pub power: Option<f64>,
+    /// How much of its design capacity the battery still holds, from 0 to 1,
+    /// if the source says.
+    pub health: Option<f64>,
+    /// The level the battery is held at while plugged in, from 0 to 1,
+    /// if a charge limit is set.
+    pub charge_limit: Option<f64>,
+    /// The temperature of the battery in degrees Celsius, if the source says.
+    pub temperature: Option<f64>,
+    /// How many charge cycles the battery has been through, if the source says.
+    pub cycles: Option<u32>,
+    /// Whether the machine runs on AC power alone, without a battery.
+    pub ac_only: bool,
+}
+
+/// What a battery is doing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum BatteryState {
+    Charging,
+    Discharging,
+    /// Plugged in and fully charged.
+    Full,
+    /// Plugged in, but held below full, like by a charge limit.
+    NotCharging,
+    #[default]
+    Unknown,
+}
+
+impl BatteryState {
+    // name is the state as the {{state}} placeholder writes it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            BatteryState::Charging => "charging",
+            BatteryState::Discharging => "discharging",
+            BatteryState::Full => "full",
+            BatteryState::NotCharging => "not-charging",
+            BatteryState::Unknown => "unknown",
+        }
+    }
+}
+
+/// The colors of the bar, as CSS colors like #ff8000 or orange.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LevelColors {
+    /// While the level is normal, or None to keep the template's color.
+    pub normal: Option<String>,
+    /// While the level is low.
+    pub low: String,
+    /// While the level is critical.
+    pub critical: String,
+    /// While charging, whatever the level, or None to color by the level.
+    pub charging: Option<String>,
+    /// While the battery is full, whatever else, or None to color it like any other level.
+    pub full: Option<String>,
+    /// Colors blended by the level, instead of the normal, low and critical steps.
+    pub ramp: Option<Ramp>,
+}
+
+impl Default for LevelColors {
+    fn default() -> LevelColors {
+        LevelColors {
+            normal: None,
+            low: "#ff8000".to_string(),
+            critical: "#ff0000".to_string(),
+            charging: None,
+            full: None,
+            ramp: None,
+        }
+    }
+}
+
+impl From<f64> for Reading {
+    fn from(level: f64) -> Reading {
+        Reading {
+            level,
+            ..Default::default()
+        }
+    }
+}
+
+// The sources that can also be given with a setting, like file:PATH.
+const SOURCE_PREFIXES: [(&str, Source); 6] = [
+    ("file:", Source::File),
+    ("bluez:", Source::Bluez),
+    ("http://", Source::Http),
+    ("https://", Source::Http),
+    ("mqtt://", Source::Mqtt),
+    ("mock:", Source::Mock),
+];
+
+// SourceParser reads a --source, which may also be file:PATH, bluez:MAC,
+// mock:SETTINGS or a URL.
+#[derive(Clone)]
+pub struct SourceParser;
+
+impl TypedValueParser for SourceParser {
+    type Value = Source;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &OsStr,
+    ) -> Result<Source, clap::Error> {
+        if let Some(settings) = value.to_str().and_then(|value| value.strip_prefix("mock:")) {
+            // Checked here, since apply_spec can't fail.
+            settings.parse::<battery::Mock>().map_err(|e| {
+                clap::Error::raw(
+                    clap::error::ErrorKind::ValueValidation,
+                    format!("invalid --source mock:{settings}: {e}\n"),
+                )
+                .with_cmd(cmd)
+            })?;
+        }
+        if let Some((_, source)) = value.to_str().and_then(|value| {
+            SOURCE_PREFIXES
+                .iter()
+                .find(|(prefix, _)| value.starts_with(prefix))
+        }) {
+            return Ok(*source);
+        }
+        EnumValueParser::<Source>::new().parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        let values = Source::value_variants()
+            .iter()
+            .filter_map(ValueEnum::to_possible_value);
+        Some(Box::new(values))
+    }
+}
+
+impl SourceArgs {
+    // apply_spec fills in the setting given with a --source like file:PATH,
+    // as though it came with --file.
+    pub fn apply_spec(&mut self, spec: &str) {
+        if let Some(path) = spec.strip_prefix("file:") {
+            self.file = Some(path.into());
+        } else if let Some(settings) = spec.strip_prefix("mock:") {
+            self.mock = settings.parse().ok();
+        } else if let Some(address) = spec.strip_prefix("bluez:") {
+            self.device = Some(address.to_string());
+        } else if ["http://", "https://", "mqtt://"]
+            .iter()
+            .any(|scheme| spec.starts_with(scheme))
+        {
+            self.url = Some(spec.to_string());
+        }
+    }
+}
+
+impl Source {
+    // read returns the current reading of the source.
+    // The level argument is used as-is for Source::Level.
+    // Without one, the battery is read instead, or drawn on AC power if there is none,
+    // unless --fallback-charge says otherwise.
+    pub fn read(&self, level: Option<f64>, args: &SourceArgs) -> Result<Reading, Box<dyn Error>> {
+        let battery = args.battery.as_deref();
+        let combined = args.batteries == Some(Batteries::Combined);
+        match self {
+            Source::Level => match level {
+                Some(level) => battery::Fixed(level).read(),
+                None if combined => battery::Combined::default().read(),
+                None => battery::read_auto(battery, args.fallback_charge),
+            },
+            Source::Auto | Source::Sysfs if combined => battery::Combined::default().read(),
+            Source::Auto => battery::read_auto(battery, args.fallback_charge),
+            Source::Upower => battery::Upower {
+                battery: args.battery.clone(),
+            }
+            .read(),
+            Source::Sysfs => battery::Sysfs::new(args.battery.clone()).read(),
+            Source::File => {
+                let path = args.file.clone().ok_or("--source file needs a --file")?;
+                battery::StatusFile { path }.read()
+            }
+            Source::Load => read_load().map(Reading::from),
+            Source::Cpu => read_cpu().map(Reading::from),
+            Source::Mem => read_mem().map(Reading::from),
+            Source::Disk => read_disk(&args.path).map(Reading::from),
+            Source::Volume => read_volume(),
+            Source::Stdin => read_stdin(),
+            Source::Brightness => read_brightness(args.backlight.as_deref()).map(Reading::from),
+            Source::Wifi => read_wifi(args.interface.as_deref()).map(Reading::from),
+            Source::Bluez => {
+                let address = args
+                    .device
+                    .clone()
+                    .ok_or("--source bluez needs a --device")?;
+                bluez::Bluez { address }.read()
+            }
+            Source::Http => battery::Http {
+                url: args.url.clone().ok_or("--source http needs a --url")?,
+                timeout: Duration::try_from_secs_f64(args.http_timeout)
+                    .map_err(|e| format!("invalid --http-timeout {}: {e}", args.http_timeout))?,
+                retries: args.http_retries,
+            }
+            .read(),
+            Source::Mock => args
+                .mock
+                .clone()
+                .ok_or("--source mock needs --mock settings")?
+                .read(),
+            Source::Mqtt => mqtt::Mqtt {
+                url: args.url.clone().ok_or("--source mqtt needs a --url")?,
+            }
+            .read(),
+        }
+    }
+
+    // level_fill returns the color for the bar at the given level,
+    // or None if the template's color should be kept.
+    // A battery is in trouble when its level is low;
+    // the system stats are in trouble when their level is high.
+    pub fn level_fill<'a>(&self, level: f64, colors: &'a LevelColors) -> Option<&'a str> {
+        let (critical, low) = match self {
+            Source::Level
+            | Source::Auto
+            | Source::Upower
+            | Source::Sysfs
+            | Source::File
+            | Source::Bluez
+            | Source::Http
+            | Source::Mqtt
+            | Source::Mock => (level < 0.15, level < 0.3),
+            Source::Load | Source::Cpu | Source::Mem => (level >= 0.9, level >= 0.75),
+            // Filesystems routinely run fuller than memory,
+            // and only get into trouble near the end.
+            Source::Disk => (level >= 0.95, level >= 0.85),
+            // Nothing is wrong with a loud or quiet speaker.
+            Source::Volume | Source::Stdin | Source::Brightness | Source::Wifi => (false, false),
+        };
+        if critical {
+            Some(&colors.critical)
+        } else if low {
+            Some(&colors.low)
+        } else {
+            colors.normal.as_deref()
+        }
+    }
+}
+
+// read_load divides the 1-minute load average by the number of CPUs.
+// An overloaded system is shown as a full bar.
+fn read_load() -> Result<f64, Box<dyn Error>> {
+    let loadavg = fs::read_to_string("/proc/loadavg")
+        .map_err(|e| format!("/proc/loadavg should be readable: {e}"))?;
+    let load: f64 = loadavg
+        .split_whitespace()
+        .next()
+        .ok_or("/proc/loadavg was empty")?
+        .parse()
+        .map_err(|e| format!("failed to parse /proc/loadavg: {e}"))?;
+    let cpus = thread::available_parallelism().map_or(1, |n| n.get());
+    Ok((load / cpus as f64).min(1.0))
+}
+
+// read_cpu samples the aggregate cpu line of /proc/stat twice,
+// and returns the fraction of time spent busy in between.
+fn read_cpu() -> Result<f64, Box<dyn Error>> {
+    let (busy1, total1) = cpu_times()?;
+    thread::sleep(Duration::from_millis(250));
+    let (busy2, total2) = cpu_times()?;
+    // The counters can go backwards, like iowait, so they only count up from 0.
+    let (busy, total) = (busy2.saturating_sub(busy1), total2.saturating_sub(total1));
+    if total == 0 {
+        return Ok(0.0);
+    }
+    Ok((busy as f64 / total as f64).min(1.0))
+}
+
+// cpu_times returns the busy and total jiffies from /proc/stat.
+fn cpu_times() -> Result<(u64, u64), Box<dyn Error>> {
+    let stat = fs::read_to_string("/proc/stat")
+        .map_err(|e| format!("/proc/stat should be readable: {e}"))?;
+    let line = stat
+        .lines()
+        .find(|line| line.starts_with("cpu "))
+        .ok_or("/proc/stat had no cpu line")?;
+    let times = line
+        .split_whitespace()
+        .skip(1)
+        .map(str::parse::<u64>)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("failed to parse /proc/stat: {e}"))?;
+    if times.len() < 4 {
+        return Err(format!("/proc/stat cpu line is too short: {line:?}").into());
+    }
+    // Fields: user nice system idle iowait irq softirq steal ...
+    let idle = times[3] + times.get(4).unwrap_or(&0);
+    let total: u64 = times.iter().take(8).sum();
+    Ok((total - idle, total))
+}
+
+// read_mem returns the fraction of memory that is not available
+// to new processes, according to /proc/meminfo.
+fn read_mem() -> Result<f64, Box<dyn Error>> {
+    let meminfo = fs::read_to_string("/proc/meminfo")
+        .map_err(|e| format!("/proc/meminfo should be readable: {e}"))?;
+    let field = |name: &str| -> Result<f64, Box<dyn Error>> {
+        let line = meminfo
+            .lines()
+            .find(|line| line.starts_with(name))
+            .ok_or_else(|| format!("/proc/meminfo had no {name}"))?;
+        let value = line
+            .split_whitespace()
+            .nth(1)
+            .ok_or_else(|| format!("/proc/meminfo had no value for {name}"))?
+            .parse()
+            .map_err(|e| format!("failed to parse {name} in /proc/meminfo: {e}"))?;
+        Ok(value)
+    };
+    let total = field("MemTotal:")?;
+    let available = field("MemAvailable:")?;
+    if total <= 0.0 {
+        return Err("/proc/meminfo reported no memory".into());
+    }
+    Ok(1.0 - available / total)
+}
+
+// read_disk returns the fraction of the filesystem at path that is in use,
+// counting the blocks reserved for root as unavailable, like df does.
+#[cfg(unix)]
+fn read_disk(path: &Path) -> Result<f64, Box<dyn Error>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| format!("invalid path {path:?}: {e}"))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: c_path is NUL-terminated, and stat is a valid statvfs to fill in.
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        let e = std::io::Error::last_os_error();
+        return Err(format!("failed to stat filesystem at {path:?}: {e}").into());
+    }
+    let used = stat.f_blocks.saturating_sub(stat.f_bfree) as f64;
+    let usable = used + stat.f_bavail as f64;
+    if usable <= 0.0 {
+        return Ok(0.0);
+    }
+    Ok(used / usable)
+}
+
+#[cfg(not(unix))]
+fn read_disk(path: &Path) -> Result<f64, Box<dyn Error>> {
+    Err(format!("disk usage of {path:?} is only supported on unix").into())
+}
+
+// read_brightness compares the brightness of a backlight to its maximum.
+// Without a device name, it uses the first device in /sys/class/backlight.
+fn read_brightness(backlight: Option<&str>) -> Result<f64, Box<dyn Error>> {
+    let class = Path::new("/sys/class/backlight");
+    let dir = match backlight {
+        Some(name) => class.join(name),
+        None => {
+            let mut devices = fs::read_dir(class)
+                .map_err(|e| format!("{class:?} should be readable: {e}"))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("failed to list {class:?}: {e}"))?;
+            devices.sort();
+            devices
+                .into_iter()
+                .next()
+                .ok_or_else(|| format!("no backlight found in {class:?}"))?
+        }
+    };
+    let read_number = |name: &str| -> Result<f64, Box<dyn Error>> {
+        let file = dir.join(name);
+        let value = fs::read_to_string(&file)
+            .map_err(|e| format!("{file:?} should be readable: {e}"))?
+            .trim()
+            .parse()
+            .map_err(|e| format!("failed to parse {file:?}: {e}"))?;
+        Ok(value)
+    };
+    let brightness = read_number("brightness")?;
+    let max = read_number("max_brightness")?;
+    if max <= 0.0 {
+        return Err(format!("{dir:?} has no brightness range").into());
+    }
+    Ok(brightness / max)
+}
+
+// read_wifi reads the link quality of a wireless interface from /proc/net/wireless.
+// Most drivers report the quality out of 70.
+fn read_wifi(interface: Option<&str>) -> Result<f64, Box<dyn Error>> {
+    let wireless = fs::read_to_string("/proc/net/wireless")
+        .map_err(|e| format!("/proc/net/wireless should be readable: {e}"))?;
+    // Skip the two header lines, then look for lines like
+    // wlan0: 0000   54.  -56.  -256        0      0      0      0    165        0
+    for line in wireless.lines().skip(2) {
+        let Some((name, stats)) = line.split_once(':') else {
+            continue;
+        };
+        if interface.is_some_and(|interface| interface != name.trim()) {
+            continue;
+        }
+        let link = stats
+            .split_whitespace()
+            .nth(1)
+            .ok_or_else(|| format!("/proc/net/wireless had no link quality for {}", name.trim()))?;
+        let link: f64 = link
+            .trim_end_matches('.')
+            .parse()
+            .map_err(|e| format!("failed to parse link quality {link:?}: {e}"))?;
+        return Ok((link / 70.0).clamp(0.0, 1.0));
+    }
+    match interface {
+        Some(interface) => Err(format!("{interface} is not a connected wireless interface").into()),
+        None => Err("no connected wireless interface found".into()),
+    }
+}
+
+// read_volume asks wpctl for the volume of the default sink,
+// falling back to pactl for systems without PipeWire.
+// Volumes boosted past 100% are shown as a full bar.
+fn read_volume() -> Result<Reading, Box<dyn Error>> {
+    let mut reading = match command_output("wpctl", &["get-volume", "@DEFAULT_AUDIO_SINK@"]) {
+        // Volume: 0.40 [MUTED]
+        Ok(output) => parse_level_line(output.trim_start_matches("Volume:"))
+            .map_err(|e| format!("failed to parse wpctl output: {e}"))?,
+        Err(wpctl_err) => {
+            let volume = command_output("pactl", &["get-sink-volume", "@DEFAULT_SINK@"])
+                .map_err(|e| format!("{wpctl_err}; {e}"))?;
+            let mute = command_output("pactl", &["get-sink-mute", "@DEFAULT_SINK@"])?;
+            // Volume: front-left: 26214 /  40% / -23.88 dB,   front-right: ...
+            let percent = volume
+                .split('/')
+                .nth(1)
+                .ok_or_else(|| format!("failed to parse pactl volume: {volume:?}"))?;
+            let mut reading = parse_level_line(percent)
+                .map_err(|e| format!("failed to parse pactl volume: {e}"))?;
+            // Mute: yes
+            reading.muted = mute.trim() == "Mute: yes";
+            reading
+        }
+    };
+    reading.level = reading.level.min(1.0);
+    Ok(reading)
+}
+
+// read_stdin reads a single level line from stdin.
+fn read_stdin() -> Result<Reading, Box<dyn Error>> {
+    let mut line = String::new();
+    io::stdin()
+        .read_line(&mut line)
+        .map_err(|e| format!("failed to read stdin: {e}"))?;
+    parse_level_line(&line).map_err(|e| format!("failed to parse stdin: {e}").into())
+}
+
+// parse_level_line parses a level like "0.4" or "40%",
+// optionally followed by "muted" or "[MUTED]".
+pub fn parse_level_line(line: &str) -> Result<Reading, Box<dyn Error>> {
+    let mut words = line.split_whitespace();
+    let value = words.next().ok_or("no level given")?;
+    let level = match value.strip_suffix('%') {
+        Some(percent) => percent.parse::<f64>().map(|p| p / 100.0),
+        None => value.parse::<f64>(),
+    }
+    .map_err(|e| format!("invalid level {value:?}: {e}"))?;
+    let mut muted = false;
+    for word in words {
+        match word.trim_matches(['[', ']']).to_lowercase().as_str() {
+            "muted" => muted = true,
+            _ => return Err(format!("unexpected {word:?} after level").into()),
+        }
+    }
+    Ok(Reading {
+        level,
+        muted,
+        ..Default::default()
+    })
+}
+
+// command_output runs a program and returns its stdout,
+// or an error if it could not be run or did not succeed.
+pub fn command_output(program: &str, args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("failed to run {program}: {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("{program} failed with {}: {}", output.status, stderr.trim()).into());
+    }
+    Ok(String::from_utf8(output.stdout).map_err(|e| format!("{program} output: {e}"))?)
+}
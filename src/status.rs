@@ -0,0 +1,61 @@
+use std::error::Error;
+
+use serde_json::json;
+
+use battery_icon::source::{self, Reading, Source};
+use battery_icon::vars;
+
+use crate::exit::{Code, OrExit};
+
+/// Settings for the status subcommand.
+#[derive(clap::Args, Debug)]
+pub struct StatusArgs {
+    /// Where to read the level from, like the --source of a render.
+    #[arg(short, long, value_parser = source::SourceParser, default_value = "auto")]
+    pub source: Source,
+
+    #[command(flatten)]
+    pub source_args: source::SourceArgs,
+
+    /// Print the reading as a line of JSON instead.
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
+// run prints a reading of the source, without rendering anything, like
+//   85% discharging, 2h 14m left
+pub fn run(args: &StatusArgs) -> Result<(), Box<dyn Error>> {
+    let reading = args
+        .source
+        .read(None, &args.source_args)
+        .map_err(|e| format!("failed to read {:?} source: {e}", args.source))
+        .or_exit(Code::Backend)?;
+    match args.json {
+        true => println!("{}", status_json(reading)),
+        false => println!("{}", status_line(reading)),
+    }
+    Ok(())
+}
+
+fn status_line(reading: Reading) -> String {
+    if reading.ac_only {
+        return "AC power, no battery".to_string();
+    }
+    let mut line = format!("{:.0}% {}", reading.level * 100.0, reading.state.name());
+    if let Some(time) = reading.time_remaining {
+        line.push_str(&format!(", {} left", vars::format_duration(time)));
+    }
+    line
+}
+
+fn status_json(reading: Reading) -> serde_json::Value {
+    json!({
+        "charge": (reading.level * 100.0).round() as i64,
+        "level": reading.level,
+        "state": reading.state.name(),
+        "plugged": reading.plugged,
+        "ac_only": reading.ac_only,
+        "time_remaining": reading.time_remaining.map(|time| time.as_secs()),
+        "watts": reading.power,
+    })
+}
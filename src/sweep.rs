@@ -0,0 +1,54 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::batch::{self, Icon, Progress};
+
+/// Settings for the sweep subcommand.
+#[derive(clap::Args, Debug)]
+pub struct SweepArgs {
+    /// Path to the base SVG.
+    svg: PathBuf,
+
+    /// Directory to write the icons to.
+    out: PathBuf,
+
+    /// Number of charges to render, evenly spaced from 0 to 100%.
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(2..))]
+    steps: u32,
+
+    /// Path to a TOML config file.
+    #[arg(long)]
+    config: Option<PathBuf>,
+}
+
+// run renders the template at evenly spaced charges, charging and not,
+// into files named like charge25.svg and charge25-charging.svg.
+// It is a batch of its own, for a quick look rather than icons to link to.
+pub fn run(args: &SweepArgs) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(&args.out)
+        .map_err(|e| format!("failed to create directory {:?}: {e}", args.out))?;
+    let icons: Vec<Icon> = (0..args.steps)
+        .flat_map(|step| {
+            let level = step as f64 / (args.steps - 1) as f64;
+            [false, true].map(|charging| {
+                let suffix = if charging { "-charging" } else { "" };
+                let mut reading: Vec<OsString> = vec![format!("--level={level}").into()];
+                if charging {
+                    reading.push("--charging".into());
+                }
+                Icon {
+                    name: format!("charge{:.0}{suffix}.svg", level * 100.0),
+                    reading,
+                }
+            })
+        })
+        .collect();
+    let render_args: Vec<OsString> = match &args.config {
+        Some(config) => vec!["--config".into(), config.clone().into()],
+        None => Vec::new(),
+    };
+    batch::render_all(&args.svg, &args.out, icons, &render_args, Progress::Text)?;
+    Ok(())
+}
@@ -1,4 +1,7 @@
+use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
+use std::ops::Deref;
 
 use quick_xml::events::attributes::Attributes;
 use quick_xml::events::{BytesEnd, BytesStart};
@@ -8,23 +11,119 @@ use quick_xml::name::QName;
 pub struct Tag {
     pub name: String,
     pub id: String,
+    /// The attributes, with their values unescaped.
+    pub attrs: Vec<(String, String)>,
+    /// Position among its siblings, counting from 1, once pushed onto a Stack.
+    pub index: usize,
+    /// Position among its siblings with the same name, counting from 1.
+    pub type_index: usize,
 }
 
 impl Tag {
     pub fn new(b: &dyn TagBytes) -> Result<Tag, Box<dyn Error>> {
         let name = str::from_utf8(b.name().into_inner())?.to_string();
-        let id_attr = b.attributes().filter_map(|attr| attr.ok()).find(|attr| {
-            let key = str::from_utf8(attr.key.into_inner()).unwrap_or("");
-            key == "id"
-        });
-        let id = if let Some(attr) = id_attr {
-            str::from_utf8(&attr.value).unwrap_or("").to_string()
-        } else {
-            "".to_string()
+        let attrs: Vec<(String, String)> = b
+            .attributes()
+            .filter_map(|attr| attr.ok())
+            .map(|attr| {
+                let key = str::from_utf8(attr.key.into_inner()).unwrap_or("");
+                let value = attr.unescape_value().unwrap_or_default();
+                (key.to_string(), value.to_string())
+            })
+            .collect();
+        let id = attrs
+            .iter()
+            .find(|(key, _)| key == "id")
+            .map_or("", |(_, value)| value.as_str())
+            .to_string();
+        let result = Tag {
+            name,
+            id,
+            attrs,
+            index: 0,
+            type_index: 0,
         };
-        let result = Tag { name, id };
         Ok(result)
     }
+
+    pub fn attr(&self, key: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // classes returns the names in the class attribute.
+    pub fn classes(&self) -> impl Iterator<Item = &str> {
+        self.attr("class").unwrap_or("").split_whitespace()
+    }
+
+    // href returns the reference of a <use>, gradient or similar tag,
+    // from either the SVG 2 href or the legacy xlink:href.
+    pub fn href(&self) -> Option<&str> {
+        self.attr("href").or_else(|| self.attr("xlink:href"))
+    }
+}
+
+// Tags display like the selectors that match them, e.g. rect#fraction.
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.id.is_empty() {
+            write!(f, "#{}", self.id)?;
+        }
+        Ok(())
+    }
+}
+
+// path describes the tags from the root down, like "svg#svg1 > rect#fraction".
+pub fn path(tags: &[Tag]) -> String {
+    let names: Vec<String> = tags.iter().map(Tag::to_string).collect();
+    names.join(" > ")
+}
+
+// Stack holds the open tags, from the root down to the current one,
+// and numbers each tag among its siblings as it is pushed.
+#[derive(Default)]
+pub struct Stack {
+    tags: Vec<Tag>,
+    // The children counted so far under each open tag, and under the document.
+    siblings: Vec<Siblings>,
+}
+
+#[derive(Default)]
+struct Siblings {
+    count: usize,
+    of_type: HashMap<String, usize>,
+}
+
+impl Stack {
+    pub fn push(&mut self, mut tag: Tag) {
+        self.siblings
+            .resize_with(self.tags.len() + 1, Default::default);
+        let siblings = &mut self.siblings[self.tags.len()];
+        siblings.count += 1;
+        let of_type = siblings.of_type.entry(tag.name.clone()).or_default();
+        *of_type += 1;
+        tag.index = siblings.count;
+        tag.type_index = *of_type;
+        self.tags.push(tag);
+    }
+
+    pub fn pop(&mut self) -> Option<Tag> {
+        let tag = self.tags.pop()?;
+        // Forget the children of the popped tag.
+        self.siblings.truncate(self.tags.len() + 1);
+        Some(tag)
+    }
+}
+
+impl Deref for Stack {
+    type Target = [Tag];
+
+    fn deref(&self) -> &[Tag] {
+        &self.tags
+    }
 }
 
 pub trait TagBytes {
@@ -0,0 +1,32 @@
+use std::error::Error;
+use std::path::Path;
+
+// PREFIX marks the SVG path of a built-in template, like builtin:circle.
+pub const PREFIX: &str = "builtin:";
+
+// TEMPLATES are the templates built into battery-icon, by name,
+// so that it draws an icon before any SVG has been designed for it.
+pub const TEMPLATES: [(&str, &str); 4] = [
+    ("horizontal", include_str!("../templates/horizontal.svg")),
+    ("vertical", include_str!("../templates/vertical.svg")),
+    ("circle", include_str!("../templates/circle.svg")),
+    ("minimal", include_str!("../templates/minimal.svg")),
+];
+
+// name returns the name of the built-in template that a path like builtin:circle picks,
+// or None for the path of a file.
+pub fn name(path: &Path) -> Option<&str> {
+    path.to_str()?.strip_prefix(PREFIX)
+}
+
+// get returns the SVG of the built-in template with the given name.
+pub fn get(name: &str) -> Result<&'static str, Box<dyn Error>> {
+    TEMPLATES
+        .iter()
+        .find(|(template, _)| *template == name)
+        .map(|(_, svg)| *svg)
+        .ok_or_else(|| {
+            let names: Vec<_> = TEMPLATES.iter().map(|(name, _)| *name).collect();
+            format!("no built-in template {name:?}, only {}", names.join(", ")).into()
+        })
+}
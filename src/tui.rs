@@ -0,0 +1,175 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use clap::Parser;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::{cursor, execute, queue, terminal};
+use resvg::tiny_skia::Pixmap;
+
+use battery_icon::raster;
+
+use crate::{critical, render_to, Args};
+
+/// Settings for the tui subcommand.
+#[derive(clap::Args, Debug)]
+pub struct TuiArgs {
+    /// Path to the base SVG.
+    svg: PathBuf,
+
+    /// Path to a TOML config file.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// More options for the render, after --, to try out thresholds and colors,
+    /// like -- --critical-charge 15 --color-low ffaa00.
+    #[arg(last = true, allow_hyphen_values = true)]
+    render_args: Vec<OsString>,
+}
+
+// run shows the rendered template in the terminal,
+// and re-renders it as the keys change the charge and charging state.
+pub fn run(args: &TuiArgs) -> Result<(), Box<dyn Error>> {
+    // Start full, rather than at the battery's charge.
+    let mut argv = vec![
+        "battery-icon".into(),
+        args.svg.clone().into_os_string(),
+        "-".into(),
+        "--level=1".into(),
+    ];
+    if let Some(config) = &args.config {
+        argv.push("--config".into());
+        argv.push(config.clone().into_os_string());
+    }
+    argv.extend(args.render_args.iter().cloned());
+    let mut render_args = Args::try_parse_from(argv)?;
+
+    terminal::enable_raw_mode().map_err(|e| format!("failed to set up the terminal: {e}"))?;
+    let mut stdout = io::stdout();
+    let result = execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)
+        .map_err(|e| e.into())
+        .and_then(|()| event_loop(&mut render_args, &mut stdout));
+    // Restore the terminal even if the loop failed.
+    let _ = execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+    result
+}
+
+fn event_loop(args: &mut Args, stdout: &mut io::Stdout) -> Result<(), Box<dyn Error>> {
+    loop {
+        draw(args, stdout)?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let level = args.level.get_or_insert(1.0);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Left => *level -= 0.01,
+            KeyCode::Right => *level += 0.01,
+            KeyCode::Down => *level -= 0.1,
+            KeyCode::Up => *level += 0.1,
+            KeyCode::Home => *level = 0.0,
+            KeyCode::End => *level = 1.0,
+            KeyCode::Char('c') => args.charging = !args.charging,
+            _ => {}
+        }
+        // Round away the drift from adding hundredths.
+        *level = (level.clamp(0.0, 1.0) * 100.0).round() / 100.0;
+    }
+}
+
+// draw renders the template as it is now, and shows it
+// above a slider for the charge and a line of help.
+fn draw(args: &Args, stdout: &mut io::Stdout) -> Result<(), Box<dyn Error>> {
+    let (cols, rows) = terminal::size()?;
+    queue!(
+        stdout,
+        terminal::Clear(terminal::ClearType::All),
+        cursor::MoveTo(0, 0)
+    )?;
+    // Keep a few rows for the slider and the help.
+    let max_rows = rows.saturating_sub(3).max(1) as u32;
+    match render_pixmap(args, cols as u32, max_rows) {
+        Ok(pixmap) => draw_pixmap(&pixmap, stdout)?,
+        Err(e) => queue!(stdout, Print(format!("{e}\r\n")))?,
+    }
+
+    let level = args.level.unwrap_or(1.0);
+    let width = 20;
+    let filled = (level * width as f64).round() as usize;
+    let slider = format!(
+        "[{}{}] {:.0}%  {}{}",
+        "#".repeat(filled),
+        "-".repeat(width - filled),
+        level * 100.0,
+        if args.charging {
+            "charging"
+        } else {
+            "discharging"
+        },
+        if critical::is_critical(&args.critical, level, args.charging) {
+            "  critical"
+        } else {
+            ""
+        },
+    );
+    let help = "left/right: 1%  down/up: 10%  home/end: empty/full  c: charging  q: quit";
+    queue!(stdout, Print(format!("\r\n{slider}\r\n{help}")))?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// render_pixmap renders the template into a pixmap
+// that fits in the given number of terminal columns and rows.
+fn render_pixmap(args: &Args, cols: u32, rows: u32) -> Result<Pixmap, Box<dyn Error>> {
+    let config = args.config()?;
+    let data = args.data()?;
+    let svg = render_to(args, &config, args.read_source()?, &data, Vec::new())?;
+    let pixmap = raster::rasterize(&svg, cols)?;
+    // Each row of cells holds two rows of pixels.
+    if pixmap.height() <= rows * 2 {
+        return Ok(pixmap);
+    }
+    let width = cols * rows * 2 / pixmap.height();
+    raster::rasterize(&svg, width)
+}
+
+// draw_pixmap prints the pixmap with half-block characters,
+// so that each cell shows two pixels, one above the other.
+// Transparent pixels show the terminal's own background.
+fn draw_pixmap(pixmap: &Pixmap, stdout: &mut io::Stdout) -> Result<(), Box<dyn Error>> {
+    let color_at = |x: u32, y: u32| {
+        let pixel = pixmap.pixel(x, y)?.demultiply();
+        if pixel.alpha() < 128 {
+            return None;
+        }
+        Some(Color::Rgb {
+            r: pixel.red(),
+            g: pixel.green(),
+            b: pixel.blue(),
+        })
+    };
+    for y in (0..pixmap.height()).step_by(2) {
+        for x in 0..pixmap.width() {
+            match (color_at(x, y), color_at(x, y + 1)) {
+                (Some(top), Some(bottom)) => queue!(
+                    stdout,
+                    SetForegroundColor(top),
+                    SetBackgroundColor(bottom),
+                    Print('▀')
+                )?,
+                (Some(top), None) => queue!(stdout, SetForegroundColor(top), Print('▀'))?,
+                (None, Some(bottom)) => queue!(stdout, SetForegroundColor(bottom), Print('▄'))?,
+                (None, None) => queue!(stdout, Print(' '))?,
+            }
+            queue!(stdout, ResetColor)?;
+        }
+        queue!(stdout, Print("\r\n"))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::Duration;
+
+use quick_xml::escape::escape;
+
+use crate::data::{self, Data};
+use crate::locale::Locale;
+use crate::source::Reading;
+
+// Vars answers the {{name}} placeholders of a template.
+// The --var values take precedence over those from the --data file,
+// which take precedence over the built-in ones.
+pub struct Vars<'a> {
+    builtins: HashMap<&'static str, String>,
+    data: &'a Data,
+    vars: &'a [(String, String)],
+}
+
+impl<'a> Vars<'a> {
+    pub fn new(
+        reading: Reading,
+        charging: bool,
+        data: &'a Data,
+        vars: &'a [(String, String)],
+        locale: &Locale,
+    ) -> Vars<'a> {
+        let state = if charging {
+            "charging"
+        } else {
+            reading.state.name()
+        };
+        let builtins = HashMap::from([
+            ("charge", format!("{:.0}", reading.level * 100.0)),
+            (
+                "percent",
+                locale.percent(&format!("{:.0}", reading.level * 100.0)),
+            ),
+            ("level", reading.level.to_string()),
+            ("state", state.to_string()),
+            ("charging", charging.to_string()),
+            ("muted", reading.muted.to_string()),
+            ("plugged", reading.plugged.to_string()),
+            ("ac_only", reading.ac_only.to_string()),
+            ("power_saver", reading.power_saver.to_string()),
+            (
+                "time_remaining",
+                reading
+                    .time_remaining
+                    .map(format_duration)
+                    .unwrap_or_default(),
+            ),
+            (
+                "health",
+                reading
+                    .health
+                    .map(|health| format!("{:.0}", health * 100.0))
+                    .unwrap_or_default(),
+            ),
+            (
+                "charge_limit",
+                format!("{:.0}", reading.charge_limit.unwrap_or(1.0) * 100.0),
+            ),
+            (
+                "temperature",
+                reading
+                    .temperature
+                    .map(|degrees| format!("{degrees:.1}"))
+                    .unwrap_or_default(),
+            ),
+            (
+                "cycles",
+                reading
+                    .cycles
+                    .map(|cycles| cycles.to_string())
+                    .unwrap_or_default(),
+            ),
+            (
+                "watts",
+                reading
+                    .power
+                    .map(|watts| format!("{watts:.1}"))
+                    .unwrap_or_default(),
+            ),
+        ]);
+        Vars {
+            builtins,
+            data,
+            vars,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<String, Box<dyn Error>> {
+        if let Some((_, value)) = self.vars.iter().rev().find(|(var, _)| var == name) {
+            return Ok(value.clone());
+        }
+        if let Ok(value) = data::lookup(self.data, name) {
+            return Ok(value);
+        }
+        match self.builtins.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(format!("unknown placeholder {{{{{name}}}}}").into()),
+        }
+    }
+
+    // expand replaces the placeholders in an attribute value.
+    // Unknown placeholders are left as they are, with a warning,
+    // since a template may hold a literal {{ of its own, like in a <style>.
+    pub fn expand<'t>(&self, text: &'t str) -> Result<Cow<'t, str>, Box<dyn Error>> {
+        self.expand_with(text, |value| Cow::Borrowed(value))
+    }
+
+    // expand_escaped replaces the placeholders in escaped XML text,
+    // escaping the values to match.
+    pub fn expand_escaped<'t>(&self, text: &'t str) -> Result<Cow<'t, str>, Box<dyn Error>> {
+        self.expand_with(text, |value| escape(value))
+    }
+
+    fn expand_with<'t>(
+        &self,
+        text: &'t str,
+        quote: for<'v> fn(&'v str) -> Cow<'v, str>,
+    ) -> Result<Cow<'t, str>, Box<dyn Error>> {
+        if !text.contains("{{") {
+            return Ok(Cow::Borrowed(text));
+        }
+        let mut result = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            result.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let Some(end) = after.find("}}") else {
+                log::warn!("unclosed placeholder in {text:?}, leaving it as it is");
+                result.push_str(&rest[start..]);
+                return Ok(Cow::Owned(result));
+            };
+            match self.get(after[..end].trim()) {
+                Ok(value) => result.push_str(&quote(&value)),
+                Err(e) => {
+                    log::warn!("{e}, leaving it as it is");
+                    result.push_str(&rest[start..start + end + 4]);
+                }
+            }
+            rest = &after[end + 2..];
+        }
+        result.push_str(rest);
+        Ok(Cow::Owned(result))
+    }
+}
+
+// format_duration writes a time to the minute, like 2h 14m, or 45m under an hour.
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{minutes}m"),
+        (hours, minutes) => format!("{hours}h {minutes}m"),
+    }
+}
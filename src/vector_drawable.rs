@@ -0,0 +1,201 @@
+use std::error::Error;
+use std::fmt::Write;
+
+use resvg::usvg::tiny_skia_path::{self, PathSegment};
+use resvg::usvg::{self, Transform};
+
+// convert turns an SVG into an Android VectorDrawable,
+// of the given width in dp, or else of the SVG's own width.
+// The shapes, text and clip paths come through, with each path in absolute coordinates.
+// Gradients become the colour of their first stop,
+// and images, patterns, masks and filters are left out,
+// since VectorDrawable can't express them without the AAPT extensions.
+pub fn convert(svg: &[u8], width: Option<u32>) -> Result<String, Box<dyn Error>> {
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree =
+        usvg::Tree::from_data(svg, &options).map_err(|e| format!("failed to parse SVG: {e}"))?;
+    let size = tree.size();
+    let width_dp = width.map_or(size.width(), |w| w as f32);
+    let height_dp = size.height() * width_dp / size.width();
+
+    let mut xml = String::new();
+    writeln!(xml, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+    writeln!(
+        xml,
+        r#"<vector xmlns:android="http://schemas.android.com/apk/res/android""#
+    )?;
+    writeln!(
+        xml,
+        r#"    android:width="{}dp" android:height="{}dp""#,
+        number(width_dp),
+        number(height_dp)
+    )?;
+    writeln!(
+        xml,
+        r#"    android:viewportWidth="{}" android:viewportHeight="{}">"#,
+        number(size.width()),
+        number(size.height())
+    )?;
+    write_group(&mut xml, tree.root(), 1.0, 1)?;
+    writeln!(xml, "</vector>")?;
+    Ok(xml)
+}
+
+// write_group writes the children of a group.
+// VectorDrawable groups have no opacity, so it is multiplied into the colours.
+fn write_group(
+    xml: &mut String,
+    group: &usvg::Group,
+    opacity: f32,
+    depth: usize,
+) -> Result<(), Box<dyn Error>> {
+    let opacity = opacity * group.opacity().get();
+    let indent = "    ".repeat(depth);
+    let clipped = group.clip_path().is_some();
+    if let Some(clip) = group.clip_path() {
+        // A clip-path clips the rest of its group, so open one just for it.
+        let transform = group.abs_transform().pre_concat(clip.transform());
+        let mut data = String::new();
+        clip_data(&mut data, clip.root(), transform);
+        writeln!(xml, "{indent}<group>")?;
+        writeln!(xml, r#"{indent}    <clip-path android:pathData="{data}"/>"#)?;
+    }
+    let depth = if clipped { depth + 1 } else { depth };
+    for child in group.children() {
+        match child {
+            usvg::Node::Group(group) => write_group(xml, group, opacity, depth)?,
+            usvg::Node::Path(path) => write_path(xml, path, opacity, depth)?,
+            usvg::Node::Text(text) => write_group(xml, text.flattened(), opacity, depth)?,
+            usvg::Node::Image(_) => {}
+        }
+    }
+    if clipped {
+        writeln!(xml, "{indent}</group>")?;
+    }
+    Ok(())
+}
+
+// clip_data collects the path data of a clip path's shapes.
+fn clip_data(data: &mut String, group: &usvg::Group, transform: Transform) {
+    for child in group.children() {
+        match child {
+            usvg::Node::Group(group) => {
+                clip_data(data, group, transform.pre_concat(group.transform()))
+            }
+            usvg::Node::Path(path) => {
+                if let Some(path) = path.data().clone().transform(transform) {
+                    path_data(data, &path);
+                }
+            }
+            usvg::Node::Text(text) => clip_data(data, text.flattened(), transform),
+            usvg::Node::Image(_) => {}
+        }
+    }
+}
+
+fn write_path(
+    xml: &mut String,
+    path: &usvg::Path,
+    opacity: f32,
+    depth: usize,
+) -> Result<(), Box<dyn Error>> {
+    if !path.is_visible() {
+        return Ok(());
+    }
+    let transform = path.abs_transform();
+    let Some(data) = path.data().clone().transform(transform) else {
+        return Ok(());
+    };
+    let mut attrs = Vec::new();
+    let mut d = String::new();
+    path_data(&mut d, &data);
+    attrs.push(format!(r#"android:pathData="{d}""#));
+    if let Some(fill) = path.fill() {
+        if let Some(color) = paint_color(fill.paint(), opacity * fill.opacity().get()) {
+            attrs.push(format!(r#"android:fillColor="{color}""#));
+        }
+        if fill.rule() == usvg::FillRule::EvenOdd {
+            attrs.push(r#"android:fillType="evenOdd""#.to_string());
+        }
+    }
+    if let Some(stroke) = path.stroke() {
+        if let Some(color) = paint_color(stroke.paint(), opacity * stroke.opacity().get()) {
+            attrs.push(format!(r#"android:strokeColor="{color}""#));
+        }
+        // Scale the width like the path, by the geometric mean of the axes.
+        let scale = (transform.sx * transform.sy - transform.kx * transform.ky)
+            .abs()
+            .sqrt();
+        let width = stroke.width().get() * scale;
+        attrs.push(format!(r#"android:strokeWidth="{}""#, number(width)));
+        let cap = match stroke.linecap() {
+            usvg::LineCap::Butt => "butt",
+            usvg::LineCap::Round => "round",
+            usvg::LineCap::Square => "square",
+        };
+        let join = match stroke.linejoin() {
+            usvg::LineJoin::Miter | usvg::LineJoin::MiterClip => "miter",
+            usvg::LineJoin::Round => "round",
+            usvg::LineJoin::Bevel => "bevel",
+        };
+        attrs.push(format!(r#"android:strokeLineCap="{cap}""#));
+        attrs.push(format!(r#"android:strokeLineJoin="{join}""#));
+        attrs.push(format!(
+            r#"android:strokeMiterLimit="{}""#,
+            number(stroke.miterlimit().get())
+        ));
+    }
+    let indent = "    ".repeat(depth);
+    let attrs = attrs.join(&format!("\n{indent}    "));
+    writeln!(xml, "{indent}<path {attrs}/>")?;
+    Ok(())
+}
+
+// paint_color returns a paint as #AARRGGBB, if it has a colour.
+fn paint_color(paint: &usvg::Paint, opacity: f32) -> Option<String> {
+    let (color, opacity) = match paint {
+        usvg::Paint::Color(color) => (*color, opacity),
+        usvg::Paint::LinearGradient(gradient) => {
+            let stop = gradient.stops().first()?;
+            (stop.color(), opacity * stop.opacity().get())
+        }
+        usvg::Paint::RadialGradient(gradient) => {
+            let stop = gradient.stops().first()?;
+            (stop.color(), opacity * stop.opacity().get())
+        }
+        usvg::Paint::Pattern(_) => return None,
+    };
+    let alpha = (opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Some(format!(
+        "#{alpha:02X}{:02X}{:02X}{:02X}",
+        color.red, color.green, color.blue
+    ))
+}
+
+// path_data appends a path in the SVG path syntax that VectorDrawable shares.
+fn path_data(d: &mut String, path: &tiny_skia_path::Path) {
+    for segment in path.segments() {
+        if !d.is_empty() {
+            d.push(' ');
+        }
+        let p = |p: tiny_skia_path::Point| format!("{},{}", number(p.x), number(p.y));
+        match segment {
+            PathSegment::MoveTo(a) => d.push_str(&format!("M{}", p(a))),
+            PathSegment::LineTo(a) => d.push_str(&format!("L{}", p(a))),
+            PathSegment::QuadTo(a, b) => d.push_str(&format!("Q{} {}", p(a), p(b))),
+            PathSegment::CubicTo(a, b, c) => d.push_str(&format!("C{} {} {}", p(a), p(b), p(c))),
+            PathSegment::Close => d.push('Z'),
+        }
+    }
+}
+
+// number formats a coordinate with up to three decimals.
+fn number(n: f32) -> String {
+    let s = format!("{n:.3}");
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    match s {
+        "-0" => "0".to_string(),
+        s => s.to_string(),
+    }
+}
@@ -0,0 +1,144 @@
+use std::error::Error;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+// Change is what prompted a check.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Change {
+    // The source announced a new reading.
+    Source,
+    // The template or config was saved.
+    Files,
+}
+
+// Trigger decides when watch mode should check the source again:
+// after the interval has passed, or when a watched event arrives first.
+pub struct Trigger {
+    interval: Duration,
+    sender: Sender<Change>,
+    events: Receiver<Change>,
+    // Kept alive for as long as the files should be watched.
+    watcher: Option<RecommendedWatcher>,
+}
+
+impl Trigger {
+    pub fn new(interval: Duration) -> Trigger {
+        let (sender, events) = mpsc::channel();
+        Trigger {
+            interval,
+            sender,
+            events,
+            watcher: None,
+        }
+    }
+
+    // watch_command runs a monitoring program in the background,
+    // and treats each line of its output accepted by is_event as an event.
+    pub fn watch_command(
+        &self,
+        program: &str,
+        args: &[&str],
+        is_event: fn(&str) -> bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("failed to run {program}: {e}"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| format!("failed to read output of {program}"))?;
+        let sender = self.sender.clone();
+        let program = program.to_string();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if is_event(&line) && sender.send(Change::Source).is_err() {
+                    break;
+                }
+            }
+            // Once the monitor exits, the interval takes over.
+            let _ = child.wait();
+            log::warn!("{program} exited, falling back to polling");
+        });
+        Ok(())
+    }
+
+    // notifier returns a function that asks for a check,
+    // for sources that announce their changes some other way.
+    pub fn notifier(&self) -> impl Fn() + Send + 'static {
+        let sender = self.sender.clone();
+        move || {
+            let _ = sender.send(Change::Source);
+        }
+    }
+
+    // watch_files sends an event whenever one of the files is written.
+    // It watches their directories rather than the files themselves,
+    // since many editors save by replacing the file.
+    pub fn watch_files(&mut self, paths: &[&Path]) -> Result<(), Box<dyn Error>> {
+        let mut files = Vec::new();
+        for path in paths {
+            let file = path
+                .canonicalize()
+                .map_err(|e| format!("failed to watch {path:?}: {e}"))?;
+            files.push(file);
+        }
+        let targets = files.clone();
+        let sender = self.sender.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                if !matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                ) {
+                    return;
+                }
+                if event.paths.iter().any(|path| targets.contains(path)) {
+                    let _ = sender.send(Change::Files);
+                }
+            })
+            .map_err(|e| format!("failed to watch files: {e}"))?;
+        let mut dirs: Vec<PathBuf> = files
+            .iter()
+            .filter_map(|file| file.parent().map(Path::to_path_buf))
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        for dir in &dirs {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(|e| format!("failed to watch {dir:?}: {e}"))?;
+        }
+        self.watcher = Some(watcher);
+        Ok(())
+    }
+
+    // wait blocks until the next check is due,
+    // and reports whether any watched files changed in the meantime.
+    // Bursts of events are coalesced into a single check.
+    pub fn wait(&self) -> bool {
+        let Ok(change) = self.events.recv_timeout(self.interval) else {
+            return false;
+        };
+        let mut files_changed = change == Change::Files;
+        while let Ok(change) = self.events.try_recv() {
+            files_changed |= change == Change::Files;
+        }
+        files_changed
+    }
+}
@@ -0,0 +1,89 @@
+use std::error::Error;
+use std::time::Duration;
+
+use crate::source::{BatteryState, Reading};
+
+// PowerStatus is SYSTEM_POWER_STATUS, as GetSystemPowerStatus fills it in.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PowerStatus {
+    // 0 on battery, 1 on AC power, 255 if unknown.
+    pub ac_line_status: u8,
+    // Bits of 8 for charging, 128 for no battery; all of them if unknown.
+    pub battery_flag: u8,
+    // 0 to 100, or 255 if unknown.
+    pub battery_life_percent: u8,
+    // 1 while battery saver is on.
+    pub system_status_flag: u8,
+    // Seconds until empty, or u32::MAX if unknown, like while plugged in.
+    pub battery_life_time: u32,
+    pub battery_full_life_time: u32,
+}
+
+const CHARGING: u8 = 8;
+const NO_BATTERY: u8 = 128;
+const UNKNOWN: u8 = 255;
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetSystemPowerStatus(status: *mut PowerStatus) -> i32;
+}
+
+// PowerStatusApi asks Windows for the status of the batteries,
+// which it reports combined, so none can be picked by name.
+pub struct PowerStatusApi {
+    pub battery: Option<String>,
+}
+
+#[cfg(windows)]
+impl crate::battery::BatterySource for PowerStatusApi {
+    fn name(&self) -> &'static str {
+        "windows"
+    }
+
+    fn read(&self) -> Result<Reading, Box<dyn Error>> {
+        if let Some(battery) = &self.battery {
+            return Err(format!("can't read battery {battery:?} alone").into());
+        }
+        let mut status = PowerStatus::default();
+        // SAFETY: PowerStatus has the layout of SYSTEM_POWER_STATUS,
+        // and outlives the call.
+        if unsafe { GetSystemPowerStatus(&mut status) } == 0 {
+            let e = std::io::Error::last_os_error();
+            return Err(format!("GetSystemPowerStatus failed: {e}").into());
+        }
+        status.reading()
+    }
+}
+
+impl PowerStatus {
+    // reading translates the status into a Reading.
+    pub fn reading(&self) -> Result<Reading, Box<dyn Error>> {
+        if self.battery_flag != UNKNOWN && self.battery_flag & NO_BATTERY != 0 {
+            return Err("no system battery".into());
+        }
+        if self.battery_life_percent == UNKNOWN {
+            return Err("the charge is unknown".into());
+        }
+        let charging = self.battery_flag != UNKNOWN && self.battery_flag & CHARGING != 0;
+        let state = match (charging, self.ac_line_status) {
+            (true, _) => BatteryState::Charging,
+            (false, 0) => BatteryState::Discharging,
+            (false, 1) if self.battery_life_percent >= 100 => BatteryState::Full,
+            (false, 1) => BatteryState::NotCharging,
+            _ => BatteryState::Unknown,
+        };
+        Ok(Reading {
+            level: self.battery_life_percent as f64 / 100.0,
+            state,
+            plugged: self.ac_line_status == 1,
+            power_saver: self.system_status_flag == 1,
+            time_remaining: match self.battery_life_time {
+                u32::MAX => None,
+                seconds => Some(Duration::from_secs(seconds.into())),
+            },
+            ..Default::default()
+        })
+    }
+}
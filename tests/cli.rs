@@ -0,0 +1,419 @@
+// Runs the battery-icon binary for how its arguments combine,
+// and what it exits with.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use flate2::read::GzDecoder;
+
+fn repo_file(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(name)
+}
+
+// run runs battery-icon with the arguments, writing to a temporary SVG,
+// and returns what it printed and exited with.
+fn run(name: &str, args: &[&str]) -> Output {
+    let output = std::env::temp_dir().join(format!(
+        "battery-icon-cli-{name}-{}.svg",
+        std::process::id()
+    ));
+    let result = Command::new(env!("CARGO_BIN_EXE_battery-icon"))
+        .args(args)
+        .arg(&output)
+        .output()
+        .unwrap();
+    let _ = fs::remove_file(&output);
+    result
+}
+
+#[test]
+fn template_without_bar() {
+    let wifi = repo_file("wifi-src.svg");
+    let wifi = wifi.to_str().unwrap();
+    let result = run("without-bar", &["--level", "0.5", wifi]);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(result.status.success(), "{stderr}");
+    assert!(
+        stderr.contains(r#"no element matched "rect#fraction""#),
+        "{stderr}"
+    );
+
+    let result = run(
+        "missing-selector",
+        &["--level", "0.5", "--selector", "rect#bar", wifi],
+    );
+    assert_eq!(result.status.code(), Some(5));
+}
+
+// watch starts the watch subcommand with the arguments, writing to a temporary SVG,
+// waits for it to write the icon, and stops it.
+fn watch(name: &str, args: &[&str]) {
+    let output = std::env::temp_dir().join(format!(
+        "battery-icon-cli-{name}-{}.svg",
+        std::process::id()
+    ));
+    let mut watch = Command::new(env!("CARGO_BIN_EXE_battery-icon"))
+        .arg("watch")
+        .args(args)
+        .arg(&output)
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let started = Instant::now();
+    while !output.exists() && started.elapsed() < Duration::from_secs(10) {
+        if let Some(status) = watch.try_wait().unwrap() {
+            let mut stderr = String::new();
+            watch
+                .stderr
+                .take()
+                .unwrap()
+                .read_to_string(&mut stderr)
+                .unwrap();
+            panic!("the watch exited with {status}: {stderr}");
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    watch.kill().unwrap();
+    watch.wait().unwrap();
+    assert!(output.exists(), "the watch wrote no icon");
+    fs::remove_file(&output).unwrap();
+}
+
+#[test]
+fn watch_with_critical_action() {
+    let base = repo_file("base-src.svg");
+    let base = base.to_str().unwrap();
+    let critical = ["--on-critical-action", "suspend", "--critical-dry-run"];
+    let mut args = critical.to_vec();
+    args.extend(["--level", "0.5", base]);
+    watch("watch-critical", &args);
+
+    // Rendering once has nothing to act on.
+    let result = run("critical-once", &args);
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(
+        stderr.contains("--on-critical-action needs --watch"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn watch_with_journal() {
+    let journal = std::env::temp_dir().join(format!(
+        "battery-icon-cli-journal-{}.log",
+        std::process::id()
+    ));
+    let journal = journal.to_str().unwrap();
+    let base = repo_file("base-src.svg");
+    let args = [
+        "--charge",
+        "50",
+        "--journal",
+        journal,
+        base.to_str().unwrap(),
+    ];
+    watch("watch-journal", &args);
+    let _ = fs::remove_file(journal);
+
+    let result = run("journal-once", &args);
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("--journal needs --watch"), "{stderr}");
+}
+
+#[test]
+fn watch_in_place() {
+    let template = std::env::temp_dir().join(format!(
+        "battery-icon-cli-watch-in-place-{}.svg",
+        std::process::id()
+    ));
+    fs::copy(repo_file("base-src.svg"), &template).unwrap();
+    let before = fs::read(&template).unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_battery-icon"))
+        .arg("watch")
+        .arg(&template)
+        .args(["--in-place", "--charge", "50"])
+        .output()
+        .unwrap();
+    let after = fs::read(&template).unwrap();
+    fs::remove_file(&template).unwrap();
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(
+        stderr.contains("--in-place can't be used with --watch"),
+        "{stderr}"
+    );
+    assert!(before == after, "the watch overwrote its template");
+}
+
+#[test]
+fn batch_archive() {
+    let dir = std::env::temp_dir().join(format!("battery-icon-cli-batch-{}", std::process::id()));
+    let base = repo_file("base-src.svg");
+    for archive in ["icons.tar.gz", "icons.zip"] {
+        let status = Command::new(env!("CARGO_BIN_EXE_battery-icon"))
+            .args(["batch", "--step", "50", "--states", "charging"])
+            .arg("--archive")
+            .arg(dir.join(archive))
+            .arg(&base)
+            .arg(dir.join("icons"))
+            .status()
+            .unwrap();
+        assert!(status.success(), "battery-icon batch failed with {status}");
+    }
+    let icon = fs::read(dir.join("icons/battery-50-charging.svg")).unwrap();
+
+    let mut tar = Vec::new();
+    GzDecoder::new(File::open(dir.join("icons.tar.gz")).unwrap())
+        .read_to_end(&mut tar)
+        .unwrap();
+    // Each file is a header block, then its bytes padded out to a block.
+    let blocks = |len: usize| 512 + len.div_ceil(512) * 512;
+    let mut at = 0;
+    let mut names = Vec::new();
+    while tar[at] != 0 {
+        let name = &tar[at..at + 100];
+        let name = String::from_utf8_lossy(&name[..name.iter().position(|&b| b == 0).unwrap()]);
+        let size = std::str::from_utf8(&tar[at + 124..at + 135]).unwrap();
+        let size = usize::from_str_radix(size, 8).unwrap();
+        if name == "battery-50-charging.svg" {
+            assert_eq!(&tar[at + 512..at + 512 + size], icon.as_slice());
+        }
+        names.push(name.into_owned());
+        at += blocks(size);
+    }
+    assert_eq!(
+        names,
+        [
+            "battery-0-charging.svg",
+            "battery-50-charging.svg",
+            "battery-100-charging.svg"
+        ]
+    );
+
+    let zip = fs::read(dir.join("icons.zip")).unwrap();
+    assert!(zip.starts_with(b"PK\x03\x04"));
+    // The end of the central directory counts the files.
+    let end = &zip[zip.len() - 22..];
+    assert!(end.starts_with(b"PK\x05\x06"));
+    assert_eq!(u16::from_le_bytes([end[10], end[11]]), 3);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn batch_progress_json() {
+    let dir =
+        std::env::temp_dir().join(format!("battery-icon-cli-progress-{}", std::process::id()));
+    let result = Command::new(env!("CARGO_BIN_EXE_battery-icon"))
+        .args(["batch", "--step", "50", "--progress", "json"])
+        .arg(repo_file("base-src.svg"))
+        .arg(&dir)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(result.status.success());
+    let lines: Vec<serde_json::Value> = String::from_utf8(result.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 7);
+    assert_eq!(lines[0]["event"], "icon");
+    assert_eq!(lines[0]["total"], 6);
+    let done = &lines[6];
+    assert_eq!(done["event"], "done");
+    assert_eq!(done["files"], 6);
+    let bytes: u64 = lines[..6]
+        .iter()
+        .map(|line| line["bytes"].as_u64().unwrap())
+        .sum();
+    assert_eq!(done["bytes"], bytes);
+}
+
+#[test]
+fn template_flag() {
+    let output = std::env::temp_dir().join(format!(
+        "battery-icon-cli-template-{}.svg",
+        std::process::id()
+    ));
+    let status = Command::new(env!("CARGO_BIN_EXE_battery-icon"))
+        .args(["--level", "0.5", "--template", "circle"])
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success(), "battery-icon failed with {status}");
+    let svg = fs::read_to_string(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+    assert!(svg.contains(r#"<clipPath id="dial">"#), "{svg}");
+
+    // The template takes the place of the SVG path, so a second path is one too many.
+    let base = repo_file("base-src.svg");
+    let result = run(
+        "template-and-svg",
+        &[
+            "--level",
+            "0.5",
+            "--template",
+            "circle",
+            base.to_str().unwrap(),
+        ],
+    );
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(
+        stderr.contains("--template takes the place of the SVG path"),
+        "{stderr}"
+    );
+}
+
+// temp_dir makes an empty directory of its own for the test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("battery-icon-cli-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// in_place renders the SVG over itself, at a charge of 50%,
+// and checks that no temporary file is left behind.
+fn in_place(svg: &Path) -> Output {
+    let result = Command::new(env!("CARGO_BIN_EXE_battery-icon"))
+        .args(["--charge", "50", "--in-place"])
+        .arg(svg)
+        .output()
+        .unwrap();
+    let names: Vec<_> = fs::read_dir(svg.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(names, [svg.file_name().unwrap()]);
+    result
+}
+
+#[test]
+fn in_place_rewrites() {
+    let dir = temp_dir("in-place");
+    let svg = dir.join("battery.svg");
+    let template = r#"<svg><rect id="fraction" data-full-width="40" width="40"/></svg>"#;
+    fs::write(&svg, template).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&svg, fs::Permissions::from_mode(0o640)).unwrap();
+    }
+    let result = in_place(&svg);
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+    let rendered = fs::read_to_string(&svg).unwrap();
+    assert!(rendered.contains(r#"width="20""#), "{rendered}");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&svg).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    // Rendering it again comes out the same, so the file is left alone.
+    let modified = fs::metadata(&svg).unwrap().modified().unwrap();
+    thread::sleep(Duration::from_millis(20));
+    let result = in_place(&svg);
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+    assert_eq!(fs::read_to_string(&svg).unwrap(), rendered);
+    assert_eq!(fs::metadata(&svg).unwrap().modified().unwrap(), modified);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn in_place_failure() {
+    let dir = temp_dir("in-place-failure");
+    let svg = dir.join("battery.svg");
+    let template = r#"<svg><rect id="fraction" width="-4"/><text>{{charge}}</text></svg>"#;
+    fs::write(&svg, template).unwrap();
+    let result = in_place(&svg);
+    assert!(!result.status.success());
+    assert_eq!(fs::read_to_string(&svg).unwrap(), template);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn check_exit_codes() {
+    let dir = temp_dir("check");
+    let svg = dir.join("battery.svg");
+    fs::write(
+        &svg,
+        r#"<svg><rect id="fraction" data-full-width="40" width="40"/></svg>"#,
+    )
+    .unwrap();
+    let check = || {
+        Command::new(env!("CARGO_BIN_EXE_battery-icon"))
+            .args(["--charge", "50", "--check"])
+            .arg(&svg)
+            .output()
+            .unwrap()
+    };
+    let result = check();
+    assert_eq!(result.status.code(), Some(1));
+    let diff = String::from_utf8(result.stdout).unwrap();
+    assert!(
+        diff.ends_with(
+            "@@ -2,4 +2,4 @@\n svg > rect#fraction\n     id=\"fraction\"\n     data-full-width=\"40\"\n\
+             -    width=\"40\"\n+    width=\"20\"\n"
+        ),
+        "{diff}"
+    );
+
+    fs::write(
+        &svg,
+        r#"<svg><rect id="fraction" data-full-width="40" width="20"/></svg>"#,
+    )
+    .unwrap();
+    let result = check();
+    assert_eq!(result.status.code(), Some(0));
+    assert!(result.stdout.is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn svgz_round_trip() {
+    let dir = temp_dir("svgz");
+    let template = dir.join("battery-src.svgz");
+    let mut gz = flate2::write::GzEncoder::new(
+        File::create(&template).unwrap(),
+        flate2::Compression::default(),
+    );
+    std::io::Write::write_all(
+        &mut gz,
+        br#"<svg><rect id="fraction" width="40"/><text id="pct">?</text></svg>"#,
+    )
+    .unwrap();
+    gz.finish().unwrap();
+
+    let output = dir.join("battery.svgz");
+    let status = Command::new(env!("CARGO_BIN_EXE_battery-icon"))
+        .args(["--charge", "50"])
+        .arg(&template)
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success(), "battery-icon failed with {status}");
+    let mut svg = String::new();
+    GzDecoder::new(File::open(&output).unwrap())
+        .read_to_string(&mut svg)
+        .unwrap();
+    assert_eq!(
+        svg,
+        r#"<svg><rect id="fraction" width="20"/><text id="pct">50%</text></svg>"#
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
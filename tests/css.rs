@@ -0,0 +1,35 @@
+// Checks that the built-in colour transforms win over
+// !important rules in the template's <style> blocks.
+
+use std::error::Error;
+
+use quick_xml::reader::Reader;
+use quick_xml::writer::Writer;
+
+use battery_icon::source::Reading;
+use battery_icon::{data, read_events, Options, Render};
+
+#[test]
+fn important_fill_is_overridden() -> Result<(), Box<dyn Error>> {
+    let options = Options {
+        foreground: "ffffff".to_string(),
+        ..Default::default()
+    };
+    let data = data::Data::new();
+    // Below 15%, the level source turns the fraction the critical red.
+    let render = Render::new(&options, Reading::from(0.1), &data, &[], [])?;
+    let template = r#"<svg><style>.fill { fill: green !important } tspan { fill: blue }</style><rect id="fraction" class="fill" width="40"/><text><tspan>10%</tspan></text></svg>"#;
+    let mut writer = Writer::new(Vec::new());
+    for event in render.transform(read_events(Reader::from_str(template))) {
+        writer.write_event(event?)?;
+    }
+    let svg = String::from_utf8(writer.into_inner())?;
+    assert!(
+        svg.contains(
+            r#"<rect id="fraction" class="fill" width="4" style="fill:#ff0000 !important"/>"#
+        ),
+        "{svg}"
+    );
+    assert!(svg.contains(r#"<tspan style="fill:#ffffff">"#), "{svg}");
+    Ok(())
+}
@@ -0,0 +1,139 @@
+// Composes filters of its own around battery-icon's transforms,
+// through the library's iterator API.
+
+use std::error::Error;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+use quick_xml::writer::Writer;
+
+use battery_icon::source::Reading;
+use battery_icon::{data, read_events, rule, templates, xml_reader, Options, Render, Report};
+
+const TEMPLATE: &str = r#"<svg><!-- icon --><rect id="bar" data-bind-width="10 * level"/><text id="label">{{charge}}%</text></svg>"#;
+
+#[test]
+fn filters_before_and_after() -> Result<(), Box<dyn Error>> {
+    let options = Options::default();
+    let data = data::Data::new();
+    let render = Render::new(&options, Reading::from(0.5), &data, &[], [])?;
+
+    // Before: rename the label, so that battery-icon sees the new id.
+    let events = read_events(Reader::from_str(TEMPLATE)).map(|event| match event? {
+        Event::Start(e) if e.name().as_ref() == b"text" => {
+            let mut tag = BytesStart::new("text");
+            tag.push_attribute(("id", "percent"));
+            Ok(Event::Start(tag))
+        }
+        event => Ok(event),
+    });
+    // After: drop the comments from the rendered icon.
+    let events = render
+        .transform(events)
+        .filter(|event| !matches!(event, Ok(Event::Comment(_))));
+
+    let mut writer = Writer::new(Vec::new());
+    for event in events {
+        writer.write_event(event?)?;
+    }
+    let svg = String::from_utf8(writer.into_inner())?;
+    assert_eq!(
+        svg,
+        r#"<svg><rect id="bar" data-bind-width="10 * level" width="5"/><text id="percent">50%</text></svg>"#
+    );
+    Ok(())
+}
+
+#[test]
+fn error_ends_the_stream() -> Result<(), Box<dyn Error>> {
+    let options = Options::default();
+    let data = data::Data::new();
+    let render = Render::new(&options, Reading::from(0.5), &data, &[], [])?;
+    let events = read_events(Reader::from_str("<svg><g></svg>"));
+    let results: Vec<_> = render.transform(events).collect();
+    assert!(results.last().is_some_and(|r| r.is_err()));
+    assert_eq!(results.iter().filter(|r| r.is_err()).count(), 1);
+    Ok(())
+}
+
+#[test]
+fn bad_level_and_width() -> Result<(), Box<dyn Error>> {
+    let options = Options::default();
+    let data = data::Data::new();
+    for level in [-0.1, 1.5, f64::NAN] {
+        assert!(Render::new(&options, Reading::from(level), &data, &[], []).is_err());
+    }
+    let render = Render::new(&options, Reading::from(0.5), &data, &[], [])?;
+    let events = read_events(Reader::from_str(
+        r#"<svg><g id="battery"><rect id="fraction" width="-4"/></g></svg>"#,
+    ));
+    let error = render
+        .transform(events)
+        .find_map(Result::err)
+        .ok_or("expected an error")?;
+    assert!(
+        error
+            .to_string()
+            .contains("svg > g#battery > rect#fraction"),
+        "{error}"
+    );
+    Ok(())
+}
+
+#[test]
+fn render_report() -> Result<(), Box<dyn Error>> {
+    let options = Options::default();
+    let data = data::Data::new();
+    let rules: Vec<rule::Rule> = vec![
+        "text#pct text={{charge}}%".parse()?,
+        "#nope fill=red".parse()?,
+    ];
+    let render = Render::new(&options, Reading::from(0.5), &data, &[], &rules)?;
+    let template = r#"<svg><rect id="fraction" width="8"/><text id="pct">?</text><g/></svg>"#;
+    for event in render.transform(read_events(Reader::from_str(template))) {
+        event?;
+    }
+    assert_eq!(
+        render.report(),
+        Report {
+            matched: 1,
+            modified: 2,
+            bar: 1,
+            unmatched: vec!["#nope".to_string()],
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn lenient_xml() -> Result<(), Box<dyn Error>> {
+    let template = r#"<svg><rect id="fraction" width="10"></Rect><g><text>a & b</text></svg>"#;
+    let mut writer = Writer::new(Vec::new());
+    for event in read_events(xml_reader(template.as_bytes(), false)) {
+        writer.write_event(event?)?;
+    }
+    let svg = String::from_utf8(writer.into_inner())?;
+    assert_eq!(
+        svg,
+        r#"<svg><rect id="fraction" width="10"></rect><g><text>a &amp; b</text></g></svg>"#
+    );
+    let strict: Result<Vec<_>, _> = read_events(xml_reader(template.as_bytes(), true)).collect();
+    assert!(strict.is_err());
+    let unclosed: Result<Vec<_>, _> = read_events(xml_reader(&b"<svg><g>"[..], true)).collect();
+    assert!(unclosed.is_err());
+    Ok(())
+}
+
+#[test]
+fn builtin_templates() -> Result<(), Box<dyn Error>> {
+    let options = Options::default();
+    let data = data::Data::new();
+    for (name, svg) in templates::TEMPLATES {
+        let render = Render::new(&options, Reading::from(0.5), &data, &[], [])?;
+        for event in render.transform(read_events(Reader::from_str(svg))) {
+            event.map_err(|e| format!("builtin:{name}: {e}"))?;
+        }
+        assert_eq!(render.report().bar, 1, "builtin:{name}");
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+// Runs --watch --source auto against a fake upower,
+// which announces a scripted series of charges through `upower --monitor`.
+#![cfg(unix)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The fake answers the upower commands that battery-icon runs,
+// reading the charge from a state file that --monitor updates
+// from the script, announcing each change.
+const FAKE_UPOWER: &str = r#"#!/bin/sh
+dir="$(dirname "$0")"
+case "$1" in
+--enumerate)
+    echo /org/freedesktop/UPower/devices/battery_BAT0
+    ;;
+--show-info)
+    echo "  native-path:          BAT0"
+    echo "  present:              yes"
+    echo "  percentage:           $(cat "$dir/percentage")%"
+    ;;
+--monitor)
+    while read -r percent; do
+        sleep 0.5
+        echo "$percent" > "$dir/percentage"
+        printf '[00:00:00.000]\tdevice changed:     /org/freedesktop/UPower/devices/battery_BAT0\n'
+    done < "$dir/script"
+    ;;
+esac
+"#;
+
+// Harness holds a directory with the fake upower, and the watch using it.
+struct Harness {
+    dir: PathBuf,
+    watch: Option<Child>,
+}
+
+impl Harness {
+    fn new(name: &str, start: u32, script: &[u32]) -> Harness {
+        let dir = std::env::temp_dir().join(format!("battery-icon-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let upower = dir.join("upower");
+        fs::write(&upower, FAKE_UPOWER).unwrap();
+        fs::set_permissions(&upower, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.join("percentage"), format!("{start}\n")).unwrap();
+        let script: Vec<String> = script.iter().map(|p| format!("{p}\n")).collect();
+        fs::write(dir.join("script"), script.concat()).unwrap();
+        Harness { dir, watch: None }
+    }
+
+    fn output(&self) -> PathBuf {
+        self.dir.join("battery.svg")
+    }
+
+    // start runs the watch with the fake upower first on the PATH.
+    // The interval is long, so that only the events can trigger renders.
+    fn start(&mut self) {
+        let path = format!(
+            "{}:{}",
+            self.dir.display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+        let template = Path::new(env!("CARGO_MANIFEST_DIR")).join("base-src.svg");
+        let watch = Command::new(env!("CARGO_BIN_EXE_battery-icon"))
+            .args(["--watch", "--source", "auto", "--interval", "60"])
+            .arg(template)
+            .arg(self.output())
+            .env("PATH", path)
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        self.watch = Some(watch);
+    }
+
+    // wait_for_width waits for the output to show the bar at the given width.
+    fn wait_for_width(&self, width: &str) {
+        let want = format!("width=\"{width}\"");
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            let svg = fs::read_to_string(self.output()).unwrap_or_default();
+            if fraction_tag(&svg).is_some_and(|tag| tag.contains(&want)) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        let svg = fs::read_to_string(self.output()).unwrap_or_default();
+        panic!("timed out waiting for {want}, got {:?}", fraction_tag(&svg));
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        if let Some(watch) = &mut self.watch {
+            let _ = watch.kill();
+            let _ = watch.wait();
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// fraction_tag finds the rect#fraction tag in the output.
+fn fraction_tag(svg: &str) -> Option<&str> {
+    svg.split('<')
+        .filter(|tag| tag.starts_with("rect"))
+        .find(|tag| tag.contains("id=\"fraction\""))
+}
+
+#[test]
+fn watch_follows_upower_events() {
+    // The bar is 38.25 wide when full.
+    let mut harness = Harness::new("upower-events", 100, &[40, 20, 80]);
+    harness.start();
+    harness.wait_for_width("38.25");
+    harness.wait_for_width("15.3");
+    harness.wait_for_width("7.65");
+    harness.wait_for_width("30.6");
+}
@@ -0,0 +1,207 @@
+// Renders tests/raster/template.svg in a few states, rasterizes the results,
+// and compares them to the blessed PNGs next to it with a perceptual diff.
+// Run with BATTERY_ICON_BLESS=1 to write the references after an intended change.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg;
+
+// The width to rasterize at, in pixels.
+const WIDTH: u32 = 128;
+// The YIQ color distance beyond which a pixel counts as different,
+// on pixelmatch's scale of 0 to 1.
+const PIXEL_THRESHOLD: f64 = 0.1;
+// The share of pixels that may differ, for anti-aliasing to vary.
+const MAX_DIFFERENT: f64 = 0.005;
+
+fn raster_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/raster")
+}
+
+// render runs battery-icon on the template, and rasterizes the result.
+fn render(name: &str, args: &[&str]) -> Pixmap {
+    let output = std::env::temp_dir().join(format!(
+        "battery-icon-raster-{name}-{}.svg",
+        std::process::id()
+    ));
+    let status = Command::new(env!("CARGO_BIN_EXE_battery-icon"))
+        .args(args)
+        .arg(raster_dir().join("template.svg"))
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(
+        status.success(),
+        "battery-icon {args:?} failed with {status}"
+    );
+    let svg = fs::read(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+
+    // Leave out the system fonts, so that the pixels don't depend on the machine.
+    let tree = usvg::Tree::from_data(&svg, &usvg::Options::default()).unwrap();
+    let scale = WIDTH as f32 / tree.size().width();
+    let height = (tree.size().height() * scale).ceil() as u32;
+    let mut pixmap = Pixmap::new(WIDTH, height).unwrap();
+    resvg::render(
+        &tree,
+        Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    pixmap
+}
+
+// yiq converts a pixel, blended onto white, to the YIQ color space,
+// which weighs differences closer to how the eye does.
+fn yiq(pixel: &[u8]) -> (f64, f64, f64) {
+    let alpha = pixel[3] as f64 / 255.0;
+    let blend = |c: u8| 255.0 + (c as f64 - 255.0) * alpha;
+    let (r, g, b) = (blend(pixel[0]), blend(pixel[1]), blend(pixel[2]));
+    (
+        r * 0.29889531 + g * 0.58662247 + b * 0.11448223,
+        r * 0.59597799 - g * 0.27417610 - b * 0.32180189,
+        r * 0.21147017 - g * 0.52261711 + b * 0.31114694,
+    )
+}
+
+// different_share returns the share of pixels that visibly differ.
+fn different_share(actual: &Pixmap, expected: &Pixmap) -> f64 {
+    // The largest possible distance is 35215, between black and white.
+    let max_delta = 35215.0 * PIXEL_THRESHOLD * PIXEL_THRESHOLD;
+    let a = actual.clone().take_demultiplied();
+    let e = expected.clone().take_demultiplied();
+    let different = a
+        .chunks(4)
+        .zip(e.chunks(4))
+        .filter(|(a, e)| {
+            let (ya, ia, qa) = yiq(a);
+            let (ye, ie, qe) = yiq(e);
+            let (y, i, q) = (ya - ye, ia - ie, qa - qe);
+            0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q > max_delta
+        })
+        .count();
+    different as f64 / (actual.width() * actual.height()) as f64
+}
+
+fn check(name: &str, args: &[&str]) {
+    let actual = render(name, args);
+    let reference = raster_dir().join(format!("{name}.png"));
+    if std::env::var_os("BATTERY_ICON_BLESS").is_some() {
+        actual.save_png(&reference).unwrap();
+        return;
+    }
+    let expected = Pixmap::load_png(&reference).unwrap_or_else(|e| {
+        panic!("failed to load {reference:?}, run with BATTERY_ICON_BLESS=1: {e}")
+    });
+    assert_eq!(
+        (actual.width(), actual.height()),
+        (expected.width(), expected.height()),
+        "{name}: size changed"
+    );
+    let share = different_share(&actual, &expected);
+    if share > MAX_DIFFERENT {
+        let saved = std::env::temp_dir().join(format!("battery-icon-raster-{name}.png"));
+        actual.save_png(&saved).unwrap();
+        panic!(
+            "{name}: {:.2}% of pixels differ from {reference:?}; the new rendering is at {saved:?}",
+            share * 100.0
+        );
+    }
+}
+
+#[test]
+fn empty() {
+    check("level0", &["--level", "0"]);
+}
+
+#[test]
+fn low() {
+    check("level10", &["--level", "0.1"]);
+}
+
+#[test]
+fn half_charging() {
+    check("level50-charging", &["--level", "0.5", "--charging"]);
+}
+
+#[test]
+fn full() {
+    check("level100", &["--level", "1"]);
+}
+
+#[test]
+fn png_output() {
+    let output =
+        std::env::temp_dir().join(format!("battery-icon-raster-{}.png", std::process::id()));
+    let status = Command::new(env!("CARGO_BIN_EXE_battery-icon"))
+        .args(["--level", "0.5", "--width", "128", "--pad", "8"])
+        .args(["--background", "ffffff", "--corner-radius", "8"])
+        .arg(raster_dir().join("template.svg"))
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success(), "battery-icon failed with {status}");
+    let pixmap = Pixmap::load_png(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+
+    assert_eq!(pixmap.width(), 128 + 2 * 8);
+    let reference = render("png-output", &["--level", "0.5"]);
+    assert_eq!(pixmap.height(), reference.height() + 2 * 8);
+    // The corner is cut off, and the padding is filled with the background.
+    let pixel = |x, y| pixmap.pixel(x, y).unwrap();
+    assert_eq!(pixel(0, 0).alpha(), 0);
+    let side = pixel(4, pixmap.height() / 2);
+    assert_eq!(
+        (side.red(), side.green(), side.blue(), side.alpha()),
+        (255, 255, 255, 255)
+    );
+}
+
+#[test]
+fn android_profile() {
+    let dir = std::env::temp_dir().join(format!("battery-icon-android-{}", std::process::id()));
+    let status = Command::new(env!("CARGO_BIN_EXE_battery-icon"))
+        .args(["--level", "0.5", "--width", "48", "--profile", "android"])
+        .arg(raster_dir().join("template.svg"))
+        .arg(&dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "battery-icon failed with {status}");
+
+    let drawable = fs::read_to_string(dir.join("drawable/template.xml")).unwrap();
+    assert!(drawable.contains(r#"android:width="48dp""#), "{drawable}");
+    assert!(drawable.contains("android:pathData="), "{drawable}");
+    for (density, width) in [("mdpi", 48), ("hdpi", 72), ("xxxhdpi", 192)] {
+        let png = dir.join(format!("mipmap-{density}/template.png"));
+        assert_eq!(Pixmap::load_png(&png).unwrap().width(), width, "{density}");
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn macos_template_profile() {
+    let dir = std::env::temp_dir().join(format!("battery-icon-macos-{}", std::process::id()));
+    let status = Command::new(env!("CARGO_BIN_EXE_battery-icon"))
+        .args(["--level", "0.5", "--profile", "macos-template"])
+        .args(["--foreground", "ff0000", "--background", "ffffff"])
+        .arg(raster_dir().join("template.svg"))
+        .arg(&dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "battery-icon failed with {status}");
+
+    let small = Pixmap::load_png(dir.join("iconTemplate.png")).unwrap();
+    let large = Pixmap::load_png(dir.join("iconTemplate@2x.png")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(small.height(), 16);
+    assert_eq!(large.width(), 2 * small.width());
+    // Only black and alpha are left, and the background is left out.
+    assert!(large.pixels().iter().any(|p| p.alpha() == 255));
+    assert!(large.pixels().iter().any(|p| p.alpha() == 0));
+    assert!(large
+        .pixels()
+        .iter()
+        .all(|p| (p.red(), p.green(), p.blue()) == (0, 0, 0)));
+}
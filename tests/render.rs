@@ -0,0 +1,639 @@
+// Renders small templates through the library,
+// checking what each of battery-icon's transforms does to them.
+
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use quick_xml::reader::Reader;
+use quick_xml::writer::Writer;
+
+use battery_icon::bar::Orientation;
+use battery_icon::ramp::{ColorSpace, Ramp};
+use battery_icon::source::{BatteryState, LevelColors, Reading};
+use battery_icon::{config, data, overlay, read_events, rule, Options, Render};
+
+// render_svg renders the template with the options, the reading and the rules,
+// and returns the SVG it comes out as.
+fn render_svg(
+    options: Options,
+    reading: Reading,
+    rules: &[rule::Rule],
+    template: &str,
+) -> Result<String, Box<dyn Error>> {
+    let data = data::Data::new();
+    let render = Render::new(&options, reading, &data, &[], rules)?;
+    let mut writer = Writer::new(Vec::new());
+    for event in render.transform(read_events(Reader::from_str(template))) {
+        writer.write_event(event?)?;
+    }
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+#[test]
+fn inline_forward_reference() -> Result<(), Box<dyn Error>> {
+    let rules: Vec<rule::Rule> = vec!["use inline=true".parse()?, "g#b rect fill=red".parse()?];
+    let template =
+        r##"<svg><use id="b" href="#seg" x="8"/><defs><rect id="seg" width="3"/></defs></svg>"##;
+    assert_eq!(
+        render_svg(Options::default(), Reading::from(0.5), &rules, template)?,
+        r##"<svg><g id="b" transform="translate(8,0)"><rect width="3" fill="red"/></g><defs><rect id="seg" width="3"/></defs></svg>"##
+    );
+    Ok(())
+}
+
+#[test]
+fn rule_with_colon() -> Result<(), Box<dyn Error>> {
+    let rules: Vec<rule::Rule> = vec![
+        "rect#bar: width *= level".parse()?,
+        r#"text#pct: text = "{{charge}}%""#.parse()?,
+    ];
+    let template = r#"<svg><rect id="bar" width="8"/><text id="pct">?</text></svg>"#;
+    assert_eq!(
+        render_svg(Options::default(), Reading::from(0.5), &rules, template)?,
+        r#"<svg><rect id="bar" width="4"/><text id="pct">50%</text></svg>"#
+    );
+    Ok(())
+}
+
+#[test]
+fn rounded_numbers() -> Result<(), Box<dyn Error>> {
+    let template = r#"<svg><rect id="fraction" width="38.25"/><rect data-bind-x="0.1 * 3"/><rect data-bind-y="0 - 0.01"/></svg>"#;
+    for (precision, want) in [
+        (
+            6,
+            r#"width="19.125"/><rect data-bind-x="0.1 * 3" x="0.3"/><rect data-bind-y="0 - 0.01" y="-0.01"/>"#,
+        ),
+        (
+            1,
+            r#"width="19.1"/><rect data-bind-x="0.1 * 3" x="0.3"/><rect data-bind-y="0 - 0.01" y="0"/>"#,
+        ),
+    ] {
+        let options = Options {
+            precision,
+            ..Default::default()
+        };
+        let svg = render_svg(options, Reading::from(0.5), &[], template)?;
+        assert!(svg.contains(want), "{precision}: {svg}");
+    }
+    Ok(())
+}
+
+#[test]
+fn vertical_bar() -> Result<(), Box<dyn Error>> {
+    for (orientation, template) in [
+        (
+            Orientation::Horizontal,
+            r#"<svg><rect id="fraction" data-orient="vertical" y="2" height="40"/></svg>"#,
+        ),
+        (
+            Orientation::Vertical,
+            r#"<svg><rect id="fraction" y="2" height="40"/></svg>"#,
+        ),
+    ] {
+        let options = Options {
+            orientation,
+            ..Default::default()
+        };
+        let svg = render_svg(options, Reading::from(0.25), &[], template)?;
+        assert!(svg.contains(r#"y="32" height="10""#), "{svg}");
+    }
+    Ok(())
+}
+
+#[test]
+fn anchored_bar() -> Result<(), Box<dyn Error>> {
+    for (anchor, want) in [
+        ("right", r#"x="32" width="10""#),
+        ("center", r#"x="17" width="10""#),
+        ("left", r#"x="2" width="10""#),
+    ] {
+        let template =
+            format!(r#"<svg><rect id="fraction" data-anchor="{anchor}" x="2" width="40"/></svg>"#);
+        let svg = render_svg(Options::default(), Reading::from(0.25), &[], &template)?;
+        assert!(svg.contains(want), "{anchor}: {svg}");
+    }
+    let template = r#"<svg><rect id="fraction" data-anchor="top" width="40"/></svg>"#;
+    assert!(render_svg(Options::default(), Reading::from(0.25), &[], template).is_err());
+    Ok(())
+}
+
+#[test]
+fn bar_bounds() -> Result<(), Box<dyn Error>> {
+    let mut svg = r#"<svg><rect id="fraction" data-anchor="right" data-full-x="2" data-full-width="40" data-empty-width="4" width="40"/></svg>"#.to_string();
+    // Rendering the output again changes nothing.
+    for _ in 0..2 {
+        svg = render_svg(Options::default(), Reading::from(0.5), &[], &svg)?;
+        assert!(svg.contains(r#"width="22" x="20""#), "{svg}");
+    }
+    let template = r#"<svg><rect id="fraction" data-empty-width="50" width="40"/></svg>"#;
+    assert!(render_svg(Options::default(), Reading::from(0.5), &[], template).is_err());
+    Ok(())
+}
+
+#[test]
+fn pct_text() -> Result<(), Box<dyn Error>> {
+    let template = r#"<svg><text id="pct">?</text><text><tspan id="pct">?</tspan></text></svg>"#;
+    for (format, want) in [
+        (
+            None,
+            r#"<text id="pct">?</text><text><tspan id="pct" style="fill:#000000">?</tspan>"#,
+        ),
+        (
+            Some("{{percent}}"),
+            r#"<text id="pct">42%</text><text><tspan id="pct" style="fill:#000000">42%</tspan>"#,
+        ),
+        (
+            Some("{{charge}} of 100 & {{state}}"),
+            r#"<text id="pct">42 of 100 &amp; unknown</text>"#,
+        ),
+    ] {
+        let options = Options {
+            pct_format: format.map(str::to_string),
+            ..Default::default()
+        };
+        let svg = render_svg(options, Reading::from(0.42), &[], template)?;
+        assert!(svg.contains(want), "{format:?}: {svg}");
+    }
+    Ok(())
+}
+
+#[test]
+fn time_remaining_text() -> Result<(), Box<dyn Error>> {
+    let reading = Reading {
+        time_remaining: Some(std::time::Duration::from_secs(134 * 60)),
+        ..Reading::from(0.5)
+    };
+    let template = r#"<svg><text id="time">--</text><text id="other"/></svg>"#;
+    assert_eq!(
+        render_svg(Options::default(), reading, &[], template)?,
+        r#"<svg><text id="time">2h 14m</text><text id="other"/></svg>"#
+    );
+    Ok(())
+}
+
+#[test]
+fn template_vars() -> Result<(), Box<dyn Error>> {
+    let options = Options {
+        css_vars: vec![
+            ("label".to_string(), "Mouse & keys".to_string()),
+            ("tint".to_string(), "#123456".to_string()),
+        ],
+        ..Default::default()
+    };
+    let template = r#"<svg><g><text fill="{{tint}}">{{label}}: {{charge}}%</text></g></svg>"#;
+    let svg = render_svg(options, Reading::from(0.5), &[], template)?;
+    assert!(
+        svg.ends_with(r##"<g><text fill="#123456">Mouse &amp; keys: 50%</text></g></svg>"##),
+        "{svg}"
+    );
+    Ok(())
+}
+
+#[test]
+fn locale_percent() -> Result<(), Box<dyn Error>> {
+    let reading = Reading {
+        power: Some(8.3),
+        ..Reading::from(0.42)
+    };
+    let template = r#"<svg><text id="pct">{{percent}}</text><text id="watts"/></svg>"#;
+    for (tag, want) in [
+        ("en", "42%</text><text id=\"watts\">8.3 W"),
+        ("de-DE", "42\u{a0}%</text><text id=\"watts\">8,3 W"),
+        ("tr", "%42</text><text id=\"watts\">8,3 W"),
+        ("ar-EG", "٤٢٪</text><text id=\"watts\">٨٫٣ W"),
+        ("ar-u-nu-latn", "42%</text><text id=\"watts\">8.3 W"),
+    ] {
+        let options = Options {
+            locale: tag.parse()?,
+            ..Default::default()
+        };
+        let svg = render_svg(options, reading, &[], template)?;
+        assert!(svg.contains(want), "{tag}: {svg}");
+    }
+    Ok(())
+}
+
+#[test]
+fn hot_battery() -> Result<(), Box<dyn Error>> {
+    let options = Options {
+        hot: true,
+        hot_color: Some("#ff0000".to_string()),
+        ..Default::default()
+    };
+    let template = r#"<svg><rect id="fraction" width="10"/><text id="temp">!</text></svg>"#;
+    assert_eq!(
+        render_svg(options, Reading::from(0.5), &[], template)?,
+        r#"<svg><rect id="fraction" width="5" style="fill:#ff0000"/><text id="temp">!</text></svg>"#
+    );
+    Ok(())
+}
+
+#[test]
+fn level_colors() -> Result<(), Box<dyn Error>> {
+    let options = Options {
+        colors: LevelColors {
+            normal: Some("green".to_string()),
+            low: "gold".to_string(),
+            charging: Some("#22aa44".to_string()),
+            ..LevelColors::default()
+        },
+        ..Default::default()
+    };
+    let template = r#"<svg><rect id="fraction" width="10" fill="black"/></svg>"#;
+    assert_eq!(
+        render_svg(options.clone(), Reading::from(0.5), &[], template)?,
+        r#"<svg><rect id="fraction" width="5" fill="green"/></svg>"#
+    );
+    assert_eq!(
+        render_svg(options.clone(), Reading::from(0.2), &[], template)?,
+        r#"<svg><rect id="fraction" width="2" fill="gold"/></svg>"#
+    );
+    assert_eq!(
+        render_svg(options.clone(), Reading::from(0.1), &[], template)?,
+        r##"<svg><rect id="fraction" width="1" fill="#ff0000"/></svg>"##
+    );
+    let charging = Options {
+        charging: true,
+        ..options
+    };
+    assert_eq!(
+        render_svg(charging, Reading::from(0.1), &[], template)?,
+        r##"<svg><rect id="fraction" width="1" fill="#22aa44"/></svg>"##
+    );
+    Ok(())
+}
+
+#[test]
+fn full_charge() -> Result<(), Box<dyn Error>> {
+    let template = r#"<svg><rect id="fraction" width="10" fill="black"/><path id="full"/></svg>"#;
+    let options = |full| Options {
+        full,
+        charging: true,
+        colors: LevelColors {
+            charging: Some("#22aa44".to_string()),
+            full: Some("#0080ff".to_string()),
+            ..LevelColors::default()
+        },
+        ..Default::default()
+    };
+    assert_eq!(
+        render_svg(options(true), Reading::from(1.0), &[], template)?,
+        r##"<svg><rect id="fraction" width="10" fill="#0080ff"/><path id="full"/></svg>"##
+    );
+    assert_eq!(
+        render_svg(options(false), Reading::from(1.0), &[], template)?,
+        r##"<svg><rect id="fraction" width="10" fill="#22aa44"/><path id="full" style="display:none"/></svg>"##
+    );
+    Ok(())
+}
+
+#[test]
+fn custom_selector() -> Result<(), Box<dyn Error>> {
+    let options = Options {
+        selector: "g#bar rect".to_string(),
+        ..Default::default()
+    };
+    let template =
+        r#"<svg><g id="bar"><rect width="10"/></g><rect id="fraction" width="10"/></svg>"#;
+    assert_eq!(
+        render_svg(options, Reading::from(0.5), &[], template)?,
+        r#"<svg><g id="bar"><rect width="5"/></g><rect id="fraction" width="10"/></svg>"#
+    );
+    Ok(())
+}
+
+#[test]
+fn color_ramp() -> Result<(), Box<dyn Error>> {
+    let mut ramp: Ramp = "red,lime".parse()?;
+    assert_eq!(ramp.at(0.5), "#808000");
+    assert_eq!(ramp.at(1.5), "#00ff00");
+    ramp.space = ColorSpace::Hsl;
+    assert_eq!(ramp.at(0.5), "#ffff00");
+
+    let ramp: Ramp = "red@15,orange@30,green".parse()?;
+    assert_eq!(ramp.at(0.1), "#ff0000");
+    assert_eq!(ramp.at(0.225), "#ff5300");
+    assert!("red".parse::<Ramp>().is_err());
+    assert!("green@50,red@10".parse::<Ramp>().is_err());
+    Ok(())
+}
+
+#[test]
+fn bolt_layer() -> Result<(), Box<dyn Error>> {
+    let template = r#"<svg><g id="bolt"><path d="M1 1"/></g></svg>"#;
+    let charging = Options {
+        charging: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        render_svg(charging, Reading::from(0.5), &[], template)?,
+        template
+    );
+    assert_eq!(
+        render_svg(Options::default(), Reading::from(0.5), &[], template)?,
+        r#"<svg><g id="bolt" style="display:none"><path d="M1 1"/></g></svg>"#
+    );
+    Ok(())
+}
+
+#[test]
+fn not_charging_icon() -> Result<(), Box<dyn Error>> {
+    let template = r#"<svg><text id="icon">+</text><path id="not-charging"/></svg>"#;
+    let reading = |state| Reading {
+        state,
+        ..Reading::from(0.8)
+    };
+    assert_eq!(
+        render_svg(
+            Options::default(),
+            reading(BatteryState::NotCharging),
+            &[],
+            template
+        )?,
+        r#"<svg><text id="icon" style="display:none">+</text><path id="not-charging"/></svg>"#
+    );
+    assert_eq!(
+        render_svg(
+            Options::default(),
+            reading(BatteryState::Full),
+            &[],
+            template
+        )?,
+        r#"<svg><text id="icon" style="display:none">+</text><path id="not-charging" style="display:none"/></svg>"#
+    );
+    Ok(())
+}
+
+#[test]
+fn repeated_segments() -> Result<(), Box<dyn Error>> {
+    let template = r#"<svg><rect id="seg" data-repeat="3" data-repeat-dx="4.5" data-repeat-dy="1" width="3"/></svg>"#;
+    assert_eq!(
+        render_svg(Options::default(), Reading::from(1.0), &[], template)?,
+        r#"<svg><rect id="seg1" width="3" transform="translate(0,0)" data-segment="1/3"/><rect id="seg2" width="3" transform="translate(4.5,1)" data-segment="2/3"/><rect id="seg3" width="3" transform="translate(9,2)" data-segment="3/3"/></svg>"#
+    );
+
+    // Each copy is a segment of the level, unless it was marked as one already,
+    // and its own transform goes after the offset.
+    let template = r#"<svg><g id="arc" data-repeat="2" data-repeat-dx="5" transform="scale(2)"><path id="a"/></g><g id="dot" data-repeat="2" data-segment="1/5"/></svg>"#;
+    assert_eq!(
+        render_svg(Options::default(), Reading::from(0.4), &[], template)?,
+        r#"<svg><g id="arc1" transform="translate(0,0) scale(2)" data-segment="1/2"><path id="a1"/></g><g id="arc2" transform="translate(5,0) scale(2)" data-segment="2/2" style="display:none"><path id="a2"/></g><g id="dot1" data-segment="1/5" transform="translate(0,0)"/><g id="dot2" data-segment="1/5" transform="translate(0,0)"/></svg>"#
+    );
+    Ok(())
+}
+
+#[test]
+fn nested_repeat() -> Result<(), Box<dyn Error>> {
+    let template = r#"<svg><g id="row" data-repeat="11" data-repeat-dy="10"><rect id="seg" data-repeat="11" data-repeat-dx="5"/></g></svg>"#;
+    let svg = render_svg(Options::default(), Reading::from(1.0), &[], template)?;
+    let ids: Vec<&str> = svg
+        .split(r#"id=""#)
+        .skip(1)
+        .map(|rest| &rest[..rest.find('"').unwrap()])
+        .collect();
+    assert_eq!(ids.len(), 11 + 11 * 11);
+    let unique: std::collections::HashSet<&str> = ids.iter().copied().collect();
+    assert_eq!(unique.len(), ids.len(), "{ids:?}");
+    // The inner copies are segments of their row, offset within it.
+    assert!(
+        svg.contains(r#"<g id="row2" transform="translate(0,10)" data-segment="2/11"><rect id="seg2-1" transform="translate(0,0)" data-segment="1/11"/><rect id="seg2-2" transform="translate(5,0)" data-segment="2/11"/>"#),
+        "{svg}"
+    );
+    assert!(svg.contains(r#"<rect id="seg1-11" "#), "{svg}");
+    assert!(svg.contains(r#"<rect id="seg11-1" "#), "{svg}");
+    Ok(())
+}
+
+#[test]
+fn unclosed_repeat() {
+    for template in [
+        r#"<svg><g data-repeat="2"><rect/>"#,
+        r#"<svg><g data-repeat="2"><g data-repeat="2"><rect/></g>"#,
+    ] {
+        let svg = render_svg(Options::default(), Reading::from(1.0), &[], template);
+        assert!(svg.is_err(), "{template}: {svg:?}");
+    }
+    let template = r#"<svg><rect data-repeat="two"/></svg>"#;
+    assert!(render_svg(Options::default(), Reading::from(1.0), &[], template).is_err());
+}
+
+#[test]
+fn show_if() -> Result<(), Box<dyn Error>> {
+    let template =
+        r#"<svg><path id="low" data-show-if="!charging &amp;&amp; charge &lt; 20"/></svg>"#;
+    assert_eq!(
+        render_svg(Options::default(), Reading::from(0.1), &[], template)?,
+        template
+    );
+    assert_eq!(
+        render_svg(Options::default(), Reading::from(0.5), &[], template)?,
+        r#"<svg><path id="low" data-show-if="!charging &amp;&amp; charge &lt; 20" style="display:none"/></svg>"#
+    );
+    for (condition, want) in [
+        ("charge &lt;", "unexpected end of expression"),
+        ("nope", "unknown placeholder {{nope}}"),
+    ] {
+        let template = format!(r#"<svg><path data-show-if="{condition}"/></svg>"#);
+        let e = render_svg(Options::default(), Reading::from(0.5), &[], &template)
+            .expect_err(condition)
+            .to_string();
+        assert!(e.contains(want), "{condition}: {e}");
+    }
+    Ok(())
+}
+
+// render_data renders the template with the --data file holding the JSON,
+// and the [[bind]] tables of the TOML config.
+fn render_data(json: &str, config: &str, template: &str) -> Result<String, Box<dyn Error>> {
+    let path = std::env::temp_dir().join(format!(
+        "battery-icon-render-data-{}.json",
+        std::process::id()
+    ));
+    std::fs::write(&path, json)?;
+    let data = data::load(&path);
+    std::fs::remove_file(&path)?;
+    let data = data?;
+    let config: config::Config = toml::from_str(config)?;
+    let options = Options::default();
+    let render = Render::new(&options, Reading::from(0.5), &data, &config.bind, [])?;
+    let mut writer = Writer::new(Vec::new());
+    for event in render.transform(read_events(Reader::from_str(template))) {
+        writer.write_event(event?)?;
+    }
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+#[test]
+fn data_bindings() -> Result<(), Box<dyn Error>> {
+    let json = r#"{"host": "tux", "cpu": {"temp": 61.5}, "online": true}"#;
+    let config = r#"
+        [[bind]]
+        selector = "text#host"
+        key = "host"
+
+        [[bind]]
+        selector = "rect#gauge"
+        key = "cpu.temp"
+        attr = "width"
+    "#;
+    let template = r#"<svg><text id="host">?</text><rect id="gauge" width="0"/><text id="up">{{online}} {{cpu.temp}}</text></svg>"#;
+    assert_eq!(
+        render_data(json, config, template)?,
+        r#"<svg><text id="host">tux</text><rect id="gauge" width="61.5"/><text id="up">true 61.5</text></svg>"#
+    );
+
+    let config = r#"
+        [[bind]]
+        selector = "text#host"
+        key = "hostname"
+    "#;
+    let e = render_data(json, config, template).expect_err("a missing field");
+    assert!(e.to_string().contains(r#"data has no "hostname""#), "{e}");
+    let e = render_data("[1, 2]", "", template).expect_err("not an object");
+    assert!(
+        e.to_string().contains("should contain a JSON object"),
+        "{e}"
+    );
+    Ok(())
+}
+
+#[test]
+fn inkscape_layer() -> Result<(), Box<dyn Error>> {
+    let options = Options {
+        selector:
+            r#"g[inkscape:groupmode="layer"][inkscape:label="Battery"] [inkscape:label=fill]"#
+                .to_string(),
+        ..Default::default()
+    };
+    let rules: Vec<rule::Rule> = vec![r#"g[inkscape:label="Bolt"] fill=gold"#.parse()?];
+    let template = r#"<svg xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape"><g inkscape:groupmode="layer" inkscape:label="Battery"><rect inkscape:label="fill" width="20"/><rect inkscape:label="outline" width="24"/></g><g inkscape:groupmode="layer" inkscape:label="Bolt"/></svg>"#;
+    let svg = render_svg(options, Reading::from(0.5), &rules, template)?;
+    assert!(
+        svg.contains(
+            r#"<rect inkscape:label="fill" width="10"/><rect inkscape:label="outline" width="24"/>"#
+        ),
+        "{svg}"
+    );
+    assert!(
+        svg.contains(r#"<g inkscape:groupmode="layer" inkscape:label="Bolt" fill="gold"/>"#),
+        "{svg}"
+    );
+    Ok(())
+}
+
+// render_overlays renders the template with the overlays, each a file's path with its SVG,
+// followed by any selector, like badge.svg:#bolt.
+fn render_overlays(overlays: &[(&str, &str)], template: &str) -> Result<String, Box<dyn Error>> {
+    // Tests run at once, so each call gets a directory of its own.
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "battery-icon-render-overlays-{}-{}",
+        std::process::id(),
+        CALLS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut specs = Vec::new();
+    for (spec, svg) in overlays {
+        let spec: overlay::Spec = dir.join(spec).to_str().ok_or("a temporary path")?.parse()?;
+        std::fs::create_dir_all(spec.path.parent().ok_or("an overlay directory")?)?;
+        std::fs::write(&spec.path, svg)?;
+        specs.push(spec);
+    }
+    let overlays = overlay::load_all(&specs);
+    std::fs::remove_dir_all(&dir)?;
+    let overlays = overlays?;
+
+    let options = Options::default();
+    let data = data::Data::new();
+    let render = Render::new(&options, Reading::from(0.5), &data, &[], [])?;
+    let events = render.transform(read_events(Reader::from_str(template)));
+    let mut writer = Writer::new(Vec::new());
+    for event in overlay::composite(events, &overlays) {
+        writer.write_event(event?)?;
+    }
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+#[test]
+fn overlays() -> Result<(), Box<dyn Error>> {
+    let badge = r##"<?xml version="1.0"?><svg><defs><radialGradient id="glow"/></defs><circle id="dot" fill="url(#glow)"/><use href="#dot"/><use xlink:href="#dot"/><use href="other.svg#dot"/></svg>"##;
+    let template = r#"<svg><rect id="fraction" width="10"/><g id="bolt"/></svg>"#;
+    assert_eq!(
+        render_overlays(&[("badge.svg", badge)], template)?,
+        r##"<svg><rect id="fraction" width="5"/><g id="bolt" style="display:none"/><svg><defs><radialGradient id="badge-glow"/></defs><circle id="badge-dot" fill="url(#badge-glow)"/><use href="#badge-dot"/><use xlink:href="#badge-dot"/><use href="other.svg#dot"/></svg></svg>"##
+    );
+
+    // Two overlays of the same name get prefixes of their own,
+    // and a selector places one before the element it matches.
+    let dot = r#"<svg><circle id="dot"/></svg>"#;
+    assert_eq!(
+        render_overlays(&[("a/dot.svg", dot), ("b/dot.svg:g#bolt", dot)], template)?,
+        r#"<svg><rect id="fraction" width="5"/><svg><circle id="dot2-dot"/></svg><g id="bolt" style="display:none"/><svg><circle id="dot-dot"/></svg></svg>"#
+    );
+    // The root can't have anything before it, so an overlay that matches it goes at the end.
+    assert_eq!(
+        render_overlays(&[("dot.svg:svg", dot)], r#"<svg><g/></svg>"#)?,
+        r#"<svg><g/><svg><circle id="dot-dot"/></svg></svg>"#
+    );
+    Ok(())
+}
+
+#[test]
+fn overlay_styles() -> Result<(), Box<dyn Error>> {
+    let badge = r##"<svg><style>#dot, g #ring:first-child { fill: #ff0000; stroke: url(#glow) } @media (min-width: 10px) { #dot { opacity: 0.5 } } text &gt; tspan { fill: #00f }</style><style><![CDATA[#ring{fill:#fff}]]></style><text>#1 &amp; url(#x)</text></svg>"##;
+    assert_eq!(
+        render_overlays(&[("badge.svg", badge)], r#"<svg><g/></svg>"#)?,
+        r##"<svg><g/><svg><style>#badge-dot, g #badge-ring:first-child { fill: #ff0000; stroke: url(#badge-glow) } @media (min-width: 10px) { #badge-dot { opacity: 0.5 } } text &gt; tspan { fill: #00f }</style><style><![CDATA[#badge-ring{fill:#fff}]]></style><text>#1 &amp; url(#badge-x)</text></svg></svg>"##
+    );
+    Ok(())
+}
+
+#[test]
+fn strip_editor() -> Result<(), Box<dyn Error>> {
+    let template = r#"<svg xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" xmlns:sodipodi="http://sodipodi.sourceforge.net/DTD/sodipodi-0.dtd" inkscape:version="1.3" width="48"><sodipodi:namedview id="view" pagecolor="white"><inkscape:page id="page"/></sodipodi:namedview><g inkscape:label="Battery" inkscape:groupmode="layer" style="fill:red;-inkscape-font-specification:Sans"><rect id="fraction" sodipodi:nodetypes="cccc" width="10"/></g><inkscape:clipboard/></svg>"#;
+    let options = Options {
+        strip_editor: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        render_svg(options, Reading::from(0.5), &[], template)?,
+        r#"<svg width="48"><g style="fill:red"><rect id="fraction" width="5"/></g></svg>"#
+    );
+    // Without --strip-editor, the editor markup is left alone.
+    let svg = render_svg(Options::default(), Reading::from(0.5), &[], template)?;
+    assert!(svg.contains(r#"<sodipodi:namedview id="view" pagecolor="white"><inkscape:page id="page"/></sodipodi:namedview>"#), "{svg}");
+    Ok(())
+}
+
+#[test]
+fn normalize_href() -> Result<(), Box<dyn Error>> {
+    let template = r##"<svg xmlns:xlink="http://www.w3.org/1999/xlink"><use xlink:href="#a"/><use href="#b" xlink:href="#old"/><image href="icon.png"/></svg>"##;
+    assert_eq!(
+        render_svg(Options::default(), Reading::from(0.5), &[], template)?,
+        r##"<svg xmlns:xlink="http://www.w3.org/1999/xlink"><use href="#a"/><use href="#b"/><image href="icon.png"/></svg>"##
+    );
+    // For SVG 1.1, the references go the other way, and the root declares xlink.
+    let options = Options {
+        xlink: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        render_svg(
+            options,
+            Reading::from(0.5),
+            &[],
+            r##"<svg><use href="#a"/></svg>"##
+        )?,
+        r##"<svg xmlns:xlink="http://www.w3.org/1999/xlink"><use xlink:href="#a"/></svg>"##
+    );
+    Ok(())
+}
+
+#[test]
+fn unknown_placeholders() -> Result<(), Box<dyn Error>> {
+    let template = r#"<svg><style>text::after { content: "}} {{ nope }}" }</style><text data-x="{{nope}} {{charge}}">{{charge}}% {{</text></svg>"#;
+    assert_eq!(
+        render_svg(Options::default(), Reading::from(0.5), &[], template)?,
+        r#"<svg><style>text::after { content: "}} {{ nope }}" }</style><text data-x="{{nope}} 50">50% {{</text></svg>"#
+    );
+    Ok(())
+}